@@ -10,7 +10,7 @@ use bevy::{
     },
     utils::Instant, asset::AssetPath,
 };
-use common::{vec4, Mat4, Ray3, UVec2, shader::base_uniform::RayTracingViewInfo};
+use common::{geometry::load::LoadedCamera, Mat4, Ray3, UVec2, shader::base_uniform::RayTracingViewInfo};
 
 fn create_single_debug_cube(
     commands: &mut Commands,
@@ -187,6 +187,7 @@ pub fn center_ray((camera, transform): (&Camera, &GlobalTransform)) -> Ray3 {
     let ray = Ray3 {
         pos: near,
         dir: dir.normalize(),
+        time: 0.0,
     };
     ray
 }
@@ -198,23 +199,14 @@ pub struct CameraProp {
 }
 impl CameraProp {
     pub fn get_ray_tracing_uniform(&self, size: UVec2, time: f32, frame_index: u32) -> RayTracingViewInfo {
-        let transform = self.transform.compute_matrix();
-        let inverse_projection = self.view_projection.inverse();
-        let mut top_left = inverse_projection * vec4(-1.0, 1.0, -1.0, 1.0);
-        let bottom_right = inverse_projection * vec4(1.0, -1.0, -1.0, 1.0);
-        let mut camera_h = (bottom_right - top_left) / (size.x as f32);
-        camera_h.y = 0.0;
-        let mut camera_v = (bottom_right - top_left) / (size.y as f32);
-        camera_v.x = 0.0;
-        top_left.w = 0.0;
-        RayTracingViewInfo {
-            camera_pos: self.transform.translation,
-            camera_look: (transform * top_left).truncate(),
-            camera_h: (transform * camera_h).truncate(),
-            camera_v: (transform * camera_v).truncate(),
-            time,
-            frame_index,
-            not_used: UVec2::ZERO,
+        RayTracingViewInfo::from_camera(self.transform.compute_matrix(), self.view_projection, size, time, frame_index)
+    }
+
+    /// a camera from a loaded scene, `size` is only used when the camera has no aspect ratio
+    pub fn from_loaded(camera: &LoadedCamera, size: UVec2) -> CameraProp {
+        CameraProp {
+            transform: GlobalTransform::from(Transform::from_matrix(camera.transform)),
+            view_projection: camera.projection(size.x as f32 / size.y as f32),
         }
     }
 }
@@ -48,7 +48,7 @@ fn create_render_uniform_bg(
     frame_index: u32,
 ) -> BindGroup {
     let uniform =
-        camera_prop.get_ray_tracing_uniform(size, layout.start_time.elapsed().as_secs_f32(), 0);
+        camera_prop.get_ray_tracing_uniform(size, layout.start_time.elapsed().as_secs_f32(), frame_index);
     let compute_uniform_buffer = device.create_buffer_with_data(&BufferInitDescriptor {
         label: None,
         usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
@@ -159,6 +159,7 @@ pub fn create_pipeline_layout(
 #[derive(Default)]
 pub struct Pipeline {
     size: UVec2,
+    frame_index: u32,
     camera_prop: CameraProp,
     texture_bg: Option<BindGroup>,
     uniform_bg: Option<BindGroup>,
@@ -182,7 +183,8 @@ pub fn queue_pipeline(
     };
     pipeline.camera_prop = camera_prop;
     let compute_uniform_bg: BindGroup =
-        create_render_uniform_bg(&device, &pipeline_layout, camera_prop, size, 0);
+        create_render_uniform_bg(&device, &pipeline_layout, camera_prop, size, pipeline.frame_index);
+    pipeline.frame_index = pipeline.frame_index.wrapping_add(1);
     pipeline.uniform_bg = Some(compute_uniform_bg);
 
     pipeline.scene_bg = Some(scene_bg);
@@ -2,4 +2,5 @@ pub mod engine_util;
 pub use engine_util::*;
 pub mod free_camera;
 pub use free_camera::*;
-pub mod full_screen_render;
\ No newline at end of file
+pub mod full_screen_render;
+pub mod voxel_mesh;
\ No newline at end of file
@@ -0,0 +1,22 @@
+use bevy::render::{
+    mesh::{Indices, Mesh},
+    render_resource::PrimitiveTopology,
+};
+use common::math::zzz_deprecated_svt::{SvtData, VoxelMeshGroup};
+
+/// per vertex ambient occlusion of the greedy mesher, not used by `StandardMaterial`, for custom shaders
+pub const ATTRIBUTE_AO: &str = "Vertex_Ao";
+
+impl<T: SvtData> From<&VoxelMeshGroup<T>> for Mesh {
+    fn from(group: &VoxelMeshGroup<T>) -> Self {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, group.positions.clone());
+        mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, group.normals.clone());
+        mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, group.uvs.clone());
+        if !group.ao.is_empty() {
+            mesh.set_attribute(ATTRIBUTE_AO, group.ao.clone());
+        }
+        mesh.set_indices(Some(Indices::U32(group.indices.clone())));
+        mesh
+    }
+}
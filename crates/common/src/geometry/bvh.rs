@@ -1,6 +1,6 @@
-use std::cmp::Ordering;
+use crate::math::*;
 
-use super::math::*;
+use super::bvh_node::*;
 
 pub trait BaseHitResult {
   fn t(self: &Self) -> f32;
@@ -10,70 +10,461 @@ pub trait Hittable: Clone {
   type T: BaseHitResult;
   fn aabb(self: &Self) -> Aabb3;
   fn hit(&self, ray: &Ray3, t_min: f32, t_max: f32) -> Option<Self::T>;
+  /// if there is any hit in the range, for shadow rays it can stop at the first one it finds
+  fn occluded(&self, ray: &Ray3, t_min: f32, t_max: f32) -> bool {
+    self.hit(ray, t_min, t_max).is_some()
+  }
 }
 
-#[derive(Debug)]
+const BIN_COUNT: usize = 12;
+const MAX_LEAF_SIZE: usize = 4;
+// relative to intersecting one item
+const TRAVERSAL_COST: f32 = 1.0;
+
+/**
+ * a bvh built with the surface area heuristic, flattened into `nodes` which can be traversed
+ * without recursion by `bvh_closest_hit`, see `BvhNode`
+ */
+#[derive(Clone, Debug)]
 pub struct Bvh3<T : Hittable> {
+  pub nodes: Vec<BvhNode>,
+  /// in the order the leaves reference them
+  pub items: Vec<T>,
+  /// `items[i]` was `indices[i]` in the `Vec` given to `new`
+  pub indices: Vec<u32>,
+}
+
+#[derive(Copy, Clone)]
+struct BuildItem {
   aabb: Aabb3,
-  body: Bvh3Inner<T>
+  center: Vec3,
+  index: u32,
+}
+
+#[derive(Copy, Clone)]
+struct Bin {
+  aabb: Aabb3,
+  count: usize,
+}
+
+fn bin_of(center: f32, min: f32, extent: f32) -> usize {
+  (((center - min) / extent * BIN_COUNT as f32) as usize).min(BIN_COUNT - 1)
+}
+
+fn build_rec(nodes: &mut Vec<BvhNode>, node: usize, items: &mut [BuildItem], first: usize, depth: usize) {
+  let mut aabb = Aabb3::empty();
+  let mut centers = Aabb3::empty();
+  for item in items.iter() {
+    aabb = aabb.union(&item.aabb);
+    centers = centers.union(&Aabb3::new(item.center, item.center));
+  }
+  nodes[node] = BvhNode { min: aabb.min, first: first as u32, max: aabb.max, count: items.len() as u32 };
+  let count = items.len();
+  // deeper than this, the traversal stack overflows
+  if count <= 1 || depth + 1 >= BVH_STACK_SIZE {
+    return;
+  }
+
+  // binned sah, (cost, axis, bin), split is before the bin
+  let parent_area = aabb.surface_area().max(f32::MIN_POSITIVE);
+  let extent = centers.max - centers.min;
+  let mut best: Option<(f32, usize, usize)> = None;
+  for axis in 0..3 {
+    if extent[axis] <= 0.0 {
+      continue;
+    }
+    let mut bins = [Bin { aabb: Aabb3::empty(), count: 0 }; BIN_COUNT];
+    for item in items.iter() {
+      let bin = &mut bins[bin_of(item.center[axis], centers.min[axis], extent[axis])];
+      bin.aabb = bin.aabb.union(&item.aabb);
+      bin.count += 1;
+    }
+    // sweep from the right, then from the left
+    let mut right_cost = [0.0; BIN_COUNT];
+    let mut right = Bin { aabb: Aabb3::empty(), count: 0 };
+    for b in (1..BIN_COUNT).rev() {
+      right.aabb = right.aabb.union(&bins[b].aabb);
+      right.count += bins[b].count;
+      right_cost[b] = right.aabb.surface_area() * right.count as f32;
+    }
+    let mut left = Bin { aabb: Aabb3::empty(), count: 0 };
+    for b in 1..BIN_COUNT {
+      left.aabb = left.aabb.union(&bins[b - 1].aabb);
+      left.count += bins[b - 1].count;
+      if left.count == 0 || left.count == count {
+        continue;
+      }
+      let cost = TRAVERSAL_COST + (left.aabb.surface_area() * left.count as f32 + right_cost[b]) / parent_area;
+      if best.map_or(true, |best| cost < best.0) {
+        best = Some((cost, axis, b));
+      }
+    }
+  }
+
+  let mid = match best {
+    Some((cost, axis, bin)) if cost < count as f32 || count > MAX_LEAF_SIZE => {
+      // partition in place
+      let mut mid = 0;
+      for i in 0..count {
+        if bin_of(items[i].center[axis], centers.min[axis], extent[axis]) < bin {
+          items.swap(i, mid);
+          mid += 1;
+        }
+      }
+      mid
+    }
+    // all the centers are the same, nothing the sah can do
+    None if count > MAX_LEAF_SIZE => count / 2,
+    _ => return,
+  };
+
+  let left = nodes.len();
+  nodes.push(BvhNode::default());
+  nodes.push(BvhNode::default());
+  nodes[node].first = left as u32;
+  nodes[node].count = 0;
+  let (left_items, right_items) = items.split_at_mut(mid);
+  build_rec(nodes, left, left_items, first, depth + 1);
+  build_rec(nodes, left + 1, right_items, first + mid, depth + 1);
+}
+
+// the items under a node are together, from its leftmost leaf to its rightmost one
+fn item_range(nodes: &[BvhNode], node: usize) -> (usize, usize) {
+  let (mut left, mut right) = (nodes[node], nodes[node]);
+  while !left.is_leaf() {
+    left = nodes[left.first as usize];
+  }
+  while !right.is_leaf() {
+    right = nodes[right.first as usize + 1];
+  }
+  (left.first as usize, (right.first + right.count) as usize)
 }
 
-#[derive(Debug)]
-pub enum Bvh3Inner<T : Hittable> {
-  Empty,
-  Leaf {
-    item: T // TODO is it a good idea to have an indirection here? so memory is more compact?
-  },
-  Node {
-    left: Box<Bvh3<T>>,
-    right: Box<Bvh3<T>>
+// copies the subtree of `old[n]` into `bvh.nodes[node]`, building again the ones `rebuild` says
+fn rebuild_rec<T : Hittable>(old: &[BvhNode], n: usize, bvh: &mut Bvh3<T>, node: usize, depth: usize, rebuild: &impl Fn(usize) -> bool) {
+  let old_node = old[n];
+  if rebuild(n) {
+    let (first, end) = item_range(old, n);
+    let mut build: Vec<BuildItem> = (first..end)
+      .map(|i| {
+        let aabb = bvh.items[i].aabb();
+        BuildItem { aabb, center: aabb.center(), index: i as u32 }
+      })
+      .collect();
+    build_rec(&mut bvh.nodes, node, &mut build, first, depth);
+    let moved: Vec<(T, u32)> = build.iter().map(|b| (bvh.items[b.index as usize].clone(), bvh.indices[b.index as usize])).collect();
+    for (i, (item, index)) in moved.into_iter().enumerate() {
+      bvh.items[first + i] = item;
+      bvh.indices[first + i] = index;
+    }
+  } else if old_node.is_leaf() {
+    bvh.nodes[node] = old_node;
+  } else {
+    let left = bvh.nodes.len();
+    bvh.nodes.push(BvhNode::default());
+    bvh.nodes.push(BvhNode::default());
+    bvh.nodes[node] = BvhNode { first: left as u32, ..old_node };
+    rebuild_rec(old, old_node.first as usize, bvh, left, depth + 1, rebuild);
+    rebuild_rec(old, old_node.first as usize + 1, bvh, left + 1, depth + 1, rebuild);
   }
 }
 
 impl<T : Hittable> Bvh3<T> {
+  pub fn new(items: Vec<T>) -> Bvh3<T> {
+    let mut build: Vec<BuildItem> = items
+      .iter()
+      .enumerate()
+      .map(|(index, item)| {
+        let aabb = item.aabb();
+        BuildItem { aabb, center: aabb.center(), index: index as u32 }
+      })
+      .collect();
+    let mut nodes = Vec::with_capacity(build.len() * 2);
+    if !build.is_empty() {
+      nodes.push(BvhNode::default());
+      build_rec(&mut nodes, 0, &mut build, 0, 0);
+    }
+    let indices: Vec<u32> = build.iter().map(|b| b.index).collect();
+    let mut items: Vec<Option<T>> = items.into_iter().map(Some).collect();
+    let items = indices.iter().map(|i| items[*i as usize].take().unwrap()).collect();
+    Bvh3 { nodes, items, indices }
+  }
+
+  pub fn aabb(&self) -> Aabb3 {
+    self.nodes.first().map_or(Aabb3::empty(), |n| n.aabb())
+  }
+
+  /**
+   * update the bounds after the items moved, the tree is kept. children are always after their
+   * parent, so one pass backwards is enough
+   */
+  pub fn refit(&mut self) {
+    for n in (0..self.nodes.len()).rev() {
+      let node = self.nodes[n];
+      let aabb = if node.is_leaf() {
+        let items = &self.items[node.first as usize..(node.first + node.count) as usize];
+        items.iter().fold(Aabb3::empty(), |aabb, item| aabb.union(&item.aabb()))
+      } else {
+        self.nodes[node.first as usize].aabb().union(&self.nodes[node.first as usize + 1].aabb())
+      };
+      self.nodes[n].min = aabb.min;
+      self.nodes[n].max = aabb.max;
+    }
+  }
+
+  /// the cost the surface area heuristic gives to the tree, it grows when a refitted tree gets worse
+  pub fn sah_cost(&self) -> f32 {
+    let root_area = self.aabb().surface_area().max(f32::MIN_POSITIVE);
+    self.subtree_costs().first().copied().unwrap_or(0.0) / root_area
+  }
+
+  /// the surface area heuristic cost of the subtree of every node, not divided by the area so it
+  /// grows when the subtree gets worse even if the nodes above grow too
+  pub fn subtree_costs(&self) -> Vec<f32> {
+    let mut costs = vec![0.0; self.nodes.len()];
+    for n in (0..self.nodes.len()).rev() {
+      let node = self.nodes[n];
+      let area = node.aabb().surface_area();
+      costs[n] = if node.is_leaf() {
+        area * node.count as f32
+      } else {
+        area * TRAVERSAL_COST + costs[node.first as usize] + costs[node.first as usize + 1]
+      };
+    }
+    costs
+  }
+
+  /**
+   * build again the subtrees of the nodes `rebuild` says yes to, going down from the root, the
+   * rest of the tree is kept. the nodes are written again, so the node indices change
+   */
+  pub fn rebuild_subtrees(&mut self, rebuild: impl Fn(usize) -> bool) {
+    if self.nodes.is_empty() {
+      return;
+    }
+    let old = std::mem::take(&mut self.nodes);
+    self.nodes = Vec::with_capacity(old.len());
+    self.nodes.push(BvhNode::default());
+    rebuild_rec(&old, 0, self, 0, 0, &rebuild);
+  }
+
+  /// the nodes for a storage buffer, items are referenced by their index in `items`
+  pub fn nodes_as_bytes(&self) -> &[u8] {
+    bytemuck::cast_slice(&self.nodes)
+  }
+
+  // the closest hit and the position of its item in `items`
+  fn hit_position(&self, ray: &Ray3, t_min: f32, t_max: f32) -> Option<(T::T, usize)> {
+    let mut closest = None;
+    bvh_closest_hit(&self.nodes, ray, t_min, t_max, |i, t_max| {
+      match self.items[i as usize].hit(ray, t_min, t_max) {
+        Some(hit) => {
+          let t = hit.t();
+          closest = Some((hit, i as usize));
+          t
+        }
+        None => t_max,
+      }
+    });
+    closest
+  }
+
   pub fn hit<'a>(self: &'a Self, ray: &Ray3, t_min: f32, t_max: f32) -> Option<(T::T, &'a T)> {
-    if self.aabb.hit_fast(ray, t_min, t_max) {
-      match &self.body {
-        Bvh3Inner::Node { left, right } => {
-          match left.hit(ray, t_min, t_max) {
-            Option::Some(res) => right.hit(ray, t_min, res.0.t()).or_else(|| { Some(res) }),
-            Option::None => right.hit(ray, t_min, t_max)
-          }
+    self.hit_position(ray, t_min, t_max).map(|(hit, i)| (hit, &self.items[i]))
+  }
+
+  /// like `hit`, with the index of the item in the `Vec` given to `new`
+  pub fn hit_index(&self, ray: &Ray3, t_min: f32, t_max: f32) -> Option<(T::T, u32)> {
+    self.hit_position(ray, t_min, t_max).map(|(hit, i)| (hit, self.indices[i]))
+  }
+}
+
+impl<T : Hittable> Hittable for Bvh3<T> {
+  type T = T::T;
+
+  fn aabb(self: &Self) -> Aabb3 {
+    Bvh3::aabb(self)
+  }
+
+  fn hit(&self, ray: &Ray3, t_min: f32, t_max: f32) -> Option<T::T> {
+    Bvh3::hit(self, ray, t_min, t_max).map(|(hit, _)| hit)
+  }
+
+  fn occluded(&self, ray: &Ray3, t_min: f32, t_max: f32) -> bool {
+    bvh_any_hit(&self.nodes, ray, t_min, t_max, |i| self.items[i as usize].occluded(ray, t_min, t_max))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::geometry::shape::*;
+  use std::sync::Arc;
+  use rand::{Rng, SeedableRng};
+
+  fn random_shapes(rng: &mut impl Rng, count: usize) -> Vec<Shape> {
+    (0..count)
+      .map(|_| {
+        let center = vec3(rng.gen(), rng.gen(), rng.gen()) * 100.0 - 50.0;
+        let size = rng.gen::<f32>() * 3.0 + 0.1;
+        match rng.gen_range(0..4) {
+          0 => Shape::Sphere(Sphere { center, radius: size }),
+          1 => Shape::Aabb3(Aabb3::new(center - size, center + size)),
+          2 => Shape::Instance(Instance::new(
+            Arc::new(Shape::Aabb3(Aabb3::new(Vec3::splat(-size), Vec3::splat(size)))),
+            Affine3A::from_rotation_translation(Quat::from_rotation_y(rng.gen()), center),
+          )),
+          _ => Shape::Translate {
+            shape: Box::new(Shape::Sphere(Sphere { center: Vec3::ZERO, radius: size })),
+            v: center,
+          },
         }
-        Bvh3Inner::Leaf { item } => item.hit(ray, t_min, t_max).map(|a | { (a, item) }),
-        Bvh3Inner::Empty => panic!(),
-    }
-    } else {
-      None
-    }
-  }
-
-  fn new_rec(items: &mut [T], axis: usize) -> Bvh3<T> {
-    let span = items.len();
-    if span == 0 {
-      Bvh3 { aabb: Aabb3::empty(), body: Bvh3Inner::Empty }
-    } else if span == 1 {
-      let item = items[0].clone(); // the only clone we use!
-      Bvh3 { aabb: item.aabb(), body: Bvh3Inner::Leaf { item: item } }
-    } else {
-      items.sort_by(|a, b| {
-        let diff = a.aabb().min[axis] - b.aabb().min[axis];
-        if diff == 0.0 {
-          Ordering::Equal
-        }  else if diff < 0.0 {
-          Ordering::Less
-        } else {
-          Ordering::Greater
+      })
+      .collect()
+  }
+
+  fn brute_force(shapes: &[Shape], ray: &Ray3, t_min: f32, t_max: f32) -> Option<(HitRecord, usize)> {
+    let mut closest = None;
+    let mut t_max = t_max;
+    for (i, shape) in shapes.iter().enumerate() {
+      if let Some(hit) = shape.hit(ray, t_min, t_max) {
+        t_max = hit.t;
+        closest = Some((hit, i));
+      }
+    }
+    closest
+  }
+
+  fn assert_valid(bvh: &Bvh3<Shape>, count: usize) {
+    let mut seen = vec![false; count];
+    for i in &bvh.indices {
+      assert!(!seen[*i as usize]);
+      seen[*i as usize] = true;
+    }
+    assert!(seen.iter().all(|s| *s));
+    let mut referenced = 0;
+    let mut stack = if count == 0 { vec![] } else { vec![(0usize, 0usize)] };
+    while let Some((n, depth)) = stack.pop() {
+      let node = bvh.nodes[n];
+      assert!(depth < BVH_STACK_SIZE);
+      if node.is_leaf() {
+        for i in node.first..node.first + node.count {
+          let aabb = bvh.items[i as usize].aabb();
+          assert!(node.min.cmple(aabb.min).all() && aabb.max.cmple(node.max).all());
+          referenced += 1;
+        }
+      } else {
+        for c in [node.first, node.first + 1] {
+          let child = bvh.nodes[c as usize];
+          assert!(node.min.cmple(child.min).all() && child.max.cmple(node.max).all());
+          stack.push((c as usize, depth + 1));
         }
-       });
-      let mid = items.len() / 2;
-      let axis = (axis + 1) % 3;
-      let left = Self::new_rec(&mut items[0.. mid], axis);
-      let right = Self::new_rec(&mut items[mid.. span], axis);
-      Bvh3 { aabb: Aabb3::union(&left.aabb, &right.aabb), body: Bvh3Inner::Node { left: Box::new(left), right: Box::new(right) } }
+      }
+    }
+    assert_eq!(referenced, count);
+    assert_eq!(bvh.nodes_as_bytes().len(), bvh.nodes.len() * 32);
+  }
+
+  #[test]
+  fn bvh_is_valid() {
+    let mut rng = rand_pcg::Pcg32::seed_from_u64(0);
+    for count in [0, 1, 2, 5, 100, 1000] {
+      let shapes = random_shapes(&mut rng, count);
+      assert_valid(&Bvh3::new(shapes), count);
+    }
+  }
+
+  #[test]
+  fn bvh_rebuild_subtrees() {
+    let mut rng = rand_pcg::Pcg32::seed_from_u64(3);
+    let count = 300;
+    let shapes = random_shapes(&mut rng, count);
+    let mut bvh = Bvh3::new(shapes.clone());
+    // move the items of a subtree apart, and refit
+    let (first, end) = item_range(&bvh.nodes, bvh.nodes[0].first as usize);
+    for i in first..end {
+      let v = vec3(rng.gen(), rng.gen(), rng.gen()) * 200.0 - 100.0;
+      bvh.items[i] = Shape::Translate { shape: Box::new(bvh.items[i].clone()), v };
+    }
+    let mut shapes = shapes;
+    for (item, index) in bvh.items.iter().zip(&bvh.indices) {
+      shapes[*index as usize] = item.clone();
+    }
+    bvh.refit();
+    assert_valid(&bvh, count);
+    let cost = bvh.sah_cost();
+    // only the subtree that got worse is built again
+    let left = bvh.nodes[0].first as usize;
+    let right = bvh.nodes[left + 1];
+    bvh.rebuild_subtrees(|n| n == left);
+    assert_valid(&bvh, count);
+    assert!(bvh.sah_cost() < cost);
+    let kept = bvh.nodes[bvh.nodes[0].first as usize + 1];
+    assert_eq!((kept.min, kept.max, kept.count), (right.min, right.max, right.count));
+    for _ in 0..3000 {
+      let pos = vec3(rng.gen(), rng.gen(), rng.gen()) * 160.0 - 80.0;
+      let dir = vec3(rng.gen(), rng.gen(), rng.gen()) * 2.0 - 1.0;
+      let ray = Ray3 { pos, dir: dir.try_normalize_or(Vec3::X), time: 0.0 };
+      let expected = brute_force(&shapes, &ray, 0.001, f32::INFINITY).map(|(h, i)| (h.t, i as u32));
+      assert_eq!(bvh.hit_index(&ray, 0.001, f32::INFINITY).map(|(h, i)| (h.t, i)), expected);
+    }
+  }
+
+  #[test]
+  fn bvh_occluded_same_as_hit() {
+    let mut rng = rand_pcg::Pcg32::seed_from_u64(2);
+    for count in [0, 1, 10, 300] {
+      let shapes = random_shapes(&mut rng, count);
+      let bvh = Bvh3::new(shapes.clone());
+      let mut occluded = 0;
+      for _ in 0..3000 {
+        let pos = vec3(rng.gen(), rng.gen(), rng.gen()) * 160.0 - 80.0;
+        let dir = vec3(rng.gen(), rng.gen(), rng.gen()) * 2.0 - 1.0;
+        let ray = Ray3 { pos, dir: dir.try_normalize_or(Vec3::X), time: 0.0 };
+        let t_max = if rng.gen() { f32::INFINITY } else { rng.gen::<f32>() * 100.0 };
+        let expected = brute_force(&shapes, &ray, 0.001, t_max).is_some();
+        assert_eq!(bvh.occluded(&ray, 0.001, t_max), expected);
+        assert_eq!(Hittable::hit(&bvh, &ray, 0.001, t_max).is_some(), expected);
+        occluded += expected as usize;
+      }
+      assert!(count == 0 || occluded > 0);
     }
   }
-  pub fn new(mut items: Vec<T>) -> Bvh3<T> {
-    Self::new_rec(&mut items, 0)
+
+  #[test]
+  fn bvh_hit_same_as_brute_force() {
+    let mut rng = rand_pcg::Pcg32::seed_from_u64(1);
+    for count in [1, 10, 300] {
+      let shapes = random_shapes(&mut rng, count);
+      let bvh = Bvh3::new(shapes.clone());
+      let mut hits = 0;
+      for _ in 0..3000 {
+        let pos = vec3(rng.gen(), rng.gen(), rng.gen()) * 160.0 - 80.0;
+        let dir = if rng.gen() {
+          vec3(rng.gen(), rng.gen(), rng.gen()) * 2.0 - 1.0
+        } else {
+          // aim at something
+          shapes[rng.gen_range(0..count)].aabb().center() - pos
+        };
+        let ray = Ray3 { pos, dir: dir.try_normalize_or(Vec3::X), time: 0.0 };
+        let t_max = if rng.gen() { f32::INFINITY } else { rng.gen::<f32>() * 100.0 };
+        let expected = brute_force(&shapes, &ray, 0.001, t_max);
+        let got = bvh.hit(&ray, 0.001, t_max);
+        match (expected, got) {
+          (None, None) => {}
+          (Some((e, i)), Some((g, item))) => {
+            assert_eq!(e.t, g.t);
+            assert_eq!(e.nor, g.nor);
+            // same `t` on two shapes would be fine too, but it doesn't happen with random ones
+            let index = bvh.items.iter().position(|s| core::ptr::eq(s, item)).unwrap();
+            assert_eq!(bvh.indices[index] as usize, i);
+            assert_eq!(bvh.hit_index(&ray, 0.001, t_max).unwrap().1 as usize, i);
+            hits += 1;
+          }
+          (e, g) => panic!("expected {:?} got {:?}", e.map(|e| e.0), g.map(|g| g.0)),
+        }
+      }
+      assert!(hits > 0);
+    }
   }
 }
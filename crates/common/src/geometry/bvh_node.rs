@@ -0,0 +1,235 @@
+use crate::math::*;
+
+// `gamma(3)` in pbrt, bounds the rounding error of the slab test
+const GAMMA_3: f32 = 3.0 * f32::EPSILON * 0.5 / (1.0 - 3.0 * f32::EPSILON * 0.5);
+
+// the traversal stack, the builder stops splitting before the tree gets deeper than this
+pub const BVH_STACK_SIZE: usize = 32;
+
+/**
+ * a node of a flattened bvh, 32 bytes so a `&[BvhNode]` can be uploaded as a storage buffer as is.
+ *
+ * for a leaf, `count > 0` and the items are `first..first + count`. otherwise the children are
+ * at `first` and `first + 1`. the root is at 0
+ */
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[repr(C)]
+#[derive(Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct BvhNode {
+    pub min: Vec3,
+    pub first: u32,
+    pub max: Vec3,
+    pub count: u32,
+}
+
+impl BvhNode {
+    pub fn is_leaf(&self) -> bool {
+        self.count > 0
+    }
+
+    pub fn aabb(&self) -> Aabb3 {
+        Aabb3::new(self.min, self.max)
+    }
+
+    /**
+     * where the ray enters the node, `f32::INFINITY` if it misses
+     */
+    #[inline]
+    pub fn hit_t(&self, pos: Vec3, inv_dir: Vec3, t_min: f32, t_max: f32) -> f32 {
+        let t0 = (self.min - pos) * inv_dir;
+        let t1 = (self.max - pos) * inv_dir;
+        let near = t0.min(t1).max_element().max(t_min);
+        // rounding can miss a hit exactly on the boundary, see "Robust BVH Ray Traversal", Ize
+        let far = (t0.max(t1).min_element() * (1.0 + 2.0 * GAMMA_3)).min(t_max);
+        if near <= far {
+            near
+        } else {
+            f32::INFINITY
+        }
+    }
+}
+
+/**
+ * closest hit, without recursion so it works in shaders.
+ *
+ * `hit_item(index, t_max)` should test the item and return the new `t_max`, which is the `t` of the
+ * hit if there is one closer than `t_max`. returns the final `t_max`
+ */
+pub fn bvh_closest_hit<F>(nodes: &[BvhNode], ray: &Ray3, t_min: f32, t_max: f32, hit_item: F) -> f32
+where
+    F: FnMut(u32, f32) -> f32,
+{
+    if nodes.len() == 0 {
+        return t_max;
+    }
+    bvh_closest_hit_from(nodes, 0, ray, t_min, t_max, hit_item)
+}
+
+/**
+ * `bvh_closest_hit` of the tree rooted at `root`, for a buffer holding more than one tree
+ */
+pub fn bvh_closest_hit_from<F>(nodes: &[BvhNode], root: u32, ray: &Ray3, t_min: f32, t_max: f32, mut hit_item: F) -> f32
+where
+    F: FnMut(u32, f32) -> f32,
+{
+    let mut t_max = t_max;
+    // zero components would give `0 * inf = NaN` for rays in the plane of a box
+    let inv_dir = 1.0 / ray.dir.de_eps(1e-20);
+    let mut stack = [0u32; BVH_STACK_SIZE];
+    stack[0] = root;
+    let mut stack_len = 1;
+    while stack_len > 0 {
+        stack_len -= 1;
+        let node = nodes[stack[stack_len] as usize];
+        // t_max might be smaller than when we pushed it
+        if node.hit_t(ray.pos, inv_dir, t_min, t_max) == f32::INFINITY {
+            continue;
+        }
+        if node.is_leaf() {
+            let mut i = node.first;
+            while i < node.first + node.count {
+                t_max = hit_item(i, t_max);
+                i += 1;
+            }
+        } else {
+            let left = node.first;
+            let right = left + 1;
+            let t_left = nodes[left as usize].hit_t(ray.pos, inv_dir, t_min, t_max);
+            let t_right = nodes[right as usize].hit_t(ray.pos, inv_dir, t_min, t_max);
+            // push the far one first, so we visit the near one first
+            let (near, t_near, far, t_far) = if t_left <= t_right {
+                (left, t_left, right, t_right)
+            } else {
+                (right, t_right, left, t_left)
+            };
+            if t_far != f32::INFINITY {
+                stack[stack_len] = far;
+                stack_len += 1;
+            }
+            if t_near != f32::INFINITY {
+                stack[stack_len] = near;
+                stack_len += 1;
+            }
+        }
+    }
+    t_max
+}
+
+/**
+ * if anything is hit, for shadow rays. stops at the first item `hit_item(index)` says is hit,
+ * without looking for the closest one
+ */
+pub fn bvh_any_hit<F>(nodes: &[BvhNode], ray: &Ray3, t_min: f32, t_max: f32, hit_item: F) -> bool
+where
+    F: FnMut(u32) -> bool,
+{
+    if nodes.len() == 0 {
+        return false;
+    }
+    bvh_any_hit_from(nodes, 0, ray, t_min, t_max, hit_item)
+}
+
+pub fn bvh_any_hit_from<F>(nodes: &[BvhNode], root: u32, ray: &Ray3, t_min: f32, t_max: f32, mut hit_item: F) -> bool
+where
+    F: FnMut(u32) -> bool,
+{
+    let inv_dir = 1.0 / ray.dir.de_eps(1e-20);
+    let mut stack = [0u32; BVH_STACK_SIZE];
+    stack[0] = root;
+    let mut stack_len = 1;
+    while stack_len > 0 {
+        stack_len -= 1;
+        let node = nodes[stack[stack_len] as usize];
+        if node.hit_t(ray.pos, inv_dir, t_min, t_max) == f32::INFINITY {
+            continue;
+        }
+        if node.is_leaf() {
+            let mut i = node.first;
+            while i < node.first + node.count {
+                if hit_item(i) {
+                    return true;
+                }
+                i += 1;
+            }
+        } else {
+            // no order, the parent is checked when popped
+            stack[stack_len] = node.first;
+            stack[stack_len + 1] = node.first + 1;
+            stack_len += 2;
+        }
+    }
+    false
+}
+
+/**
+ * a instance in a two level bvh. the leaves of the top level reference these, and `root` is the
+ * node of its bottom level tree in the same node buffer
+ */
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[repr(C)]
+#[derive(Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct BvhInstance {
+    pub world_to_object: Mat4,
+    pub object_to_world: Mat4,
+    pub root: u32,
+    /// index of the instance before it is sorted into the top level
+    pub index: u32,
+    pub not_used: UVec2,
+}
+
+/**
+ * a triangle of a bottom level tree, `index` is the one in its mesh
+ */
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[repr(C)]
+#[derive(Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct BvhTriangle {
+    pub a: Vec3,
+    pub index: u32,
+    pub b: Vec3,
+    pub not_used0: u32,
+    pub c: Vec3,
+    pub not_used1: u32,
+}
+
+/**
+ * closest hit of a two level bvh, the top level is at 0 in `nodes`.
+ *
+ * `hit_triangle(instance, triangle, object_ray, t_max)` works like `hit_item` of `bvh_closest_hit`,
+ * the ray is in object space where `t` is the same as in world space
+ */
+pub fn tlas_closest_hit<F>(nodes: &[BvhNode], instances: &[BvhInstance], ray: &Ray3, t_min: f32, t_max: f32, mut hit_triangle: F) -> f32
+where
+    F: FnMut(u32, u32, &Ray3, f32) -> f32,
+{
+    bvh_closest_hit(nodes, ray, t_min, t_max, |i, t_max| {
+        let instance = instances[i as usize];
+        let object_ray = Ray3 {
+            pos: instance.world_to_object.transform_point3(ray.pos),
+            dir: instance.world_to_object.transform_vector3(ray.dir),
+            time: ray.time,
+        };
+        bvh_closest_hit_from(nodes, instance.root, &object_ray, t_min, t_max, |t, t_max| {
+            hit_triangle(i, t, &object_ray, t_max)
+        })
+    })
+}
+
+/**
+ * if a two level bvh is hit anywhere, `hit_triangle(instance, triangle, object_ray)` should
+ * check the `t_min`, `t_max` range
+ */
+pub fn tlas_any_hit<F>(nodes: &[BvhNode], instances: &[BvhInstance], ray: &Ray3, t_min: f32, t_max: f32, mut hit_triangle: F) -> bool
+where
+    F: FnMut(u32, u32, &Ray3) -> bool,
+{
+    bvh_any_hit(nodes, ray, t_min, t_max, |i| {
+        let instance = instances[i as usize];
+        let object_ray = Ray3 {
+            pos: instance.world_to_object.transform_point3(ray.pos),
+            dir: instance.world_to_object.transform_vector3(ray.dir),
+            time: ray.time,
+        };
+        bvh_any_hit_from(nodes, instance.root, &object_ray, t_min, t_max, |t| hit_triangle(i, t, &object_ray))
+    })
+}
@@ -0,0 +1,318 @@
+use crate::graphics::*;
+use crate::math::*;
+
+use super::shape::*;
+use super::triangle::*;
+
+/**
+ * a point picked on a light. `pdf` is per area and already includes picking the light from the
+ * list
+ */
+#[derive(Copy, Clone, Debug)]
+pub struct LightSample {
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub emission: RgbLinear,
+    pub pdf: f32,
+    /// index into `LightList::lights`
+    pub light: usize,
+}
+
+impl LightSample {
+    /**
+     * the pdf per solid angle seen from `from`. zero when `from` is behind the light, as lights
+     * only emit from the front
+     */
+    pub fn solid_angle_pdf(&self, from: Vec3) -> f32 {
+        area_to_solid_angle_pdf(self.pdf, from, self.point, self.normal)
+    }
+}
+
+pub fn area_to_solid_angle_pdf(pdf: f32, from: Vec3, point: Vec3, normal: Vec3) -> f32 {
+    let d = from - point;
+    let distance_squared = d.length_squared();
+    let cos = normal.dot(d) / distance_squared.sqrt();
+    if cos > 0.0 {
+        pdf * distance_squared / cos
+    } else {
+        0.0
+    }
+}
+
+// normalized running sums, the last one is exactly 1
+fn build_cdf(weights: impl Iterator<Item = f32>) -> Vec<f32> {
+    let mut sum = 0.0;
+    let mut cdf: Vec<f32> = weights
+        .map(|w| {
+            sum += w;
+            sum
+        })
+        .collect();
+    for c in &mut cdf {
+        *c /= sum;
+    }
+    if let Some(last) = cdf.last_mut() {
+        *last = 1.0;
+    }
+    cdf
+}
+
+// the picked index and `u` remapped to [0, 1) inside it, so it can be used again
+fn sample_cdf(cdf: &[f32], u: f32) -> (usize, f32) {
+    let index = cdf.partition_point(|c| *c <= u).min(cdf.len() - 1);
+    let low = if index == 0 { 0.0 } else { cdf[index - 1] };
+    let width = cdf[index] - low;
+    let u = if width > 0.0 { ((u - low) / width).min(1.0 - f32::EPSILON) } else { 0.0 };
+    (index, u)
+}
+
+fn cdf_pmf(cdf: &[f32], index: usize) -> f32 {
+    cdf[index] - if index == 0 { 0.0 } else { cdf[index - 1] }
+}
+
+fn sample_triangle(a: Vec3, b: Vec3, c: Vec3, u: Vec2) -> Vec3 {
+    let bary = uniform_triangle(u);
+    a * (1.0 - bary.x - bary.y) + b * bary.x + c * bary.y
+}
+
+fn triangle_area(a: Vec3, b: Vec3, c: Vec3) -> f32 {
+    (b - a).cross(c - a).length() * 0.5
+}
+
+/**
+ * a emissive shape that can be sampled uniformly by area, the emission is the same as a
+ * `DiffuseLight` on it
+ */
+#[derive(Clone, Debug)]
+pub struct Light {
+    pub shape: Shape,
+    pub emission: RgbLinear,
+    area: f32,
+    // for meshes, to pick a triangle by area
+    triangle_cdf: Vec<f32>,
+}
+
+impl Light {
+    /**
+     * only spheres, triangles and triangle meshes can be lights, `None` for other shapes
+     */
+    pub fn new(shape: Shape, emission: RgbLinear) -> Option<Light> {
+        let mut triangle_cdf = Vec::new();
+        let area = match &shape {
+            Shape::Sphere(s) => 4.0 * core::f32::consts::PI * s.radius * s.radius,
+            Shape::Triangle(t) => {
+                let [a, b, c] = t.positions;
+                triangle_area(a, b, c)
+            }
+            Shape::TriangleMesh(m) => {
+                let areas: Vec<f32> = m
+                    .indices
+                    .iter()
+                    .map(|i| triangle_area(m.positions[i[0] as usize], m.positions[i[1] as usize], m.positions[i[2] as usize]))
+                    .collect();
+                triangle_cdf = build_cdf(areas.iter().copied());
+                areas.iter().sum()
+            }
+            _ => return None,
+        };
+        Some(Light {
+            shape,
+            emission,
+            area,
+            triangle_cdf,
+        })
+    }
+
+    pub fn area(&self) -> f32 {
+        self.area
+    }
+
+    /**
+     * the power up to a constant factor, used to pick the lights
+     */
+    pub fn power(&self) -> f32 {
+        self.emission.luminance() * self.area
+    }
+
+    /**
+     * a point uniformly on the surface and the normal of the emitting side, the pdf is `1 / area`
+     */
+    pub fn sample(&self, u: Vec2) -> (Vec3, Vec3) {
+        match &self.shape {
+            Shape::Sphere(s) => {
+                let normal = uniform_sphere(u);
+                (s.center + normal * s.radius, normal)
+            }
+            Shape::Triangle(t) => {
+                let [a, b, c] = t.positions;
+                (sample_triangle(a, b, c, u), t.face_normal())
+            }
+            Shape::TriangleMesh(m) => {
+                let (index, x) = sample_cdf(&self.triangle_cdf, u.x);
+                let [a, b, c] = m.indices[index].map(|i| m.positions[i as usize]);
+                (sample_triangle(a, b, c, vec2(x, u.y)), Triangle::new(a, b, c).face_normal())
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+/**
+ * the lights of a scene, picked in proportion to their power
+ */
+#[derive(Clone, Debug, Default)]
+pub struct LightList {
+    pub lights: Vec<Light>,
+    cdf: Vec<f32>,
+}
+
+impl LightList {
+    pub fn new(lights: Vec<Light>) -> LightList {
+        let cdf = build_cdf(lights.iter().map(|l| l.power()));
+        LightList { lights, cdf }
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    /**
+     * the index of a light and the probability it is picked
+     */
+    pub fn pick(&self, u: f32) -> Option<(usize, f32)> {
+        if self.is_empty() {
+            return None;
+        }
+        let (index, _) = sample_cdf(&self.cdf, u);
+        Some((index, self.pmf(index)))
+    }
+
+    pub fn pmf(&self, light: usize) -> f32 {
+        cdf_pmf(&self.cdf, light)
+    }
+
+    /**
+     * the area pdf of `sample` giving a point on `light`, for when a path hits it by itself
+     */
+    pub fn pdf(&self, light: usize) -> f32 {
+        self.pmf(light) / self.lights[light].area
+    }
+
+    /**
+     * picks a light with `u_pick` then a point on it with `u`
+     */
+    pub fn sample(&self, u_pick: f32, u: Vec2) -> Option<LightSample> {
+        let (light, _) = self.pick(u_pick)?;
+        let (point, normal) = self.lights[light].sample(u);
+        Some(LightSample {
+            point,
+            normal,
+            emission: self.lights[light].emission,
+            pdf: self.pdf(light),
+            light,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::bvh::*;
+    use std::sync::Arc;
+    use rand::{Rng, SeedableRng};
+
+    fn quad_mesh(size: Vec2) -> TriangleMesh {
+        // two triangles of different areas
+        TriangleMesh::new(
+            vec![Vec3::ZERO, vec3(size.x, 0.0, 0.0), vec3(size.x, size.y, 0.0), vec3(0.0, size.y * 2.0, 0.0)],
+            vec![],
+            vec![],
+            vec![[0, 1, 2], [0, 2, 3]],
+        )
+    }
+
+    #[test]
+    fn samples_are_on_the_surface() {
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(1);
+        let shapes = vec![
+            Shape::Sphere(Sphere { center: vec3(1.0, 2.0, 3.0), radius: 0.5 }),
+            Shape::Triangle(Triangle::new(vec3(0.0, 0.0, 1.0), vec3(2.0, 0.0, 1.0), vec3(0.0, 1.0, 2.0))),
+            Shape::TriangleMesh(Arc::new(quad_mesh(vec2(1.0, 2.0)))),
+        ];
+        for shape in shapes {
+            let light = Light::new(shape, RgbLinear(Vec3::ONE)).unwrap();
+            for _ in 0..100 {
+                let (point, normal) = light.sample(vec2(rng.gen(), rng.gen()));
+                assert!((normal.length() - 1.0).abs() < 1e-4);
+                let ray = Ray3 { pos: point + normal, dir: -normal, time: 0.0 };
+                let hit = light.shape.hit(&ray, 0.0, 2.0).unwrap();
+                assert!((hit.t - 1.0).abs() < 1e-3, "{:?}", hit);
+                assert!(hit.out);
+            }
+        }
+    }
+
+    #[test]
+    fn mesh_triangles_picked_by_area() {
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(2);
+        let light = Light::new(Shape::TriangleMesh(Arc::new(quad_mesh(Vec2::ONE))), RgbLinear(Vec3::ONE)).unwrap();
+        // 0.5 and 1.0
+        assert!((light.area() - 1.5).abs() < 1e-5);
+        let n = 30000;
+        let in_first = (0..n)
+            .filter(|_| {
+                let (point, _) = light.sample(vec2(rng.gen(), rng.gen()));
+                point.x > point.y
+            })
+            .count();
+        assert!((in_first as f32 / n as f32 - 1.0 / 3.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn lights_picked_by_power() {
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(3);
+        let sphere = |radius| Shape::Sphere(Sphere { center: Vec3::ZERO, radius });
+        let list = LightList::new(vec![
+            Light::new(sphere(1.0), RgbLinear(Vec3::ONE)).unwrap(),
+            Light::new(sphere(1.0), RgbLinear(Vec3::splat(3.0))).unwrap(),
+            Light::new(sphere(2.0), RgbLinear(Vec3::ONE)).unwrap(),
+        ]);
+        assert!(Light::new(Shape::Aabb3(Aabb3::new(Vec3::ZERO, Vec3::ONE)), RgbLinear(Vec3::ONE)).is_none());
+        assert!(LightList::default().sample(0.5, Vec2::ZERO).is_none());
+        let expected = [1.0 / 8.0, 3.0 / 8.0, 4.0 / 8.0];
+        let mut counts = [0; 3];
+        let n = 40000;
+        for _ in 0..n {
+            let (index, pmf) = list.pick(rng.gen()).unwrap();
+            assert!((pmf - expected[index]).abs() < 1e-5);
+            counts[index] += 1;
+        }
+        for i in 0..3 {
+            assert!((counts[i] as f32 / n as f32 - expected[i]).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn solid_angle_of_sphere() {
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(4);
+        let list = LightList::new(vec![
+            Light::new(Shape::Sphere(Sphere { center: Vec3::ZERO, radius: 1.0 }), RgbLinear(Vec3::ONE)).unwrap(),
+        ]);
+        let from = vec3(0.0, 0.0, 3.0);
+        let n = 100000;
+        let mut sum = 0.0;
+        for _ in 0..n {
+            let sample = list.sample(rng.gen(), vec2(rng.gen(), rng.gen())).unwrap();
+            let pdf = sample.solid_angle_pdf(from);
+            if pdf > 0.0 {
+                sum += 1.0 / pdf;
+            }
+        }
+        let expected = 2.0 * core::f32::consts::PI * (1.0 - (1.0f32 - 1.0 / 9.0).sqrt());
+        assert!((sum / n as f32 - expected).abs() < expected * 0.02);
+    }
+}
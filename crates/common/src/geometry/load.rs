@@ -0,0 +1,806 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::graphics::material::*;
+use crate::graphics::*;
+use crate::math::*;
+use crate::shader::base_uniform::RayTracingViewInfo;
+
+use super::light::*;
+use super::shape::*;
+use super::triangle::*;
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(std::io::Error),
+    Obj(tobj::LoadError),
+    Ply(String),
+    Gltf(gltf::Error),
+}
+
+impl From<std::io::Error> for LoadError {
+    fn from(e: std::io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+impl From<tobj::LoadError> for LoadError {
+    fn from(e: tobj::LoadError) -> Self {
+        LoadError::Obj(e)
+    }
+}
+
+impl From<gltf::Error> for LoadError {
+    fn from(e: gltf::Error) -> Self {
+        LoadError::Gltf(e)
+    }
+}
+
+/**
+ * `emission` is kept aside from `material`, see `LoadedScene::lights`. `principled` is the same
+ * material without picking one of the simple ones
+ */
+#[derive(Copy, Clone)]
+pub struct LoadedMaterial {
+    pub material: Material,
+    pub principled: Principled,
+    pub emission: RgbLinear,
+    /// index into `LoadedScene::images`, multiplies the albedo
+    pub base_color_texture: Option<usize>,
+}
+
+/**
+ * 8 bit rgba, the color channels are sRGB encoded
+ */
+#[derive(Clone, Debug)]
+pub struct LoadedImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[u8; 4]>,
+}
+
+/**
+ * a perspective camera, looking at -z of `transform`. `aspect_ratio` is width / height, when it is
+ * `None` the one of the viewport is used
+ */
+#[derive(Copy, Clone, Debug)]
+pub struct LoadedCamera {
+    pub transform: Mat4,
+    pub yfov: f32,
+    pub aspect_ratio: Option<f32>,
+    pub znear: f32,
+    pub zfar: Option<f32>,
+}
+
+impl LoadedCamera {
+    pub fn projection(&self, viewport_aspect_ratio: f32) -> Mat4 {
+        let aspect_ratio = self.aspect_ratio.unwrap_or(viewport_aspect_ratio);
+        match self.zfar {
+            Some(zfar) => Mat4::perspective_rh(self.yfov, aspect_ratio, self.znear, zfar),
+            None => Mat4::perspective_infinite_reverse_rh(self.yfov, aspect_ratio, self.znear),
+        }
+    }
+
+    pub fn view_info(&self, size: UVec2, time: f32, frame_index: u32) -> RayTracingViewInfo {
+        let projection = self.projection(size.x as f32 / size.y as f32);
+        RayTracingViewInfo::from_camera(self.transform, projection, size, time, frame_index)
+    }
+}
+
+pub struct LoadedMesh {
+    pub name: String,
+    pub mesh: Arc<TriangleMesh>,
+    /// index into `LoadedScene::materials`
+    pub material: Option<usize>,
+}
+
+pub struct LoadedScene {
+    pub meshes: Vec<LoadedMesh>,
+    pub materials: Vec<LoadedMaterial>,
+    pub images: Vec<LoadedImage>,
+    pub cameras: Vec<LoadedCamera>,
+}
+
+impl LoadedScene {
+    pub fn shapes(&self) -> Vec<Shape> {
+        self.meshes.iter().map(|m| Shape::TriangleMesh(m.mesh.clone())).collect()
+    }
+
+    /**
+     * the meshes with a emissive material
+     */
+    pub fn lights(&self) -> LightList {
+        let lights = self
+            .meshes
+            .iter()
+            .filter_map(|m| {
+                let emission = self.materials[m.material?].emission;
+                if emission.luminance() > 0.0 {
+                    Light::new(Shape::TriangleMesh(m.mesh.clone()), emission)
+                } else {
+                    None
+                }
+            })
+            .collect();
+        LightList::new(lights)
+    }
+}
+
+fn rgb(c: [f32; 3]) -> RgbLinear {
+    RgbLinear(Vec3::from(c))
+}
+
+// the `specular` of `Principled` with the normal reflectance of `ior`
+fn specular_from_ior(ior: f32) -> f32 {
+    let f0 = (ior - 1.0) / (ior + 1.0);
+    (f0 * f0 / 0.08).min(1.0)
+}
+
+/**
+ * glass if it is transparent or uses one of the refraction illumination models, metal if it is
+ * more specular than diffuse or uses the reflection model, otherwise lambertian
+ */
+pub fn material_from_mtl(m: &tobj::Material) -> LoadedMaterial {
+    let illum = m.illumination_model.unwrap_or(2);
+    let diffuse = rgb(m.diffuse);
+    let specular = rgb(m.specular);
+    let ref_idx = if m.optical_density > 1.0 { m.optical_density } else { 1.5 };
+    // phong exponent to roughness
+    let roughness = (2.0 / (m.shininess.max(0.0) + 2.0)).sqrt();
+    let transparent = m.dissolve < 1.0 || [4, 6, 7, 9].contains(&illum);
+    let metallic = illum == 3 || specular.0.max_element() > diffuse.0.max_element();
+    let material = if transparent {
+        Material::Dielectric(Dielectric { ref_idx })
+    } else if metallic {
+        Material::Metal(Metal { albedo: specular, fuzz: roughness })
+    } else {
+        Material::Lambertian(Lambertian { albedo: diffuse })
+    };
+    let principled = Principled {
+        base_color: if metallic && !transparent { specular } else { diffuse },
+        metallic: if metallic && !transparent { 1.0 } else { 0.0 },
+        roughness,
+        specular: specular_from_ior(ref_idx),
+        transmission: if transparent { 1.0 } else { 0.0 },
+        ..Default::default()
+    };
+    // `Ke` is not something tobj knows
+    let emission = m
+        .unknown_param
+        .get("Ke")
+        .and_then(|ke| {
+            let v: Vec<f32> = ke.split_whitespace().filter_map(|f| f.parse().ok()).collect();
+            if v.len() == 3 { Some(RgbLinear(vec3(v[0], v[1], v[2]))) } else { None }
+        })
+        .unwrap_or(RgbLinear(Vec3::ZERO));
+    LoadedMaterial {
+        material,
+        principled,
+        emission,
+        base_color_texture: None,
+    }
+}
+
+/**
+ * a Wavefront OBJ, with the materials in its MTL libraries. one mesh per object and material. when
+ * the MTL libraries can't be read the meshes are still loaded, without materials
+ */
+pub fn load_obj(path: &Path) -> Result<LoadedScene, LoadError> {
+    let (models, materials) = tobj::load_obj(
+        path,
+        &tobj::LoadOptions {
+            single_index: true,
+            triangulate: true,
+            ignore_points: true,
+            ignore_lines: true,
+        },
+    )?;
+    let materials: Vec<LoadedMaterial> = materials.map(|m| m.iter().map(material_from_mtl).collect()).unwrap_or_default();
+    let meshes = models
+        .into_iter()
+        .map(|model| {
+            let m = model.mesh;
+            let mesh = TriangleMesh::new(
+                m.positions.chunks_exact(3).map(Vec3::from_slice).collect(),
+                m.normals.chunks_exact(3).map(Vec3::from_slice).collect(),
+                m.texcoords.chunks_exact(2).map(Vec2::from_slice).collect(),
+                m.indices.chunks_exact(3).map(|i| [i[0], i[1], i[2]]).collect(),
+            );
+            LoadedMesh {
+                name: model.name,
+                mesh: Arc::new(mesh),
+                material: m.material_id.filter(|&i| i < materials.len()),
+            }
+        })
+        .collect();
+    Ok(LoadedScene {
+        meshes,
+        materials,
+        images: vec![],
+        cameras: vec![],
+    })
+}
+
+/**
+ * metallic-roughness to the closest material we have, `KHR_materials_transmission` makes it glass
+ */
+pub fn material_from_gltf(m: &gltf::Material) -> LoadedMaterial {
+    let pbr = m.pbr_metallic_roughness();
+    let [r, g, b, _] = pbr.base_color_factor();
+    let albedo = RgbLinear(vec3(r, g, b));
+    let transmission = m.transmission().map_or(0.0, |t| t.transmission_factor());
+    let ior = m.ior().unwrap_or(1.5);
+    let material = if transmission >= 0.5 {
+        Material::Dielectric(Dielectric { ref_idx: ior })
+    } else if pbr.metallic_factor() >= 0.5 {
+        Material::Metal(Metal {
+            albedo,
+            fuzz: pbr.roughness_factor(),
+        })
+    } else {
+        Material::Lambertian(Lambertian { albedo })
+    };
+    let principled = Principled {
+        base_color: albedo,
+        metallic: pbr.metallic_factor(),
+        roughness: pbr.roughness_factor(),
+        specular: specular_from_ior(ior),
+        transmission,
+        ..Default::default()
+    };
+    LoadedMaterial {
+        material,
+        principled,
+        emission: rgb(m.emissive_factor()),
+        base_color_texture: pbr.base_color_texture().map(|t| t.texture().source().index()),
+    }
+}
+
+fn image_from_gltf(data: &gltf::image::Data) -> LoadedImage {
+    use gltf::image::Format;
+    let (channels, bytes) = match data.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 | Format::B8G8R8 => (3, 1),
+        Format::R8G8B8A8 | Format::B8G8R8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+    };
+    let bgr = matches!(data.format, Format::B8G8R8 | Format::B8G8R8A8);
+    let pixels = data
+        .pixels
+        .chunks_exact(channels * bytes)
+        .map(|p| {
+            // the high byte, 16 bit values are little endian
+            let c = |i: usize| p[i * bytes + bytes - 1];
+            let mut rgba = match channels {
+                1 => [c(0), c(0), c(0), 255],
+                2 => [c(0), c(0), c(0), c(1)],
+                3 => [c(0), c(1), c(2), 255],
+                _ => [c(0), c(1), c(2), c(3)],
+            };
+            if bgr {
+                rgba.swap(0, 2);
+            }
+            rgba
+        })
+        .collect();
+    LoadedImage {
+        width: data.width,
+        height: data.height,
+        pixels,
+    }
+}
+
+fn load_gltf_node(
+    node: gltf::Node,
+    parent: Mat4,
+    buffers: &[gltf::buffer::Data],
+    scene: &mut LoadedScene,
+) {
+    let transform = parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+    // orthographic cameras can't be expressed by `RayTracingViewInfo`
+    if let Some(camera) = node.camera() {
+        if let gltf::camera::Projection::Perspective(p) = camera.projection() {
+            scene.cameras.push(LoadedCamera {
+                transform,
+                yfov: p.yfov(),
+                aspect_ratio: p.aspect_ratio(),
+                znear: p.znear(),
+                zfar: p.zfar(),
+            });
+        }
+    }
+    if let Some(mesh) = node.mesh() {
+        // meshes are baked into world space
+        let normal_transform = Mat3::from_mat4(transform).inverse().transpose();
+        let mirrored = transform.determinant() < 0.0;
+        for (i, primitive) in mesh.primitives().enumerate() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                continue;
+            }
+            let reader = primitive.reader(|b| Some(&buffers[b.index()]));
+            let positions: Vec<Vec3> = match reader.read_positions() {
+                Some(p) => p.map(|p| transform.transform_point3(Vec3::from(p))).collect(),
+                None => continue,
+            };
+            let normals = reader.read_normals().map_or(vec![], |n| {
+                n.map(|n| (normal_transform * Vec3::from(n)).normalize_or_zero()).collect()
+            });
+            let uvs = reader
+                .read_tex_coords(0)
+                .map_or(vec![], |uv| uv.into_f32().map(Vec2::from).collect());
+            let indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..positions.len() as u32).collect(),
+            };
+            let indices = indices
+                .chunks_exact(3)
+                .map(|t| if mirrored { [t[0], t[2], t[1]] } else { [t[0], t[1], t[2]] })
+                .collect();
+            let name = match (node.name(), mesh.name()) {
+                (Some(n), _) | (None, Some(n)) => n.to_string(),
+                (None, None) => String::new(),
+            };
+            scene.meshes.push(LoadedMesh {
+                name: format!("{}.{}", name, i),
+                mesh: Arc::new(TriangleMesh::new(positions, normals, uvs, indices)),
+                material: primitive.material().index(),
+            });
+        }
+    }
+    for child in node.children() {
+        load_gltf_node(child, transform, buffers, scene);
+    }
+}
+
+/**
+ * a `.gltf` or `.glb`, the default scene or the first one. `LoadedMesh::material` is `None` for
+ * the default material
+ */
+pub fn load_gltf(path: &Path) -> Result<LoadedScene, LoadError> {
+    let (document, buffers, images) = gltf::import(path)?;
+    let mut scene = LoadedScene {
+        meshes: vec![],
+        materials: document.materials().map(|m| material_from_gltf(&m)).collect(),
+        images: images.iter().map(image_from_gltf).collect(),
+        cameras: vec![],
+    };
+    if let Some(root) = document.default_scene().or_else(|| document.scenes().next()) {
+        for node in root.nodes() {
+            load_gltf_node(node, Mat4::IDENTITY, &buffers, &mut scene);
+        }
+    }
+    Ok(scene)
+}
+
+#[derive(Copy, Clone)]
+enum PlyType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl PlyType {
+    fn parse(s: &str) -> Result<PlyType, LoadError> {
+        Ok(match s {
+            "char" | "int8" => PlyType::I8,
+            "uchar" | "uint8" => PlyType::U8,
+            "short" | "int16" => PlyType::I16,
+            "ushort" | "uint16" => PlyType::U16,
+            "int" | "int32" => PlyType::I32,
+            "uint" | "uint32" => PlyType::U32,
+            "float" | "float32" => PlyType::F32,
+            "double" | "float64" => PlyType::F64,
+            _ => return Err(LoadError::Ply(format!("unknown type {}", s))),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            PlyType::I8 | PlyType::U8 => 1,
+            PlyType::I16 | PlyType::U16 => 2,
+            PlyType::I32 | PlyType::U32 | PlyType::F32 => 4,
+            PlyType::F64 => 8,
+        }
+    }
+}
+
+struct PlyProperty {
+    name: String,
+    ty: PlyType,
+    // the type of the count for list properties
+    list: Option<PlyType>,
+}
+
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+enum PlyBody<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary { bytes: &'a [u8], big_endian: bool },
+}
+
+impl<'a> PlyBody<'a> {
+    fn read(&mut self, ty: PlyType) -> Result<f64, LoadError> {
+        let eof = || LoadError::Ply("unexpected end of file".to_string());
+        match self {
+            PlyBody::Ascii(tokens) => {
+                let token = tokens.next().ok_or_else(eof)?;
+                token.parse().map_err(|_| LoadError::Ply(format!("bad number {}", token)))
+            }
+            PlyBody::Binary { bytes, big_endian } => {
+                let size = ty.size();
+                if bytes.len() < size {
+                    return Err(eof());
+                }
+                let mut b = [0u8; 8];
+                b[..size].copy_from_slice(&bytes[..size]);
+                *bytes = &bytes[size..];
+                if *big_endian {
+                    b[..size].reverse();
+                }
+                Ok(match ty {
+                    PlyType::I8 => b[0] as i8 as f64,
+                    PlyType::U8 => b[0] as f64,
+                    PlyType::I16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+                    PlyType::U16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+                    PlyType::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    PlyType::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    PlyType::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    PlyType::F64 => f64::from_le_bytes(b),
+                })
+            }
+        }
+    }
+}
+
+/**
+ * a ascii or binary PLY, only `vertex` (position, normal and uv) and `face` are used.
+ * polygons are triangulated as fans
+ */
+pub fn load_ply(path: &Path) -> Result<TriangleMesh, LoadError> {
+    parse_ply(&fs::read(path)?)
+}
+
+fn parse_ply(data: &[u8]) -> Result<TriangleMesh, LoadError> {
+    let bad = |s: &str| LoadError::Ply(s.to_string());
+    let header_end = data
+        .windows(b"end_header".len())
+        .position(|w| w == b"end_header")
+        .ok_or_else(|| bad("no end_header"))?;
+    let body_start = data[header_end..]
+        .iter()
+        .position(|c| *c == b'\n')
+        .map_or(data.len(), |p| header_end + p + 1);
+    let header = std::str::from_utf8(&data[..header_end]).map_err(|_| bad("header is not utf8"))?;
+    let mut lines = header.lines().map(|l| l.trim());
+    if lines.next() != Some("ply") {
+        return Err(bad("not a ply file"));
+    }
+    let mut format = None;
+    let mut elements: Vec<PlyElement> = vec![];
+    for line in lines {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", f, _] => format = Some(*f),
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| bad("bad element count"))?,
+                properties: vec![],
+            }),
+            ["property", "list", count, ty, name] => elements
+                .last_mut()
+                .ok_or_else(|| bad("property before element"))?
+                .properties
+                .push(PlyProperty {
+                    name: name.to_string(),
+                    ty: PlyType::parse(ty)?,
+                    list: Some(PlyType::parse(count)?),
+                }),
+            ["property", ty, name] => elements
+                .last_mut()
+                .ok_or_else(|| bad("property before element"))?
+                .properties
+                .push(PlyProperty {
+                    name: name.to_string(),
+                    ty: PlyType::parse(ty)?,
+                    list: None,
+                }),
+            _ => {}
+        }
+    }
+    let body = &data[body_start..];
+    let mut body = match format {
+        Some("ascii") => PlyBody::Ascii(
+            std::str::from_utf8(body)
+                .map_err(|_| bad("body is not utf8"))?
+                .split_ascii_whitespace(),
+        ),
+        Some("binary_little_endian") => PlyBody::Binary { bytes: body, big_endian: false },
+        Some("binary_big_endian") => PlyBody::Binary { bytes: body, big_endian: true },
+        _ => return Err(bad("unknown format")),
+    };
+
+    let mut positions = vec![];
+    let mut normals = vec![];
+    let mut uvs = vec![];
+    let mut indices = vec![];
+    for element in &elements {
+        let mut values: HashMap<&str, f64> = HashMap::new();
+        for _ in 0..element.count {
+            values.clear();
+            let mut polygon: Vec<u32> = vec![];
+            for property in &element.properties {
+                match property.list {
+                    Some(count_ty) => {
+                        let count = body.read(count_ty)? as usize;
+                        let is_face = property.name == "vertex_indices" || property.name == "vertex_index";
+                        for _ in 0..count {
+                            let v = body.read(property.ty)?;
+                            if is_face {
+                                polygon.push(v as u32);
+                            }
+                        }
+                    }
+                    None => {
+                        values.insert(&property.name, body.read(property.ty)?);
+                    }
+                }
+            }
+            if element.name == "vertex" {
+                let get = |names: &[&str]| names.iter().find_map(|n| values.get(n)).map(|v| *v as f32);
+                let (x, y, z) = (get(&["x"]), get(&["y"]), get(&["z"]));
+                positions.push(vec3(x.unwrap_or(0.0), y.unwrap_or(0.0), z.unwrap_or(0.0)));
+                if let (Some(x), Some(y), Some(z)) = (get(&["nx"]), get(&["ny"]), get(&["nz"])) {
+                    normals.push(vec3(x, y, z));
+                }
+                if let (Some(u), Some(v)) = (get(&["u", "s", "texture_u"]), get(&["v", "t", "texture_v"])) {
+                    uvs.push(vec2(u, v));
+                }
+            } else if element.name == "face" {
+                for i in 2..polygon.len() {
+                    indices.push([polygon[0], polygon[i - 1], polygon[i]]);
+                }
+            }
+        }
+    }
+    // only per vertex attributes all the vertices have
+    if normals.len() != positions.len() {
+        normals.clear();
+    }
+    if uvs.len() != positions.len() {
+        uvs.clear();
+    }
+    if let Some(i) = indices.iter().flatten().find(|i| **i as usize >= positions.len()) {
+        return Err(LoadError::Ply(format!("vertex index {} out of range", i)));
+    }
+    Ok(TriangleMesh::new(positions, normals, uvs, indices))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::bvh::*;
+
+    fn temp_file(name: &str, content: &[u8]) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join("common-geometry-load");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn check_quad(mesh: &TriangleMesh) {
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.triangle_count(), 2);
+        let ray = Ray3 {
+            pos: vec3(0.25, 0.75, 1.0),
+            dir: -Vec3::Z,
+            time: 0.0,
+        };
+        let hit = mesh.hit(&ray, 0.0, f32::INFINITY).unwrap();
+        assert_eq!(hit.t, 1.0);
+        assert!(hit.out);
+        assert_eq!(hit.nor, Vec3::Z);
+    }
+
+    #[test]
+    fn load_obj_with_mtl() {
+        temp_file(
+            "quad.mtl",
+            b"newmtl red\nKd 0.8 0.1 0.1\nKs 0 0 0\nillum 2\n\
+              newmtl mirror\nKd 0 0 0\nKs 0.9 0.9 0.9\nNs 1000\nillum 3\n\
+              newmtl glass\nKd 1 1 1\nNi 1.33\nd 0.1\nillum 7\n\
+              newmtl lamp\nKd 1 1 1\nKe 4 3 2\n",
+        );
+        let path = temp_file(
+            "quad.obj",
+            b"mtllib quad.mtl\no quad\n\
+              v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+              vn 0 0 1\nvt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n\
+              usemtl red\nf 1/1/1 2/2/1 3/3/1 4/4/1\n",
+        );
+        let scene = load_obj(&path).unwrap();
+        assert_eq!(scene.meshes.len(), 1);
+        assert_eq!(scene.meshes[0].name, "quad");
+        check_quad(&scene.meshes[0].mesh);
+        assert_eq!(scene.materials.len(), 4);
+        let material = |name: &str| {
+            let i = ["red", "mirror", "glass", "lamp"].iter().position(|n| *n == name).unwrap();
+            scene.materials[i]
+        };
+        assert_eq!(scene.meshes[0].material, Some(0));
+        assert!(matches!(material("red").material, Material::Lambertian(l) if l.albedo.0 == vec3(0.8, 0.1, 0.1)));
+        assert!(matches!(material("mirror").material, Material::Metal(m) if m.fuzz < 0.1));
+        assert!(matches!(material("glass").material, Material::Dielectric(d) if d.ref_idx == 1.33));
+        assert_eq!(material("mirror").principled.metallic, 1.0);
+        assert!(material("glass").principled.transmission > 0.0);
+        assert_eq!(material("red").principled.base_color.0, vec3(0.8, 0.1, 0.1));
+        assert_eq!(material("lamp").emission.0, vec3(4.0, 3.0, 2.0));
+        assert_eq!(material("red").emission.0, Vec3::ZERO);
+        assert!(scene.lights().is_empty());
+    }
+
+    #[test]
+    fn load_obj_without_mtl() {
+        let path = temp_file(
+            "quad_missing_mtl.obj",
+            b"mtllib missing.mtl
+o quad
+              v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vn 0 0 1
+              usemtl red
+f 1//1 2//1 3//1 4//1
+",
+        );
+        let scene = load_obj(&path).unwrap();
+        check_quad(&scene.meshes[0].mesh);
+        assert!(scene.materials.is_empty());
+        assert_eq!(scene.meshes[0].material, None);
+    }
+
+    const PLY_HEADER: &str = "element vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+                              property float nx\nproperty float ny\nproperty float nz\n\
+                              element face 1\nproperty list uchar int vertex_indices\nend_header\n";
+
+    #[test]
+    fn load_ply_ascii() {
+        let ply = format!(
+            "ply\nformat ascii 1.0\ncomment a quad\n{}\
+             0 0 0 0 0 1\n1 0 0 0 0 1\n1 1 0 0 0 1\n0 1 0 0 0 1\n4 0 1 2 3\n",
+            PLY_HEADER
+        );
+        let mesh = load_ply(&temp_file("quad_ascii.ply", ply.as_bytes())).unwrap();
+        check_quad(&mesh);
+        assert_eq!(mesh.normals.len(), 4);
+    }
+
+    #[test]
+    fn load_ply_binary() {
+        let vertices = [[0.0f32, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        for big_endian in [false, true] {
+            let format = if big_endian { "binary_big_endian" } else { "binary_little_endian" };
+            let mut ply = format!("ply\nformat {} 1.0\n{}", format, PLY_HEADER).into_bytes();
+            let mut push = |f: f32| ply.extend(if big_endian { f.to_be_bytes() } else { f.to_le_bytes() });
+            for [x, y] in vertices {
+                for f in [x, y, 0.0, 0.0, 0.0, 1.0] {
+                    push(f);
+                }
+            }
+            ply.push(4);
+            for i in 0..4i32 {
+                ply.extend(if big_endian { i.to_be_bytes() } else { i.to_le_bytes() });
+            }
+            let mesh = load_ply(&temp_file(&format!("quad_{}.ply", format), &ply)).unwrap();
+            check_quad(&mesh);
+        }
+        assert!(matches!(parse_ply(b"ply\nformat binary_little_endian 1.0\nelement vertex 1\nproperty float x\nend_header\n"), Err(LoadError::Ply(_))));
+    }
+
+    #[test]
+    fn load_gltf_scene() {
+        let mut bin = vec![];
+        for v in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0] {
+            bin.extend(v.to_le_bytes());
+        }
+        for i in [0u16, 1, 2, 0, 2, 3] {
+            bin.extend(i.to_le_bytes());
+        }
+        temp_file("scene.bin", &bin);
+        let png = temp_file("scene.png", &[]);
+        image::RgbaImage::from_raw(2, 1, vec![255, 0, 0, 255, 0, 0, 255, 128]).unwrap().save(&png).unwrap();
+        let path = temp_file(
+            "scene.gltf",
+            br#"{
+              "asset": { "version": "2.0" },
+              "extensionsUsed": ["KHR_materials_transmission", "KHR_materials_ior"],
+              "scene": 0,
+              "scenes": [{ "nodes": [0, 2, 3, 4] }],
+              "nodes": [
+                { "name": "root", "translation": [0, 0, -5], "children": [1] },
+                { "name": "quad", "scale": [2, 2, 2], "mesh": 0 },
+                { "name": "camera", "translation": [1, 1, 0], "camera": 0 },
+                { "name": "mirrored", "translation": [0, 0, -10], "scale": [-1, 1, 1], "mesh": 0 },
+                { "name": "far camera", "translation": [1, 1, 0], "camera": 1 }
+              ],
+              "cameras": [
+                { "type": "perspective", "perspective": { "yfov": 0.5, "aspectRatio": 2.0, "znear": 0.1 } },
+                { "type": "perspective", "perspective": { "yfov": 0.5, "znear": 0.1, "zfar": 100 } }
+              ],
+              "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 }, "indices": 1, "material": 0 }] }],
+              "materials": [
+                { "pbrMetallicRoughness": { "baseColorFactor": [0.5, 0.5, 0.5, 1], "metallicFactor": 0, "baseColorTexture": { "index": 0 } }, "emissiveFactor": [1, 2, 3] },
+                { "pbrMetallicRoughness": { "metallicFactor": 1, "roughnessFactor": 0.2 } },
+                { "extensions": { "KHR_materials_transmission": { "transmissionFactor": 1 }, "KHR_materials_ior": { "ior": 1.4 } } }
+              ],
+              "textures": [{ "source": 0 }],
+              "images": [{ "uri": "scene.png" }],
+              "buffers": [{ "uri": "scene.bin", "byteLength": 60 }],
+              "bufferViews": [
+                { "buffer": 0, "byteOffset": 0, "byteLength": 48 },
+                { "buffer": 0, "byteOffset": 48, "byteLength": 12 }
+              ],
+              "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] },
+                { "bufferView": 1, "componentType": 5123, "count": 6, "type": "SCALAR" }
+              ]
+            }"#,
+        );
+        let scene = load_gltf(&path).unwrap();
+
+        assert_eq!(scene.meshes.len(), 2);
+        assert_eq!(scene.meshes[0].name, "quad.0");
+        assert_eq!(scene.meshes[0].material, Some(0));
+        let quad = &scene.meshes[0].mesh;
+        assert_eq!(quad.aabb().min, vec3(0.0, 0.0, -5.0));
+        assert_eq!(quad.aabb().max, vec3(2.0, 2.0, -5.0));
+        // mirroring keeps the front face
+        let hit = scene.meshes[1].mesh.hit(&Ray3 { pos: vec3(-0.5, 0.5, 0.0), dir: -Vec3::Z, time: 0.0 }, 0.0, f32::INFINITY);
+        assert!(hit.unwrap().out);
+
+        assert_eq!(scene.materials.len(), 3);
+        let textured = scene.materials[0];
+        assert!(matches!(textured.material, Material::Lambertian(l) if l.albedo.0 == Vec3::splat(0.5)));
+        assert_eq!(textured.emission.0, vec3(1.0, 2.0, 3.0));
+        assert_eq!(textured.base_color_texture, Some(0));
+        assert!(matches!(scene.materials[1].material, Material::Metal(m) if m.fuzz == 0.2 && m.albedo.0 == Vec3::ONE));
+        assert!(matches!(scene.materials[2].material, Material::Dielectric(d) if d.ref_idx == 1.4));
+        let principled = scene.materials[1].principled;
+        assert!(principled.metallic == 1.0 && principled.roughness == 0.2 && principled.transmission == 0.0);
+        let principled = scene.materials[2].principled;
+        assert!(principled.transmission == 1.0 && (principled.specular - 0.347).abs() < 1e-3);
+        assert_eq!(scene.images.len(), 1);
+        assert_eq!(scene.images[0].pixels, vec![[255, 0, 0, 255], [0, 0, 255, 128]]);
+
+        assert_eq!(scene.cameras.len(), 2);
+        let size = uvec2(200, 100);
+        for camera in &scene.cameras {
+            let view = camera.view_info(size, 0.0, 0);
+            assert_eq!(view.camera_pos, vec3(1.0, 1.0, 0.0));
+            let center = view.get_ray(size.as_vec2() * 0.5);
+            assert!((center.dir.normalize() - -Vec3::Z).abs().max_element() < 1e-6);
+            let hit = scene.shapes()[0].hit(&Ray3 { pos: center.pos, dir: center.dir.normalize(), time: 0.0 }, 0.0, f32::INFINITY);
+            assert!((hit.unwrap().t - 5.0).abs() < 1e-5);
+            // the top left corner
+            let corner = view.get_ray(Vec2::ZERO).dir;
+            let tan = (0.5f32 / 2.0).tan();
+            assert!((corner / -corner.z - vec3(-2.0 * tan, tan, -1.0)).abs().max_element() < 1e-5);
+        }
+    }
+}
@@ -1,2 +1,14 @@
+pub mod bvh_node;
+#[cfg(not(target_arch = "spirv"))]
 pub mod bvh;
-pub mod shape;
\ No newline at end of file
+#[cfg(not(target_arch = "spirv"))]
+pub mod light;
+#[cfg(not(target_arch = "spirv"))]
+pub mod load;
+#[cfg(not(target_arch = "spirv"))]
+pub mod shape;
+#[cfg(not(target_arch = "spirv"))]
+pub mod triangle;
+#[cfg(not(target_arch = "spirv"))]
+pub mod tlas;
+pub mod util;
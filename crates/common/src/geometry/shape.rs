@@ -1,7 +1,11 @@
 use std::mem::swap;
+use std::sync::Arc;
 
-use super::math::*;
+use crate::graphics::beer_lambert::*;
+use crate::graphics::material::*;
+use crate::math::*;
 use super::bvh::*;
+use super::triangle::*;
 
 #[derive(Copy, Clone, Debug)]
 pub struct HitRecord {
@@ -9,6 +13,9 @@ pub struct HitRecord {
     pub out: bool,
     pub nor: Vec3,
     pub t: f32,
+    pub uv: Vec2,
+    /// weights of the three vertices for triangles, zero for other shapes
+    pub barycentric: Vec3,
 }
 
 impl BaseHitResult for HitRecord {
@@ -27,11 +34,12 @@ impl Hittable for Aabb3 {
     fn hit(&self, ray: &Ray3, t_min: f32, t_max: f32) -> Option<HitRecord> {
         let mut candidate: Option<HitRecord> = None;
         for a in 0..3 {
+            let a = Axis3::from_raw(a);
             let side = self.omit(a);
-            let inv = 1.0 / ray.dir[a];
-            let mut t0 = (self.min[a] - ray.pos[a]) * inv;
-            let mut t1 = (self.max[a] - ray.pos[a]) * inv;
-            let mut axis = -Vec3::new_axis(a);
+            let inv = 1.0 / ray.dir.get(a);
+            let mut t0 = (self.min.get(a) - ray.pos.get(a)) * inv;
+            let mut t1 = (self.max.get(a) - ray.pos.get(a)) * inv;
+            let mut axis = -a.as_vec3();
             if inv < 0.0 {
                 swap(&mut t0, &mut t1);
                 axis = -axis;
@@ -41,23 +49,27 @@ impl Hittable for Aabb3 {
             if t_min < t0 && t_max > t0 {
                 // go inside from min side
                 let point = ray.at(t0);
-                if side.inside(point.omit_axis(a)) {
+                if side.contains(point.omit_axis(a)) {
                     candidate = Some(HitRecord {
                         point: point,
                         out: true,
                         nor: axis,
                         t: t0,
+                        uv: Vec2::ZERO,
+                        barycentric: Vec3::ZERO,
                     });
                 }
             } else if t_min < t1 && t_max > t1 {
                 // to outside from max side
                 let point = ray.at(t1);
-                if side.inside(point.omit_axis(a)) {
+                if side.contains(point.omit_axis(a)) {
                     candidate = Some(HitRecord {
                         point: point,
                         out: false,
                         nor: axis,
                         t: t1,
+                        uv: Vec2::ZERO,
+                        barycentric: Vec3::ZERO,
                     });
                 }
             } else if t_max_original <= t0 || t_min >= t1 {
@@ -107,6 +119,11 @@ impl Hittable for Sphere {
             t.map(|t| {
                 let point = ray.at(t);
                 let mut normal: Vec3 = (point - self.center) / self.radius;
+                // longitude and latitude, v goes up from -y
+                let uv = vec2(
+                    ((-normal.z).atan2(normal.x) + core::f32::consts::PI) / (2.0 * core::f32::consts::PI),
+                    (-normal.y).clamp(-1.0, 1.0).acos() / core::f32::consts::PI,
+                );
                 let out = normal.dot(ray.dir) <= 0.0;
                 if !out {
                     normal = -normal;
@@ -116,6 +133,8 @@ impl Hittable for Sphere {
                     nor: normal,
                     out,
                     t,
+                    uv,
+                    barycentric: Vec3::ZERO,
                 }
             })
         } else {
@@ -124,21 +143,328 @@ impl Hittable for Sphere {
     }
 }
 
+/**
+ * `shape` placed by an affine `transform`, rays are moved into object space. the direction is not
+ * normalized there, so `t` is the same in both spaces
+ */
+#[derive(Clone, Debug)]
+pub struct Instance {
+    // shared, so the same shape can be placed many times
+    pub shape: Arc<Shape>,
+    transform: Affine3A,
+    inverse: Affine3A,
+    aabb: Aabb3,
+}
+
+impl Instance {
+    pub fn new(shape: Arc<Shape>, transform: Affine3A) -> Instance {
+        let aabb = transform_aabb(&transform, &shape.aabb());
+        Instance {
+            shape,
+            transform,
+            inverse: transform.inverse(),
+            aabb,
+        }
+    }
+
+    pub fn transform(&self) -> Affine3A {
+        self.transform
+    }
+
+    pub fn set_transform(&mut self, transform: Affine3A) {
+        self.transform = transform;
+        self.inverse = transform.inverse();
+        self.update_aabb();
+    }
+
+    /// after `shape` changed
+    pub fn update_aabb(&mut self) {
+        self.aabb = transform_aabb(&self.transform, &self.shape.aabb());
+    }
+}
+
+/**
+ * the box around the transformed box, "Transforming Axis-Aligned Bounding Boxes", Arvo
+ */
+pub fn transform_aabb(transform: &Affine3A, aabb: &Aabb3) -> Aabb3 {
+    if aabb.min.cmpgt(aabb.max).any() {
+        return Aabb3::empty();
+    }
+    let m = transform.matrix3;
+    let abs = Mat3A::from_cols(m.x_axis.abs(), m.y_axis.abs(), m.z_axis.abs());
+    let center = transform.transform_point3(aabb.center());
+    let extent = abs.mul_vec3((aabb.max - aabb.min) * 0.5);
+    Aabb3::new(center - extent, center + extent)
+}
+
+impl Hittable for Instance {
+    type T = HitRecord;
+
+    fn aabb(self: &Self) -> Aabb3 {
+        self.aabb
+    }
+
+    fn hit(&self, ray: &Ray3, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        hit_transformed(&self.shape, &self.transform, &self.inverse, ray, t_min, t_max)
+    }
+
+    fn occluded(&self, ray: &Ray3, t_min: f32, t_max: f32) -> bool {
+        self.shape.occluded(&object_ray(&self.inverse, ray), t_min, t_max)
+    }
+}
+
+fn object_ray(inverse: &Affine3A, ray: &Ray3) -> Ray3 {
+    Ray3 {
+        pos: inverse.transform_point3(ray.pos),
+        dir: inverse.transform_vector3(ray.dir),
+        time: ray.time,
+    }
+}
+
+fn hit_transformed(shape: &Shape, transform: &Affine3A, inverse: &Affine3A, ray: &Ray3, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    shape.hit(&object_ray(inverse, ray), t_min, t_max).map(|mut r| {
+        r.point = transform.transform_point3(r.point);
+        // the inverse transpose keeps it perpendicular to the surface, the side it faces is
+        // kept too because `n' . d' = n . d`
+        r.nor = inverse.matrix3.transpose().mul_vec3(r.nor).normalize();
+        r
+    })
+}
+
+// where `time` is from `time0` to `time1`, held still outside
+fn keyframe_fraction(time: f32, time0: f32, time1: f32) -> f32 {
+    if time1 > time0 {
+        ((time - time0) / (time1 - time0)).clamp(0.0, 1.0)
+    } else {
+        0.0
+    }
+}
+
+/**
+ * a sphere moving from `center0` at `time0` to `center1` at `time1`
+ */
+#[derive(Copy, Clone, Debug)]
+pub struct MovingSphere {
+    pub center0: Vec3,
+    pub center1: Vec3,
+    pub time0: f32,
+    pub time1: f32,
+    pub radius: f32,
+}
+
+impl MovingSphere {
+    pub fn center(&self, time: f32) -> Vec3 {
+        self.center0.lerp(self.center1, keyframe_fraction(time, self.time0, self.time1))
+    }
+}
+
+impl Hittable for MovingSphere {
+    type T = HitRecord;
+
+    fn aabb(self: &Self) -> Aabb3 {
+        let sphere = |center| Sphere { center, radius: self.radius }.aabb();
+        sphere(self.center0).union(&sphere(self.center1))
+    }
+
+    fn hit(&self, ray: &Ray3, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        Sphere {
+            center: self.center(ray.time),
+            radius: self.radius,
+        }
+        .hit(ray, t_min, t_max)
+    }
+}
+
+// the bounds of the motion are of this many steps, padded by how far the rotation can swing between
+const MOTION_AABB_STEPS: usize = 16;
+
+/**
+ * a instance moving from `transform0` at `time0` to `transform1` at `time1`. the scale,
+ * rotation and translation are interpolated separately, so it rotates without shrinking
+ */
+#[derive(Clone, Debug)]
+pub struct MovingInstance {
+    pub shape: Arc<Shape>,
+    keyframes: [(Vec3, Quat, Vec3); 2],
+    time0: f32,
+    time1: f32,
+    aabb: Aabb3,
+}
+
+impl MovingInstance {
+    pub fn new(shape: Arc<Shape>, transform0: Affine3A, transform1: Affine3A, time0: f32, time1: f32) -> MovingInstance {
+        let mut keyframes = [transform0.to_scale_rotation_translation(), transform1.to_scale_rotation_translation()];
+        // the short way around
+        if keyframes[0].1.dot(keyframes[1].1) < 0.0 {
+            keyframes[1].1 = -keyframes[1].1;
+        }
+        let mut instance = MovingInstance {
+            shape,
+            keyframes,
+            time0,
+            time1,
+            aabb: Aabb3::empty(),
+        };
+        instance.update_aabb();
+        instance
+    }
+
+    pub fn transform(&self, time: f32) -> Affine3A {
+        let s = keyframe_fraction(time, self.time0, self.time1);
+        let [(scale0, rotation0, translation0), (scale1, rotation1, translation1)] = self.keyframes;
+        Affine3A::from_scale_rotation_translation(
+            scale0.lerp(scale1, s),
+            rotation0.slerp(rotation1, s),
+            translation0.lerp(translation1, s),
+        )
+    }
+
+    /**
+     * after `shape` changed. between two steps a point `p` of the scaled shape rotates by at most
+     * `angle`, which moves it at most `|p| * angle` away from where the steps put it
+     */
+    pub fn update_aabb(&mut self) {
+        let local = self.shape.aabb();
+        if local.min.cmpgt(local.max).any() {
+            self.aabb = Aabb3::empty();
+            return;
+        }
+        let mut radius: f32 = 0.0;
+        for i in 0..8 {
+            let corner = vec3(
+                if i & 1 == 0 { local.min.x } else { local.max.x },
+                if i & 2 == 0 { local.min.y } else { local.max.y },
+                if i & 4 == 0 { local.min.z } else { local.max.z },
+            );
+            for (scale, _, _) in self.keyframes {
+                radius = radius.max((corner * scale).length());
+            }
+        }
+        let angle = self.keyframes[0].1.angle_between(self.keyframes[1].1);
+        let pad = 2.0 * radius * angle / MOTION_AABB_STEPS as f32;
+        let mut aabb = Aabb3::empty();
+        for i in 0..=MOTION_AABB_STEPS {
+            let time = self.time0 + (self.time1 - self.time0) * i as f32 / MOTION_AABB_STEPS as f32;
+            aabb = aabb.union(&transform_aabb(&self.transform(time), &local));
+        }
+        self.aabb = Aabb3::new(aabb.min - pad, aabb.max + pad);
+    }
+}
+
+impl Hittable for MovingInstance {
+    type T = HitRecord;
+
+    fn aabb(self: &Self) -> Aabb3 {
+        self.aabb
+    }
+
+    fn hit(&self, ray: &Ray3, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let transform = self.transform(ray.time);
+        hit_transformed(&self.shape, &transform, &transform.inverse(), ray, t_min, t_max)
+    }
+
+    fn occluded(&self, ray: &Ray3, t_min: f32, t_max: f32) -> bool {
+        let inverse = self.transform(ray.time).inverse();
+        self.shape.occluded(&object_ray(&inverse, ray), t_min, t_max)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct RotateY {
-    pub shape: Box<Shape>,
-    pub theta: f32
+    pub theta: f32,
+    pub instance: Instance,
+}
+
+impl RotateY {
+    pub fn new(shape: Shape, theta: f32) -> RotateY {
+        RotateY {
+            theta,
+            instance: Instance::new(Arc::new(shape), Affine3A::from_rotation_y(theta)),
+        }
+    }
 }
 
 impl Hittable for RotateY {
     type T = HitRecord;
 
     fn aabb(self: &Self) -> Aabb3 {
-        todo!()
+        self.instance.aabb()
     }
 
     fn hit(&self, ray: &Ray3, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        todo!()
+        self.instance.hit(ray, t_min, t_max)
+    }
+}
+
+/**
+ * fog or smoke of constant `density` filling a convex `boundary`, scattered by the `phase` material.
+ *
+ * rays hit it at a randomly sampled free-flight distance, or pass through with the probability
+ * of the Beer-Lambert transmittance. the normal is meaningless. `hit_with` takes the random number
+ * from the caller, `hit` hashes it from the ray, so the same ray always gets the same distance.
+ * an integrator tracing the same ray again should pass its own random numbers to `hit_with`
+ */
+#[derive(Clone, Debug)]
+pub struct ConstantMedium {
+    pub boundary: Box<Shape>,
+    pub density: f32,
+    pub phase: Material,
+}
+
+fn ray_seed(ray: &Ray3) -> u32 {
+    let mut rng = SRng::new(0);
+    let mut seed = 0;
+    for f in [ray.pos.x, ray.pos.y, ray.pos.z, ray.dir.x, ray.dir.y, ray.dir.z, ray.time] {
+        seed = SRng::new(seed ^ f.to_bits() ^ rng.gen_u32()).gen_u32();
+    }
+    seed
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Box<Shape>, density: f32, phase: Material) -> Self {
+        ConstantMedium { boundary, density, phase }
+    }
+
+    /**
+     * `hit` with the random number `u` in `[0, 1)` deciding the distance
+     */
+    pub fn hit_with(&self, ray: &Ray3, t_min: f32, t_max: f32, u: f32) -> Option<HitRecord> {
+        // where the line enters and leaves, the ray can start inside
+        let enter = self.boundary.hit(ray, f32::NEG_INFINITY, f32::INFINITY)?;
+        let exit = self.boundary.hit(ray, enter.t + 0.0001, f32::INFINITY)?;
+        let t0 = enter.t.max(t_min).max(0.0);
+        let t1 = exit.t.min(t_max);
+        if t0 >= t1 {
+            return None;
+        }
+        let length = ray.dir.length();
+        let inside = (t1 - t0) * length;
+        // in `(0, 1]`
+        let u = 1.0 - u;
+        if u < Beer_Lambert(self.density, inside) {
+            return None;
+        }
+        let t = t0 + -u.ln() / self.density / length;
+        Some(HitRecord {
+            point: ray.at(t),
+            out: true,
+            nor: Vec3::X,
+            t,
+            uv: Vec2::ZERO,
+            barycentric: Vec3::ZERO,
+        })
+    }
+}
+
+impl Hittable for ConstantMedium {
+    type T = HitRecord;
+
+    fn aabb(self: &Self) -> Aabb3 {
+        self.boundary.aabb()
+    }
+
+    fn hit(&self, ray: &Ray3, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        self.hit_with(ray, t_min, t_max, SRng::new(ray_seed(ray)).gen())
     }
 }
 
@@ -150,7 +476,14 @@ pub enum Shape {
         shape: Box<Shape>,
         v: Vec3
     },
-    RotateY(RotateY)
+    RotateY(RotateY),
+    Instance(Instance),
+    MovingSphere(MovingSphere),
+    MovingInstance(MovingInstance),
+    ConstantMedium(ConstantMedium),
+    Triangle(Triangle),
+    // shared, so the same mesh can be placed many times
+    TriangleMesh(Arc<TriangleMesh>),
 }
 impl Hittable for Shape {
     type T = HitRecord;
@@ -159,6 +492,12 @@ impl Hittable for Shape {
             Shape::Sphere(s) => s.aabb(),
             Shape::Aabb3(s) => s.aabb(),
             Shape::RotateY(s) => s.aabb(),
+            Shape::Instance(s) => s.aabb(),
+            Shape::MovingSphere(s) => s.aabb(),
+            Shape::MovingInstance(s) => s.aabb(),
+            Shape::ConstantMedium(s) => s.aabb(),
+            Shape::Triangle(s) => s.aabb(),
+            Shape::TriangleMesh(s) => s.aabb(),
             Shape::Translate { shape, v } => shape.aabb() + *v
         }
     }
@@ -166,12 +505,253 @@ impl Hittable for Shape {
     fn hit(&self, ray: &Ray3, t_min: f32, t_max: f32) -> Option<HitRecord> {
         match self {
             Shape::Sphere(s) => s.hit(ray, t_min, t_max),
-            Shape::Aabb3(s) => s.hit(ray, t_min, t_max),
+            // `Aabb3` has a inherent `hit` for the shaders
+            Shape::Aabb3(s) => Hittable::hit(s, ray, t_min, t_max),
             Shape::RotateY(s) => s.hit(ray, t_min, t_max),
+            Shape::Instance(s) => s.hit(ray, t_min, t_max),
+            Shape::MovingSphere(s) => s.hit(ray, t_min, t_max),
+            Shape::MovingInstance(s) => s.hit(ray, t_min, t_max),
+            Shape::ConstantMedium(s) => s.hit(ray, t_min, t_max),
+            Shape::Triangle(s) => s.hit(ray, t_min, t_max),
+            Shape::TriangleMesh(s) => s.hit(ray, t_min, t_max),
             Shape::Translate { shape, v } => shape.hit(&(*ray + (-*v)), t_min, t_max).map(|mut r| {
                 r.point = r.point + *v;
                 r
             })
         }
     }
+
+    fn occluded(&self, ray: &Ray3, t_min: f32, t_max: f32) -> bool {
+        match self {
+            Shape::RotateY(s) => s.instance.occluded(ray, t_min, t_max),
+            Shape::Instance(s) => s.occluded(ray, t_min, t_max),
+            Shape::MovingInstance(s) => s.occluded(ray, t_min, t_max),
+            Shape::Triangle(s) => s.occluded(ray, t_min, t_max),
+            Shape::TriangleMesh(s) => s.occluded(ray, t_min, t_max),
+            Shape::Translate { shape, v } => shape.occluded(&(*ray + (-*v)), t_min, t_max),
+            _ => self.hit(ray, t_min, t_max).is_some(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::*;
+    use rand::{Rng, SeedableRng};
+
+    fn random_ray(rng: &mut impl Rng, target: Vec3) -> Ray3 {
+        let pos = (vec3(rng.gen(), rng.gen(), rng.gen()) * 2.0 - 1.0) * 10.0;
+        let dir = if rng.gen() {
+            target + vec3(rng.gen(), rng.gen(), rng.gen()) - 0.5 - pos
+        } else {
+            vec3(rng.gen(), rng.gen(), rng.gen()) * 2.0 - 1.0
+        };
+        Ray3 { pos, dir: dir.try_normalize_or(Vec3::X), time: 0.0 }
+    }
+
+    fn random_rotation(rng: &mut impl Rng) -> Quat {
+        let axis = (vec3(rng.gen(), rng.gen(), rng.gen()) * 2.0 - 1.0).try_normalize_or(Vec3::Y);
+        Quat::from_axis_angle(axis, rng.gen::<f32>() * 6.0)
+    }
+
+    #[test]
+    fn instance_same_as_transformed_sphere() {
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(0);
+        let unit = Arc::new(Shape::Sphere(Sphere { center: Vec3::ZERO, radius: 1.0 }));
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let scale = rng.gen::<f32>() * 3.0 + 0.1;
+            let center = (vec3(rng.gen(), rng.gen(), rng.gen()) * 2.0 - 1.0) * 5.0;
+            let transform = Affine3A::from_scale_rotation_translation(Vec3::splat(scale), random_rotation(&mut rng), center);
+            let instance = Instance::new(unit.clone(), transform);
+            let sphere = Sphere { center, radius: scale };
+            // the box of the rotated box, bigger than the one of the sphere
+            let aabb = instance.aabb();
+            assert!(aabb.min.cmple(sphere.aabb().min + 1e-4).all() && aabb.max.cmpge(sphere.aabb().max - 1e-4).all());
+            let ray = random_ray(&mut rng, center);
+            match (sphere.hit(&ray, 0.001, f32::INFINITY), instance.hit(&ray, 0.001, f32::INFINITY)) {
+                (None, None) => {}
+                (Some(e), Some(g)) => {
+                    assert!((e.t - g.t).abs() < 1e-3);
+                    assert!((e.point - g.point).abs().max_element() < 1e-3);
+                    assert!((e.nor - g.nor).abs().max_element() < 1e-3);
+                    assert_eq!(e.out, g.out);
+                    hits += 1;
+                }
+                // grazing rays can go either way
+                (e, g) => assert!(e.or(g).unwrap().nor.dot(ray.dir).abs() < 1e-2),
+            }
+        }
+        assert!(hits > 100);
+    }
+
+    #[test]
+    fn instance_normals_with_non_uniform_scale() {
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(1);
+        let unit = Arc::new(Shape::Sphere(Sphere { center: Vec3::ZERO, radius: 1.0 }));
+        for _ in 0..2000 {
+            let scale = vec3(rng.gen(), rng.gen(), rng.gen()) * 3.0 + 0.2;
+            let rotation = random_rotation(&mut rng);
+            let center = (vec3(rng.gen(), rng.gen(), rng.gen()) * 2.0 - 1.0) * 5.0;
+            let instance = Instance::new(unit.clone(), Affine3A::from_scale_rotation_translation(scale, rotation, center));
+            let ray = random_ray(&mut rng, center);
+            if let Some(hit) = instance.hit(&ray, 0.001, f32::INFINITY) {
+                // the gradient of `|S^-1 R^-1 (x - c)|^2`
+                let local = rotation.inverse() * (hit.point - center) / scale;
+                assert!((local.length() - 1.0).abs() < 1e-3);
+                let gradient = (rotation * (local / scale)).normalize();
+                let expected = if hit.out { gradient } else { -gradient };
+                assert!((hit.nor - expected).abs().max_element() < 1e-3);
+                assert!(hit.nor.dot(ray.dir) <= 0.0);
+                assert!(instance.aabb().contains(hit.point));
+            }
+        }
+    }
+
+    #[test]
+    fn constant_medium_transmittance() {
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(3);
+        let density = 0.7;
+        // a slab 2 thick
+        let medium = ConstantMedium::new(
+            Box::new(Shape::Aabb3(Aabb3::new(vec3(-100.0, -100.0, -1.0), vec3(100.0, 100.0, 1.0)))),
+            density,
+            Material::Isotropic(Isotropic { albedo: RgbLinear(Vec3::ONE) }),
+        );
+        let n = 20000;
+        let mut passed = 0;
+        let mut depth = 0.0;
+        for _ in 0..n {
+            let pos = vec3(rng.gen::<f32>() * 10.0, rng.gen::<f32>() * 10.0, 5.0);
+            // not normalized, `t` is not a distance
+            let ray = Ray3 { pos, dir: vec3(0.0, 0.0, -2.0), time: 0.0 };
+            match medium.hit(&ray, 0.001, f32::INFINITY) {
+                None => passed += 1,
+                Some(hit) => {
+                    assert!(hit.point.z <= 1.0 && hit.point.z >= -1.0);
+                    assert_eq!(medium.hit_with(&ray, 0.001, f32::INFINITY, 0.5).unwrap().t, medium.hit_with(&ray, 0.001, f32::INFINITY, 0.5).unwrap().t);
+                    depth += 1.0 - hit.point.z;
+                }
+            }
+        }
+        let transmittance = Beer_Lambert(density, 2.0);
+        assert!((passed as f32 / n as f32 - transmittance).abs() < 0.01);
+        // the mean of the exponential distribution cut at 2
+        let expected = 1.0 / density - 2.0 * transmittance / (1.0 - transmittance);
+        assert!((depth / (n - passed) as f32 - expected).abs() < 0.02);
+
+        // from inside, and limited by `t_max`
+        let ray = Ray3 { pos: Vec3::ZERO, dir: Vec3::Z, time: 0.0 };
+        let passed = (0..n)
+            .filter(|_| {
+                let ray = ray + vec3(rng.gen(), rng.gen(), 0.0);
+                medium.hit(&ray, 0.001, 0.5).is_none()
+            })
+            .count();
+        assert!((passed as f32 / n as f32 - Beer_Lambert(density, 0.5)).abs() < 0.01);
+
+        // the same ray always gets the same distance from `hit`, and converges with the random
+        // numbers given to `hit_with`, at any time
+        for time in [0.0, 0.25] {
+            let ray = Ray3 { pos: vec3(0.0, 0.0, 5.0), dir: -Vec3::Z, time };
+            let first = medium.hit(&ray, 0.001, f32::INFINITY).map(|hit| hit.t);
+            assert!((0..10).all(|_| medium.hit(&ray, 0.001, f32::INFINITY).map(|hit| hit.t) == first));
+            let passed = (0..n).filter(|_| medium.hit_with(&ray, 0.001, f32::INFINITY, rng.gen()).is_none()).count();
+            assert!((passed as f32 / n as f32 - transmittance).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn moving_sphere() {
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(4);
+        let sphere = MovingSphere {
+            center0: vec3(-2.0, 0.0, 0.0),
+            center1: vec3(2.0, 1.0, 0.0),
+            time0: 1.0,
+            time1: 2.0,
+            radius: 0.5,
+        };
+        assert_eq!(sphere.aabb().min, vec3(-2.5, -0.5, -0.5));
+        assert_eq!(sphere.aabb().max, vec3(2.5, 1.5, 0.5));
+        assert_eq!(sphere.center(0.0), sphere.center0);
+        assert_eq!(sphere.center(1.5), vec3(0.0, 0.5, 0.0));
+        for _ in 0..2000 {
+            let mut ray = random_ray(&mut rng, Vec3::ZERO);
+            ray.time = rng.gen::<f32>() * 3.0;
+            let expected = Sphere { center: sphere.center(ray.time), radius: 0.5 }.hit(&ray, 0.001, f32::INFINITY);
+            let got = sphere.hit(&ray, 0.001, f32::INFINITY);
+            assert_eq!(expected.map(|h| h.t), got.map(|h| h.t));
+            if let Some(hit) = got {
+                assert!(sphere.aabb().contains(hit.point));
+            }
+        }
+    }
+
+    #[test]
+    fn moving_instance_covers_motion() {
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(5);
+        let shape = Arc::new(Shape::Aabb3(Aabb3::new(vec3(-1.0, -0.5, 0.0), vec3(2.0, 0.5, 1.0))));
+        for _ in 0..200 {
+            let mut keyframe = || {
+                let scale = vec3(rng.gen(), rng.gen(), rng.gen()) * 2.0 + 0.2;
+                let translation = (vec3(rng.gen(), rng.gen(), rng.gen()) * 2.0 - 1.0) * 5.0;
+                Affine3A::from_scale_rotation_translation(scale, random_rotation(&mut rng), translation)
+            };
+            let (transform0, transform1) = (keyframe(), keyframe());
+            let instance = MovingInstance::new(shape.clone(), transform0, transform1, 0.0, 1.0);
+            let aabb = instance.aabb();
+            // the corners of the box along their paths
+            let local = shape.aabb();
+            for step in 0..=500 {
+                let transform = instance.transform(step as f32 / 500.0);
+                for i in 0..8 {
+                    let corner = vec3(
+                        if i & 1 == 0 { local.min.x } else { local.max.x },
+                        if i & 2 == 0 { local.min.y } else { local.max.y },
+                        if i & 4 == 0 { local.min.z } else { local.max.z },
+                    );
+                    assert!(aabb.contains(transform.transform_point3(corner)));
+                }
+            }
+            // the same as a still instance at the keyframes
+            for _ in 0..20 {
+                let mut ray = random_ray(&mut rng, aabb.center());
+                for (time, transform) in [(0.0, transform0), (-1.0, transform0), (1.0, transform1)] {
+                    ray.time = time;
+                    let expected = Instance::new(shape.clone(), transform).hit(&ray, 0.001, f32::INFINITY);
+                    let got = instance.hit(&ray, 0.001, f32::INFINITY);
+                    assert_eq!(expected.is_some(), got.is_some());
+                    if let (Some(e), Some(g)) = (expected, got) {
+                        assert!((e.t - g.t).abs() < 1e-3 && (e.nor - g.nor).abs().max_element() < 1e-3);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rotate_y_box() {
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(2);
+        let shape = Shape::Aabb3(Aabb3::new(Vec3::ZERO, vec3(1.0, 2.0, 3.0)));
+        // `x' = z, z' = -x`
+        let rotated = RotateY::new(shape.clone(), core::f32::consts::FRAC_PI_2);
+        let expected = Aabb3::new(vec3(0.0, 0.0, -1.0), vec3(3.0, 2.0, 0.0));
+        assert!((rotated.aabb().min - expected.min).abs().max_element() < 1e-6);
+        assert!((rotated.aabb().max - expected.max).abs().max_element() < 1e-6);
+        for _ in 0..1000 {
+            let ray = random_ray(&mut rng, expected.center());
+            let e = Hittable::hit(&expected, &ray, 0.001, f32::INFINITY);
+            let g = rotated.hit(&ray, 0.001, f32::INFINITY);
+            assert_eq!(e.is_some(), g.is_some());
+            if let (Some(e), Some(g)) = (e, g) {
+                assert!((e.t - g.t).abs() < 1e-4);
+                assert!((e.nor - g.nor).abs().max_element() < 1e-5);
+            }
+        }
+
+        let diagonal = RotateY::new(Shape::Aabb3(Aabb3::new(Vec3::splat(-0.5), Vec3::splat(0.5))), core::f32::consts::FRAC_PI_4);
+        let half = core::f32::consts::FRAC_1_SQRT_2;
+        assert!((diagonal.aabb().max - vec3(half, 0.5, half)).abs().max_element() < 1e-6);
+    }
 }
@@ -0,0 +1,342 @@
+use std::sync::Arc;
+
+use crate::math::*;
+
+use super::bvh::*;
+use super::bvh_node::*;
+use super::shape::*;
+use super::triangle::*;
+
+// a subtree of the top level is rebuilt when refitting made it this much worse than when it was built
+const REBUILD_RATIO: f32 = 1.5;
+
+/**
+ * a two level bvh. the top level is a bvh over the instances, the bottom levels are the bvhs of
+ * the instanced shapes, so a mesh placed many times is only stored once.
+ *
+ * for animation, move the instances with `set_transform` or change their shapes then call
+ * `update`, which refits the top level and only rebuilds the subtrees that got too bad
+ */
+#[derive(Clone, Debug)]
+pub struct Tlas {
+    pub bvh: Bvh3<Instance>,
+    // the position in `bvh.items` of the instance given to `new` at that index
+    positions: Vec<u32>,
+    // `subtree_costs` of the top level when its nodes were built
+    built_costs: Vec<f32>,
+}
+
+/**
+ * a `Tlas` of triangle meshes flattened for storage buffers, see `tlas_closest_hit`. the top level
+ * is at the start of `nodes`, then the bottom levels with their `first` offset into `nodes` or
+ * `triangles`
+ */
+#[derive(Clone, Debug, Default)]
+pub struct TlasBuffers {
+    pub nodes: Vec<BvhNode>,
+    pub instances: Vec<BvhInstance>,
+    pub triangles: Vec<BvhTriangle>,
+}
+
+impl Tlas {
+    pub fn new(instances: Vec<Instance>) -> Tlas {
+        let mut tlas = Tlas {
+            bvh: Bvh3::new(instances),
+            positions: vec![],
+            built_costs: vec![],
+        };
+        tlas.built();
+        tlas
+    }
+
+    // after the top level was built again, the instances moved in it
+    fn built(&mut self) {
+        self.positions = vec![0; self.bvh.indices.len()];
+        for (position, index) in self.bvh.indices.iter().enumerate() {
+            self.positions[*index as usize] = position as u32;
+        }
+        self.built_costs = self.bvh.subtree_costs();
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    pub fn instance(&self, index: usize) -> &Instance {
+        &self.bvh.items[self.positions[index] as usize]
+    }
+
+    /// call `update` after changing it
+    pub fn instance_mut(&mut self, index: usize) -> &mut Instance {
+        &mut self.bvh.items[self.positions[index] as usize]
+    }
+
+    /// call `update` after moving the instances
+    pub fn set_transform(&mut self, index: usize, transform: Affine3A) {
+        self.instance_mut(index).set_transform(transform);
+    }
+
+    /**
+     * refit after the instances or their shapes changed, returns if any of the top level was
+     * rebuilt. only the highest subtrees that got too bad are rebuilt, the rest is kept
+     */
+    pub fn update(&mut self) -> bool {
+        for instance in &mut self.bvh.items {
+            instance.update_aabb();
+        }
+        self.bvh.refit();
+        let costs = self.bvh.subtree_costs();
+        let built_costs = &self.built_costs;
+        let degraded = |n: usize| costs[n] > built_costs[n] * REBUILD_RATIO;
+        if !(0..costs.len()).any(degraded) {
+            return false;
+        }
+        self.bvh.rebuild_subtrees(degraded);
+        self.built();
+        true
+    }
+
+    pub fn rebuild(&mut self) {
+        self.bvh.rebuild_subtrees(|_| true);
+        self.built();
+    }
+
+    /**
+     * the closest hit and the index of the instance given to `new`
+     */
+    pub fn hit_instance(&self, ray: &Ray3, t_min: f32, t_max: f32) -> Option<(HitRecord, usize)> {
+        self.bvh.hit_index(ray, t_min, t_max).map(|(hit, index)| (hit, index as usize))
+    }
+
+    /**
+     * `None` if any of the instanced shapes is not a `Shape::TriangleMesh`. meshes shared by `Arc`
+     * are uploaded once
+     */
+    pub fn to_buffers(&self) -> Option<TlasBuffers> {
+        let mut buffers = TlasBuffers {
+            nodes: self.bvh.nodes.clone(),
+            ..Default::default()
+        };
+        let mut meshes: Vec<(*const TriangleMesh, u32)> = vec![];
+        for (instance, index) in self.bvh.items.iter().zip(&self.bvh.indices) {
+            let mesh = match instance.shape.as_ref() {
+                Shape::TriangleMesh(mesh) => mesh,
+                _ => return None,
+            };
+            let root = match meshes.iter().find(|(m, _)| *m == Arc::as_ptr(mesh)) {
+                Some((_, root)) => *root,
+                None => {
+                    let root = buffers.nodes.len() as u32;
+                    let first_triangle = buffers.triangles.len() as u32;
+                    buffers.nodes.extend(mesh.bvh.nodes.iter().map(|node| BvhNode {
+                        first: node.first + if node.is_leaf() { first_triangle } else { root },
+                        ..*node
+                    }));
+                    buffers
+                        .triangles
+                        .extend(mesh.bvh.items.iter().zip(&mesh.bvh.indices).map(|(t, i)| BvhTriangle {
+                            a: t.positions[0],
+                            index: *i,
+                            b: t.positions[1],
+                            c: t.positions[2],
+                            ..Default::default()
+                        }));
+                    meshes.push((Arc::as_ptr(mesh), root));
+                    root
+                }
+            };
+            let transform = Mat4::from(instance.transform());
+            buffers.instances.push(BvhInstance {
+                world_to_object: transform.inverse(),
+                object_to_world: transform,
+                root,
+                index: *index,
+                not_used: UVec2::ZERO,
+            });
+        }
+        Some(buffers)
+    }
+}
+
+impl Hittable for Tlas {
+    type T = HitRecord;
+
+    fn aabb(self: &Self) -> Aabb3 {
+        self.bvh.aabb()
+    }
+
+    fn hit(&self, ray: &Ray3, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        Hittable::hit(&self.bvh, ray, t_min, t_max)
+    }
+
+    fn occluded(&self, ray: &Ray3, t_min: f32, t_max: f32) -> bool {
+        self.bvh.occluded(ray, t_min, t_max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+
+    fn random_point(rng: &mut impl Rng) -> Vec3 {
+        vec3(rng.gen(), rng.gen(), rng.gen()) * 2.0 - 1.0
+    }
+
+    // a triangle soup in the unit cube
+    fn random_mesh(rng: &mut impl Rng, count: u32) -> Arc<TriangleMesh> {
+        let positions = (0..count * 3).map(|_| random_point(rng)).collect();
+        let indices = (0..count).map(|i| [i * 3, i * 3 + 1, i * 3 + 2]).collect();
+        Arc::new(TriangleMesh::new(positions, vec![], vec![], indices))
+    }
+
+    fn random_transform(rng: &mut impl Rng, spread: f32) -> Affine3A {
+        let rotation = Quat::from_axis_angle(random_point(rng).try_normalize_or(Vec3::Y), rng.gen::<f32>() * 6.0);
+        let scale = random_point(rng).abs() + 0.5;
+        Affine3A::from_scale_rotation_translation(scale, rotation, random_point(rng) * spread)
+    }
+
+    fn random_ray(rng: &mut impl Rng, spread: f32) -> Ray3 {
+        Ray3 {
+            pos: random_point(rng) * spread * 1.5,
+            dir: random_point(rng).try_normalize_or(Vec3::X),
+            time: 0.0,
+        }
+    }
+
+    fn brute_force(tlas: &Tlas, ray: &Ray3) -> Option<(HitRecord, usize)> {
+        let mut closest = None;
+        let mut t_max = f32::INFINITY;
+        for i in 0..tlas.len() {
+            if let Some(hit) = tlas.instance(i).hit(ray, 0.001, t_max) {
+                t_max = hit.t;
+                closest = Some((hit, i));
+            }
+        }
+        closest
+    }
+
+    fn random_tlas(rng: &mut impl Rng, spread: f32) -> Tlas {
+        let meshes: Vec<Arc<Shape>> = (0..3).map(|_| Arc::new(Shape::TriangleMesh(random_mesh(rng, 20)))).collect();
+        let instances = (0..200)
+            .map(|i| Instance::new(meshes[i % meshes.len()].clone(), random_transform(rng, spread)))
+            .collect();
+        Tlas::new(instances)
+    }
+
+    fn check_hits(rng: &mut impl Rng, tlas: &Tlas, spread: f32) {
+        let buffers = tlas.to_buffers().unwrap();
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let ray = random_ray(rng, spread);
+            let expected = brute_force(tlas, &ray);
+            let got = tlas.hit_instance(&ray, 0.001, f32::INFINITY);
+            assert_eq!(expected.map(|(h, i)| (h.t, i)), got.map(|(h, i)| (h.t, i)));
+            // the flattened buffers
+            let mut instance = None;
+            let t = tlas_closest_hit(&buffers.nodes, &buffers.instances, &ray, 0.001, f32::INFINITY, |i, t, ray, t_max| {
+                let t = buffers.triangles[t as usize];
+                match Triangle::new(t.a, t.b, t.c).intersect(ray, 0.001, t_max) {
+                    Some((t, _)) => {
+                        instance = Some(buffers.instances[i as usize].index as usize);
+                        t
+                    }
+                    None => t_max,
+                }
+            });
+            assert_eq!(tlas.occluded(&ray, 0.001, f32::INFINITY), expected.is_some());
+            let any = tlas_any_hit(&buffers.nodes, &buffers.instances, &ray, 0.001, f32::INFINITY, |_, t, ray| {
+                let t = buffers.triangles[t as usize];
+                Triangle::new(t.a, t.b, t.c).intersect(ray, 0.001, f32::INFINITY).is_some()
+            });
+            assert_eq!(any, t != f32::INFINITY);
+            match expected {
+                Some((hit, i)) => {
+                    // the matrices are not exactly the ones of the affine transforms
+                    assert!((t - hit.t).abs() < 1e-3 * hit.t.max(1.0));
+                    if (t - hit.t).abs() < 1e-6 {
+                        assert_eq!(instance, Some(i));
+                    }
+                    hits += 1;
+                }
+                None => assert!(t == f32::INFINITY || instance.is_some()),
+            }
+        }
+        assert!(hits > 100);
+    }
+
+    #[test]
+    fn tlas_same_as_brute_force() {
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(0);
+        let tlas = random_tlas(&mut rng, 20.0);
+        let buffers = tlas.to_buffers().unwrap();
+        // 3 meshes of 20 triangles, shared by 200 instances
+        assert_eq!(buffers.instances.len(), 200);
+        assert_eq!(buffers.triangles.len(), 60);
+        check_hits(&mut rng, &tlas, 20.0);
+        // only meshes can be flattened
+        let sphere = Arc::new(Shape::Sphere(Sphere { center: Vec3::ZERO, radius: 1.0 }));
+        let mut instances = vec![tlas.instance(0).clone()];
+        instances.push(Instance::new(sphere, Affine3A::IDENTITY));
+        assert!(Tlas::new(instances).to_buffers().is_none());
+        assert!(Tlas::new(vec![]).is_empty());
+    }
+
+    #[test]
+    fn tlas_update() {
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(1);
+        let mut tlas = random_tlas(&mut rng, 20.0);
+        // small moves only refit
+        for i in 0..tlas.len() {
+            let transform = tlas.instance(i).transform();
+            tlas.set_transform(i, Affine3A::from_translation(random_point(&mut rng) * 0.1) * transform);
+        }
+        assert!(!tlas.update());
+        check_hits(&mut rng, &tlas, 20.0);
+        // scattering everything makes it rebuild
+        for i in 0..tlas.len() {
+            tlas.set_transform(i, random_transform(&mut rng, 20.0));
+        }
+        assert!(tlas.update());
+        check_hits(&mut rng, &tlas, 20.0);
+        // throwing a few far away rebuilds around them, then it is good again
+        for i in [3, 77] {
+            let transform = tlas.instance(i).transform();
+            tlas.set_transform(i, Affine3A::from_translation(random_point(&mut rng) * 15.0) * transform);
+        }
+        assert!(tlas.update());
+        assert!(!tlas.update());
+        check_hits(&mut rng, &tlas, 20.0);
+
+        // deform a mesh, not shared so we can change it in place
+        let mut instance = tlas.instance(0).clone();
+        instance.shape = Arc::new(Shape::TriangleMesh(random_mesh(&mut rng, 50)));
+        *tlas.instance_mut(0) = instance;
+        if let Shape::TriangleMesh(mesh) = Arc::get_mut(&mut tlas.instance_mut(0).shape).unwrap() {
+            let mesh = Arc::get_mut(mesh).unwrap();
+            let positions = mesh.positions.iter().map(|p| *p * 3.0 + 0.5).collect();
+            mesh.update_vertices(positions, vec![]);
+        }
+        tlas.update();
+        check_hits(&mut rng, &tlas, 20.0);
+    }
+
+    #[test]
+    fn mesh_update_vertices() {
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(2);
+        let mut mesh = (*random_mesh(&mut rng, 200)).clone();
+        let positions: Vec<Vec3> = mesh.positions.iter().map(|p| *p + random_point(&mut rng) * 0.3).collect();
+        let expected = TriangleMesh::new(positions.clone(), vec![], vec![], mesh.indices.clone());
+        mesh.update_vertices(positions, vec![]);
+        for _ in 0..2000 {
+            let ray = random_ray(&mut rng, 1.0);
+            let e = expected.hit(&ray, 0.001, f32::INFINITY);
+            let g = mesh.hit(&ray, 0.001, f32::INFINITY);
+            assert_eq!(e.map(|h| (h.t, h.nor)), g.map(|h| (h.t, h.nor)));
+        }
+    }
+}
@@ -0,0 +1,324 @@
+use crate::math::*;
+
+use super::bvh::*;
+use super::shape::*;
+
+/**
+ * counter clockwise is the front face. `normals` are per vertex, use `Triangle::new` for flat shading
+ */
+#[derive(Copy, Clone, Debug)]
+pub struct Triangle {
+    pub positions: [Vec3; 3],
+    pub normals: [Vec3; 3],
+    pub uvs: [Vec2; 3],
+}
+
+impl Triangle {
+    pub fn new(a: Vec3, b: Vec3, c: Vec3) -> Triangle {
+        let n = Self::face_normal_of(a, b, c);
+        Triangle {
+            positions: [a, b, c],
+            normals: [n; 3],
+            uvs: [Vec2::ZERO, Vec2::X, Vec2::Y],
+        }
+    }
+
+    fn face_normal_of(a: Vec3, b: Vec3, c: Vec3) -> Vec3 {
+        (b - a).cross(c - a).normalize_or_zero()
+    }
+
+    pub fn face_normal(&self) -> Vec3 {
+        let [a, b, c] = self.positions;
+        Self::face_normal_of(a, b, c)
+    }
+
+    /**
+     * "Watertight Ray/Triangle Intersection", Woop, Benthin & Wald. rays through a shared edge or
+     * vertex hit at least one of the triangles. two sided. returns `t` and the barycentrics
+     */
+    pub fn intersect(&self, ray: &Ray3, t_min: f32, t_max: f32) -> Option<(f32, Vec3)> {
+        let dir = ray.dir;
+        let abs = dir.abs();
+        // the largest dimension is z, keep the winding
+        let kz = if abs.x > abs.y && abs.x > abs.z {
+            0
+        } else if abs.y > abs.z {
+            1
+        } else {
+            2
+        };
+        let mut kx = (kz + 1) % 3;
+        let mut ky = (kx + 1) % 3;
+        if dir[kz] < 0.0 {
+            core::mem::swap(&mut kx, &mut ky);
+        }
+        let sx = dir[kx] / dir[kz];
+        let sy = dir[ky] / dir[kz];
+        let sz = 1.0 / dir[kz];
+
+        let a = self.positions[0] - ray.pos;
+        let b = self.positions[1] - ray.pos;
+        let c = self.positions[2] - ray.pos;
+        let ax = a[kx] - sx * a[kz];
+        let ay = a[ky] - sy * a[kz];
+        let bx = b[kx] - sx * b[kz];
+        let by = b[ky] - sy * b[kz];
+        let cx = c[kx] - sx * c[kz];
+        let cy = c[ky] - sy * c[kz];
+
+        let mut u = cx * by - cy * bx;
+        let mut v = ax * cy - ay * cx;
+        let mut w = bx * ay - by * ax;
+        // on a edge, redo it in double so the neighbour agrees
+        if u == 0.0 || v == 0.0 || w == 0.0 {
+            u = (cx as f64 * by as f64 - cy as f64 * bx as f64) as f32;
+            v = (ax as f64 * cy as f64 - ay as f64 * cx as f64) as f32;
+            w = (bx as f64 * ay as f64 - by as f64 * ax as f64) as f32;
+        }
+        if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+            return None;
+        }
+        let det = u + v + w;
+        if det == 0.0 {
+            return None;
+        }
+        let az = sz * a[kz];
+        let bz = sz * b[kz];
+        let cz = sz * c[kz];
+        let t = (u * az + v * bz + w * cz) / det;
+        if t > t_min && t < t_max {
+            Some((t, vec3(u, v, w) / det))
+        } else {
+            None
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    type T = HitRecord;
+
+    fn aabb(self: &Self) -> Aabb3 {
+        let [a, b, c] = self.positions;
+        Aabb3::new(a.min(b).min(c), a.max(b).max(c))
+    }
+
+    fn hit(&self, ray: &Ray3, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        self.intersect(ray, t_min, t_max).map(|(t, w)| {
+            let out = self.face_normal().dot(ray.dir) <= 0.0;
+            let normal = (self.normals[0] * w.x + self.normals[1] * w.y + self.normals[2] * w.z)
+                .try_normalize_or(self.face_normal());
+            HitRecord {
+                point: ray.at(t),
+                out,
+                nor: if out { normal } else { -normal },
+                t,
+                uv: self.uvs[0] * w.x + self.uvs[1] * w.y + self.uvs[2] * w.z,
+                barycentric: w,
+            }
+        })
+    }
+
+    fn occluded(&self, ray: &Ray3, t_min: f32, t_max: f32) -> bool {
+        self.intersect(ray, t_min, t_max).is_some()
+    }
+}
+
+/**
+ * indexed triangles with their own bvh. `normals` and `uvs` are per vertex and can be empty,
+ * then it is flat shaded and uvs are zero
+ */
+#[derive(Clone, Debug)]
+pub struct TriangleMesh {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<Vec2>,
+    pub indices: Vec<[u32; 3]>,
+    pub bvh: Bvh3<Triangle>,
+}
+
+impl TriangleMesh {
+    pub fn new(positions: Vec<Vec3>, normals: Vec<Vec3>, uvs: Vec<Vec2>, indices: Vec<[u32; 3]>) -> TriangleMesh {
+        let triangles = indices
+            .iter()
+            .map(|i| {
+                let i = i.map(|i| i as usize);
+                let mut triangle = Triangle::new(positions[i[0]], positions[i[1]], positions[i[2]]);
+                if !normals.is_empty() {
+                    triangle.normals = i.map(|i| normals[i]);
+                }
+                triangle.uvs = if uvs.is_empty() { [Vec2::ZERO; 3] } else { i.map(|i| uvs[i]) };
+                triangle
+            })
+            .collect();
+        TriangleMesh {
+            positions,
+            normals,
+            uvs,
+            indices,
+            bvh: Bvh3::new(triangles),
+        }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len()
+    }
+
+    /**
+     * move the vertices of a deforming mesh, the bvh is refitted instead of rebuilt. `normals` can
+     * be empty like in `new`
+     */
+    pub fn update_vertices(&mut self, positions: Vec<Vec3>, normals: Vec<Vec3>) {
+        assert_eq!(positions.len(), self.positions.len());
+        self.positions = positions;
+        self.normals = normals;
+        for (triangle, index) in self.bvh.items.iter_mut().zip(&self.bvh.indices) {
+            let i = self.indices[*index as usize].map(|i| i as usize);
+            let uvs = triangle.uvs;
+            *triangle = Triangle::new(self.positions[i[0]], self.positions[i[1]], self.positions[i[2]]);
+            if !self.normals.is_empty() {
+                triangle.normals = i.map(|i| self.normals[i]);
+            }
+            triangle.uvs = uvs;
+        }
+        self.bvh.refit();
+    }
+}
+
+impl Hittable for TriangleMesh {
+    type T = HitRecord;
+
+    fn aabb(self: &Self) -> Aabb3 {
+        self.bvh.aabb()
+    }
+
+    fn hit(&self, ray: &Ray3, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        Hittable::hit(&self.bvh, ray, t_min, t_max)
+    }
+
+    fn occluded(&self, ray: &Ray3, t_min: f32, t_max: f32) -> bool {
+        self.bvh.occluded(ray, t_min, t_max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::util::barycentric;
+    use rand::{Rng, SeedableRng};
+
+    // a unit sphere made of `2 * n * n` quads, the poles are fans so we have a lot of shared vertices
+    fn uv_sphere(n: u32) -> TriangleMesh {
+        let mut positions = vec![];
+        let mut uvs = vec![];
+        for j in 0..=n {
+            for i in 0..=2 * n {
+                let uv = vec2(i as f32 / (2 * n) as f32, j as f32 / n as f32);
+                // shared vertices need to be exactly the same
+                let phi = (i % (2 * n)) as f32 / n as f32 * core::f32::consts::PI;
+                let theta = uv.y * core::f32::consts::PI;
+                let r = if j == 0 || j == n { 0.0 } else { theta.sin() };
+                let y = if j == 0 { -1.0 } else if j == n { 1.0 } else { -theta.cos() };
+                positions.push(vec3(r * phi.cos(), y, r * phi.sin()));
+                uvs.push(uv);
+            }
+        }
+        let row = 2 * n + 1;
+        let mut indices = vec![];
+        for j in 0..n {
+            for i in 0..2 * n {
+                let a = j * row + i;
+                let (b, c, d) = (a + 1, a + row, a + row + 1);
+                if j != 0 {
+                    indices.push([a, c, b]);
+                }
+                if j != n - 1 {
+                    indices.push([b, c, d]);
+                }
+            }
+        }
+        let normals = positions.clone();
+        TriangleMesh::new(positions, normals, uvs, indices)
+    }
+
+    #[test]
+    fn triangle_hit() {
+        let triangle = Triangle {
+            positions: [vec3(0.0, 0.0, 0.0), vec3(4.0, 0.0, 0.0), vec3(0.0, 4.0, 0.0)],
+            normals: [Vec3::Z, Vec3::Z, vec3(0.0, 1.0, 1.0).normalize()],
+            uvs: [vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(0.0, 1.0)],
+        };
+        let ray = Ray3 {
+            pos: vec3(1.0, 2.0, 5.0),
+            dir: -Vec3::Z,
+            time: 0.0,
+        };
+        let hit = triangle.hit(&ray, 0.0, f32::INFINITY).unwrap();
+        assert_eq!(hit.t, 5.0);
+        assert!(hit.out);
+        assert!((hit.barycentric - vec3(0.25, 0.25, 0.5)).abs().max_element() < 1e-6);
+        let expected = barycentric(vec2(0.0, 0.0), vec2(4.0, 0.0), vec2(0.0, 4.0), vec2(1.0, 2.0));
+        assert!((hit.barycentric - expected).abs().max_element() < 1e-6);
+        assert!((hit.uv - vec2(0.25, 0.5)).abs().max_element() < 1e-6);
+        let normal = (Vec3::Z * 0.5 + vec3(0.0, 1.0, 1.0).normalize() * 0.5).normalize();
+        assert!((hit.nor - normal).abs().max_element() < 1e-6);
+
+        // from the back
+        let hit = triangle.hit(&Ray3 { pos: vec3(1.0, 2.0, -5.0), dir: Vec3::Z, time: 0.0 }, 0.0, f32::INFINITY).unwrap();
+        assert!(!hit.out);
+        assert!(hit.nor.z < 0.0);
+
+        assert!(triangle.hit(&ray, 0.0, 5.0).is_none());
+        assert!(triangle.hit(&Ray3 { pos: vec3(3.0, 3.0, 5.0), dir: -Vec3::Z, time: 0.0 }, 0.0, f32::INFINITY).is_none());
+        assert!(triangle.hit(&Ray3 { pos: vec3(1.0, 2.0, 5.0), dir: Vec3::X, time: 0.0 }, 0.0, f32::INFINITY).is_none());
+    }
+
+    #[test]
+    fn triangle_mesh_is_watertight() {
+        let mesh = uv_sphere(16);
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(0);
+        for _ in 0..20000 {
+            // from somewhere inside, including through the vertices and edges at the center
+            let pos = if rng.gen::<f32>() < 0.2 {
+                Vec3::ZERO
+            } else {
+                (vec3(rng.gen(), rng.gen(), rng.gen()) * 2.0 - 1.0) * 0.5
+            };
+            let dir = if rng.gen::<f32>() < 0.2 {
+                mesh.positions[rng.gen_range(0..mesh.positions.len())] - pos
+            } else {
+                vec3(rng.gen(), rng.gen(), rng.gen()) * 2.0 - 1.0
+            }
+            .try_normalize_or(Vec3::X);
+            let hit = mesh.hit(&Ray3 { pos, dir, time: 0.0 }, 0.0, f32::INFINITY);
+            let hit = hit.unwrap_or_else(|| panic!("leaked at {} {}", pos, dir));
+            assert!(!hit.out);
+            assert!(hit.nor.dot(dir) <= 0.0);
+        }
+    }
+
+    #[test]
+    fn triangle_mesh_same_as_brute_force() {
+        let mesh = uv_sphere(8);
+        let triangles: Vec<Triangle> = mesh.bvh.items.clone();
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(1);
+        for _ in 0..5000 {
+            let pos = (vec3(rng.gen(), rng.gen(), rng.gen()) * 2.0 - 1.0) * 3.0;
+            let dir = (vec3(rng.gen(), rng.gen(), rng.gen()) * 2.0 - 1.0).try_normalize_or(Vec3::X);
+            let ray = Ray3 { pos, dir, time: 0.0 };
+            let mut expected: Option<HitRecord> = None;
+            for triangle in &triangles {
+                if let Some(hit) = triangle.hit(&ray, 0.0, expected.map_or(f32::INFINITY, |h| h.t)) {
+                    expected = Some(hit);
+                }
+            }
+            let got = mesh.hit(&ray, 0.0, f32::INFINITY);
+            assert_eq!(expected.map(|h| h.t), got.map(|h| h.t));
+            assert_eq!(mesh.occluded(&ray, 0.0, f32::INFINITY), got.is_some());
+            if let Some(hit) = got {
+                // the mesh approximates the unit sphere
+                assert!((hit.point.length() - 1.0).abs() < 0.1);
+                assert!(hit.nor.dot(hit.point.normalize() * if hit.out { 1.0 } else { -1.0 }) > 0.9);
+            }
+        }
+    }
+}
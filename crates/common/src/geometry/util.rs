@@ -1,3 +1,5 @@
+use crate::math::*;
+
 
 pub fn barycentric(a: Vec2, b: Vec2, c: Vec2, p: Vec2) -> Vec3 {
     let v0 = Vec3::new(c.x - a.x, b.x - a.x, a.x - p.x);
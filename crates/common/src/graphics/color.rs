@@ -49,6 +49,13 @@ impl RgbLinear {
     pub fn with_a(self: Self, a: f32) -> RgbaLinear {
         RgbaLinear(Vec4::new(self.0.x, self.0.y, self.0.z, a))
     }
+
+    /// Rec. 709 weights
+    #[inline]
+    pub fn luminance(self) -> f32 {
+        self.0.dot(vec3(0.2126, 0.7152, 0.0722))
+    }
+
     #[inline]
     pub fn to_rgb(self: Self) -> Rgb {
         Rgb(vec3(
@@ -0,0 +1,233 @@
+use crate::geometry::bvh::*;
+use crate::geometry::light::*;
+use crate::geometry::shape::*;
+use crate::math::*;
+
+use super::color::*;
+use super::material::*;
+use super::texture::*;
+
+// rays start this far from what they left, on top of `offset_ray_origin`
+const T_MIN: f32 = 1e-4;
+
+/**
+ * shapes with their materials, which take textures of type `T`. the `DiffuseLight`s with a
+ * constant emission on shapes that `Light` can sample are also in `lights`
+ */
+#[derive(Clone, Debug)]
+pub struct Scene<T = RgbLinear> {
+    pub bvh: Bvh3<Shape>,
+    /// by the index of the shape given to `new`
+    pub materials: Vec<Material<T>>,
+    pub lights: LightList,
+    // the index in `lights` of each shape
+    light_of: Vec<Option<usize>>,
+    /// the radiance of rays that hit nothing
+    pub background: RgbLinear,
+}
+
+impl<T: AbstractTexture + Copy> Scene<T> {
+    pub fn new(objects: Vec<(Shape, Material<T>)>, background: RgbLinear) -> Scene<T> {
+        let mut lights = Vec::new();
+        let light_of = objects
+            .iter()
+            .map(|(shape, material)| match material {
+                Material::DiffuseLight(l) => Light::new(shape.clone(), l.emit.constant()?).map(|light| {
+                    lights.push(light);
+                    lights.len() - 1
+                }),
+                _ => None,
+            })
+            .collect();
+        let (shapes, materials) = objects.into_iter().unzip();
+        Scene {
+            bvh: Bvh3::new(shapes),
+            materials,
+            lights: LightList::new(lights),
+            light_of,
+            background,
+        }
+    }
+}
+
+fn material_hit(hit: &HitRecord) -> HitRecord3 {
+    HitRecord3 {
+        is_hit: true,
+        t: hit.t,
+        from_outside: hit.out,
+        nor: hit.nor,
+        point: hit.point,
+        uv: hit.uv,
+    }
+}
+
+// the power heuristic, "Optimally Combining Sampling Techniques for Monte Carlo Rendering", Veach
+fn mis_weight(pdf: f32, other_pdf: f32) -> f32 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
+    }
+}
+
+/**
+ * a unidirectional path tracer. with `mis` it also samples a light at every bounce that isn't a
+ * delta, and combines that with the lights `scatter` runs into by multiple importance sampling.
+ * without it, only `scatter` finds the lights
+ */
+#[derive(Copy, Clone, Debug)]
+pub struct PathIntegrator {
+    pub max_depth: u32,
+    pub mis: bool,
+}
+
+impl Default for PathIntegrator {
+    fn default() -> Self {
+        PathIntegrator {
+            max_depth: 16,
+            mis: true,
+        }
+    }
+}
+
+impl PathIntegrator {
+    pub fn radiance<T: AbstractTexture + Copy>(&self, scene: &Scene<T>, ray: Ray3, rng: &mut SRng) -> RgbLinear {
+        let mut ray = ray;
+        let mut radiance = Vec3::ZERO;
+        let mut throughput = Vec3::ONE;
+        // the pdf of `scatter` giving `ray`, zero for camera rays and deltas
+        let mut scatter_pdf = 0.0;
+        for depth in 0..self.max_depth {
+            let (hit, index) = match scene.bvh.hit_index(&ray, T_MIN, f32::INFINITY) {
+                Some(hit) => hit,
+                None => {
+                    radiance += throughput * scene.background.0;
+                    break;
+                }
+            };
+            let material = scene.materials[index as usize];
+            let material_hit = material_hit(&hit);
+            let interaction = material.scatter(rng, ray, material_hit);
+            if interaction.emission.0 != Vec3::ZERO {
+                let weight = match scene.light_of[index as usize] {
+                    Some(light) if self.mis && scatter_pdf > 0.0 => {
+                        let light_pdf = area_to_solid_angle_pdf(scene.lights.pdf(light), ray.pos, hit.point, hit.nor);
+                        mis_weight(scatter_pdf, light_pdf)
+                    }
+                    _ => 1.0,
+                };
+                radiance += throughput * interaction.emission.0 * weight;
+            }
+            if !interaction.is_scattered {
+                break;
+            }
+            let wo = -ray.dir.normalize();
+            scatter_pdf = material.pdf(wo, interaction.ray.dir, &material_hit);
+            if self.mis && scatter_pdf > 0.0 {
+                radiance += throughput * self.sample_light(scene, material, wo, &hit, ray.time, rng);
+            }
+            throughput *= interaction.attenuation.0;
+            // russian roulette
+            if depth >= 3 {
+                let survive = throughput.max_element().min(0.95);
+                if rng.gen() >= survive {
+                    break;
+                }
+                throughput /= survive;
+            }
+            ray = interaction.ray;
+        }
+        RgbLinear(radiance)
+    }
+
+    // the light reaching `hit` from a point sampled on the lights, weighted against `scatter`
+    fn sample_light<T: AbstractTexture + Copy>(&self, scene: &Scene<T>, material: Material<T>, wo: Vec3, hit: &HitRecord, time: f32, rng: &mut SRng) -> Vec3 {
+        let sample = match scene.lights.sample(rng.gen(), rng.gen_vec2()) {
+            Some(sample) => sample,
+            None => return Vec3::ZERO,
+        };
+        let light_pdf = sample.solid_angle_pdf(hit.point);
+        if light_pdf <= 0.0 {
+            return Vec3::ZERO;
+        }
+        let wi = (sample.point - hit.point).normalize();
+        let material_hit = material_hit(hit);
+        let f = material.eval(wo, wi, &material_hit).0;
+        if f == Vec3::ZERO {
+            return Vec3::ZERO;
+        }
+        let pos = offset_ray_origin(hit.point, if wi.dot(hit.nor) > 0.0 { hit.nor } else { -hit.nor });
+        let to_light = sample.point - pos;
+        let distance = to_light.length();
+        let shadow = Ray3 {
+            pos,
+            dir: to_light / distance,
+            time,
+        };
+        if scene.bvh.occluded(&shadow, 0.0, distance * (1.0 - 1e-3)) {
+            return Vec3::ZERO;
+        }
+        let weight = mis_weight(light_pdf, material.pdf(wo, wi, &material_hit));
+        f * sample.emission.0 * (weight / light_pdf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::triangle::*;
+
+    // a ground at y = 0 and a sphere light above the origin
+    fn light_over_ground(ground: Material) -> Scene {
+        let s = 100.0;
+        let (a, b, c, d) = (vec3(-s, 0.0, -s), vec3(s, 0.0, -s), vec3(s, 0.0, s), vec3(-s, 0.0, s));
+        let light = DiffuseLight { emit: RgbLinear(Vec3::splat(10.0)) };
+        Scene::new(
+            vec![
+                (Shape::Triangle(Triangle::new(a, c, b)), ground),
+                (Shape::Triangle(Triangle::new(a, d, c)), ground),
+                (Shape::Sphere(Sphere { center: vec3(0.0, 2.0, 0.0), radius: 0.5 }), Material::DiffuseLight(light)),
+            ],
+            RgbLinear(Vec3::ZERO),
+        )
+    }
+
+    // the mean and variance of the red channel
+    fn estimate(integrator: PathIntegrator, scene: &Scene, ray: Ray3, n: u32) -> (f32, f32) {
+        let mut rng = SRng::new(5);
+        let (mut sum, mut sum2) = (0.0, 0.0);
+        for _ in 0..n {
+            let l = integrator.radiance(scene, ray, &mut rng).0.x;
+            sum += l;
+            sum2 += l * l;
+        }
+        let mean = sum / n as f32;
+        (mean, sum2 / n as f32 - mean * mean)
+    }
+
+    #[test]
+    fn diffuse_lit_by_sphere() {
+        let scene = light_over_ground(Material::Lambertian(Lambertian { albedo: RgbLinear(Vec3::splat(0.5)) }));
+        assert_eq!(scene.lights.len(), 1);
+        // the sphere covers `sin² = r² / d²` of the projected hemisphere, nothing else comes back
+        let expected = 0.5 * 10.0 * 0.25 / 4.0;
+        let ray = Ray3 { pos: vec3(3.0, 1.0, 0.0), dir: vec3(-3.0, -1.0, 0.0).normalize(), time: 0.0 };
+        let n = 20000;
+        let (mis, mis_variance) = estimate(PathIntegrator::default(), &scene, ray, n);
+        let (scatter, scatter_variance) = estimate(PathIntegrator { mis: false, ..Default::default() }, &scene, ray, n);
+        assert!((mis - expected).abs() < expected * 0.05, "{} {}", mis, expected);
+        assert!((scatter - expected).abs() < expected * 0.05, "{} {}", scatter, expected);
+        assert!(mis_variance * 4.0 < scatter_variance, "{} {}", mis_variance, scatter_variance);
+    }
+
+    #[test]
+    fn light_in_mirror() {
+        // a delta is never sampled against the light
+        let scene = light_over_ground(Material::Metal(Metal { albedo: RgbLinear(Vec3::ONE), fuzz: 0.0 }));
+        let ray = Ray3 { pos: vec3(0.0, 1.0, 0.0), dir: -Vec3::Y, time: 0.0 };
+        let (mean, variance) = estimate(PathIntegrator::default(), &scene, ray, 100);
+        assert!((mean - 10.0).abs() < 1e-3);
+        assert!(variance < 1e-3);
+    }
+}
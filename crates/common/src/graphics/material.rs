@@ -1,137 +1,1480 @@
+use super::beer_lambert::*;
 use super::color::*;
+use super::microfacet::*;
+use super::texture::*;
 use crate::math::*;
 
 // all assume normalized ray
 
-// #[derive(Copy, Clone)]
-// pub enum MaterialInteraction {
-//     Scatter {
-//         pub attenuation: RgbLinear,
-//         pub ray: Ray3
-//     },
-//     Emit {
-//         pub color: RgbLinear
-//     }
-// }
-
+/**
+ * what happens at a hit. `emission` is the light given off there, then the path goes on with
+ * `ray` if `is_scattered`, otherwise it is absorbed.
+ *
+ * not a enum because rust-gpu can't have fields in them
+ */
 #[derive(Copy, Clone)]
 pub struct MaterialInteraction {
     pub attenuation: RgbLinear,
     pub ray: Ray3,
+    pub emission: RgbLinear,
+    pub is_scattered: bool,
 }
 
+impl MaterialInteraction {
+    pub fn scattered(attenuation: RgbLinear, ray: Ray3) -> Self {
+        MaterialInteraction {
+            attenuation,
+            ray,
+            emission: RgbLinear(Vec3::ZERO),
+            is_scattered: true,
+        }
+    }
+
+    pub fn emitted(emission: RgbLinear) -> Self {
+        MaterialInteraction {
+            attenuation: RgbLinear(Vec3::ZERO),
+            ray: Ray3::default(),
+            emission,
+            is_scattered: false,
+        }
+    }
+
+    pub fn absorbed() -> Self {
+        Self::emitted(RgbLinear(Vec3::ZERO))
+    }
+}
+
+/**
+ * `eval` and `pdf` are for sampling lights and combining it with `scatter` by multiple importance
+ * sampling. `wo` points to where the ray comes from and `wi` to where it goes, both away from the
+ * surface. `eval` is the bsdf times the cosine of `wi`, so `scatter` weights the directions it
+ * picks by `eval / pdf`.
+ *
+ * materials that scatter to single directions like mirrors, or that we can't evaluate, keep the
+ * defaults of zero. a scattered ray with zero `pdf` is such a delta
+ */
 pub trait AbstractMaterial {
     fn scatter(self, rng: &mut SRng, ray: Ray3, hit: HitRecord3) -> MaterialInteraction;
+
+    fn eval(self, _wo: Vec3, _wi: Vec3, _hit: &HitRecord3) -> RgbLinear
+    where
+        Self: Sized,
+    {
+        RgbLinear(Vec3::ZERO)
+    }
+
+    fn pdf(self, _wo: Vec3, _wi: Vec3, _hit: &HitRecord3) -> f32
+    where
+        Self: Sized,
+    {
+        0.0
+    }
+}
+
+// the normal on the side of `w`
+fn facing(nor: Vec3, w: Vec3) -> Vec3 {
+    if w.dot(nor) < 0.0 {
+        -nor
+    } else {
+        nor
+    }
 }
 
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
 #[derive(Copy, Clone)]
-pub struct Lambertian {
-    pub albedo: RgbLinear,
+pub struct Lambertian<T = RgbLinear> {
+    pub albedo: T,
 }
 
-const LAMBERTIAN_SCATTER: u32 = 1;
+// 0 is cosine weighted, 1 is uniform on the hemisphere
+const LAMBERTIAN_SCATTER: u32 = 0;
 
-impl AbstractMaterial for Lambertian {
+impl<T: AbstractTexture + Copy> AbstractMaterial for Lambertian<T> {
     fn scatter(self, rng: &mut SRng, ray: Ray3, hit: HitRecord3) -> MaterialInteraction {
-        let mut dir = if LAMBERTIAN_SCATTER == 0 {
-            hit.nor + rng.gen_in_unit_sphere().normalize()
+        let albedo = self.albedo.value(hit.uv, hit.point);
+        let n = facing(hit.nor, -ray.dir);
+        let dir = if LAMBERTIAN_SCATTER == 0 {
+            rng.gen_cosine_hemisphere(n)
         } else if LAMBERTIAN_SCATTER == 1 {
-            rng.gen_in_hemisphere(hit.nor)
+            rng.gen_in_hemisphere(n).try_normalize_or(n)
         } else {
             panic!();
         };
-        // avoid the case normal and ray_dir cancel out
-        dir = dir.try_normalize_or(hit.nor);
-        MaterialInteraction {
-            attenuation: self.albedo,
-            ray: Ray3 {
+        let attenuation = if LAMBERTIAN_SCATTER == 1 {
+            RgbLinear(albedo.0 * (2.0 * dir.dot(n)))
+        } else {
+            albedo
+        };
+        MaterialInteraction::scattered(
+            attenuation,
+            Ray3 {
                 pos: ray.at(hit.t),
                 dir,
+                time: ray.time,
             },
+        )
+    }
+
+    fn eval(self, wo: Vec3, wi: Vec3, hit: &HitRecord3) -> RgbLinear {
+        let cos = wi.dot(facing(hit.nor, wo)).max(0.0);
+        RgbLinear(self.albedo.value(hit.uv, hit.point).0 * (cos / core::f32::consts::PI))
+    }
+
+    fn pdf(self, wo: Vec3, wi: Vec3, hit: &HitRecord3) -> f32 {
+        let cos = wi.dot(facing(hit.nor, wo));
+        if cos <= 0.0 {
+            0.0
+        } else if LAMBERTIAN_SCATTER == 1 {
+            2.0 * uniform_sphere_pdf()
+        } else {
+            cosine_hemisphere_pdf(cos)
         }
     }
 }
 
+/**
+ * the fuzz has no pdf, so it is a delta for `eval` and `pdf`
+ */
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
 #[derive(Copy, Clone)]
-pub struct Metal {
-    pub albedo: RgbLinear,
+pub struct Metal<T = RgbLinear> {
+    pub albedo: T,
     pub fuzz: f32,
 }
 
-impl AbstractMaterial for Metal {
+impl<T: AbstractTexture + Copy> AbstractMaterial for Metal<T> {
     fn scatter(self, rng: &mut SRng, ray: Ray3, hit: HitRecord3) -> MaterialInteraction {
         let n = hit.nor;
         let reflected = ray.dir.reflect(n);
         let dir = (reflected + rng.gen_in_unit_sphere() * self.fuzz).try_normalize_or(hit.nor);
-        MaterialInteraction {
-            attenuation: self.albedo,
-            ray: Ray3 {
+        MaterialInteraction::scattered(
+            self.albedo.value(hit.uv, hit.point),
+            Ray3 {
                 pos: ray.at(hit.t),
                 dir,
+                time: ray.time,
             },
-        }
+        )
     }
 }
 
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
 #[derive(Copy, Clone)]
 pub struct Dielectric {
     pub ref_idx: f32,
 }
 
-fn refract(uv: Vec3, n: Vec3, etai_over_etat: f32) -> Vec3 {
-    // not understanding, just copying
+/**
+ * refracts `uv` through the surface with normal `n` facing it, `eta` is the ratio of the refraction
+ * index it comes from over the one it goes into. there must be no total internal reflection
+ */
+pub fn refract(uv: Vec3, n: Vec3, eta: f32) -> Vec3 {
     let cos_theta = (-uv).dot(n).min(1.0);
-    let r_out_perp: Vec3 = etai_over_etat * (uv + cos_theta * n);
-    let r_out_parallel: Vec3 = -(1.0 - r_out_perp.length_squared()).abs().sqrt() * n;
-     r_out_parallel + r_out_perp
+    let r_out_perp: Vec3 = eta * (uv + cos_theta * n);
+    let r_out_parallel: Vec3 = -(1.0 - r_out_perp.length_squared()).max(0.0).sqrt() * n;
+    r_out_parallel + r_out_perp
+}
+
+/**
+ * the unpolarized fresnel reflectance of a dielectric, `cos_i` is the cosine of the incident
+ * angle and `eta` is like in `refract`. it is 1 on total internal reflection
+ */
+pub fn fresnel_dielectric(cos_i: f32, eta: f32) -> f32 {
+    let sin_t2 = eta * eta * (1.0 - cos_i * cos_i).max(0.0);
+    if sin_t2 >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin_t2).sqrt();
+    let r_s = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_p = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (r_s * r_s + r_p * r_p) * 0.5
 }
 
-fn schlick(cosine: f32, ref_idx: f32) -> f32 {
-    let r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
-    let r0 = r0 * r0;
-    r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
+/**
+ * moves a ray origin on a surface off it along the normal, so the next ray doesn't hit the same
+ * surface again. the offset grows with the distance to the origin, where floats are coarser
+ */
+pub fn offset_ray_origin(pos: Vec3, nor: Vec3) -> Vec3 {
+    pos + nor * (1e-4 * (1.0 + pos.abs().max_element()))
+}
+
+fn scatter_dielectric(ref_idx: f32, absorption: Vec3, rng: &mut SRng, ray: Ray3, hit: HitRecord3) -> MaterialInteraction {
+    let eta = if hit.from_outside { 1.0 / ref_idx } else { ref_idx };
+    let v: Vec3 = ray.dir.normalize();
+    // the normal on the side the ray comes from
+    let n = if v.dot(hit.nor) > 0.0 { -hit.nor } else { hit.nor };
+    let cos_i = (-v).dot(n).min(1.0);
+    let pos = ray.at(hit.t);
+    let (pos, dir) = if rng.gen() < fresnel_dielectric(cos_i, eta) {
+        (offset_ray_origin(pos, n), v.reflect(n))
+    } else {
+        (offset_ray_origin(pos, -n), refract(v, n, eta).try_normalize_or(-n))
+    };
+    // the ray travelled inside to get here
+    let attenuation = if hit.from_outside {
+        Vec3::ONE
+    } else {
+        Beer_Lambert3(absorption, hit.t * ray.dir.length())
+    };
+    MaterialInteraction::scattered(
+        RgbLinear(attenuation),
+        Ray3 {
+            pos,
+            dir,
+            time: ray.time,
+        },
+    )
 }
 
 impl AbstractMaterial for Dielectric {
     fn scatter(self, rng: &mut SRng, ray: Ray3, hit: HitRecord3) -> MaterialInteraction {
-        let refraction_index = self.ref_idx;
-        let refrection_ratio = if hit.from_outside {
-            1.0 / refraction_index
+        scatter_dielectric(self.ref_idx, Vec3::ZERO, rng, ray, hit)
+    }
+}
+
+/**
+ * colored glass, light is absorbed by `Beer_Lambert3` with `absorption` per unit length inside. it
+ * needs closed shapes, so `from_outside` is false where a ray leaves
+ */
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[derive(Copy, Clone)]
+pub struct AbsorbingDielectric {
+    pub ref_idx: f32,
+    pub absorption: Vec3,
+}
+
+impl AbstractMaterial for AbsorbingDielectric {
+    fn scatter(self, rng: &mut SRng, ray: Ray3, hit: HitRecord3) -> MaterialInteraction {
+        scatter_dielectric(self.ref_idx, self.absorption, rng, ray, hit)
+    }
+}
+
+/**
+ * the phase function of a medium, scatters the same to all directions
+ */
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[derive(Copy, Clone)]
+pub struct Isotropic<T = RgbLinear> {
+    pub albedo: T,
+}
+
+impl<T: AbstractTexture + Copy> AbstractMaterial for Isotropic<T> {
+    fn scatter(self, rng: &mut SRng, ray: Ray3, hit: HitRecord3) -> MaterialInteraction {
+        let dir = rng.gen_on_unit_sphere();
+        MaterialInteraction::scattered(
+            self.albedo.value(hit.uv, hit.point),
+            Ray3 {
+                pos: ray.at(hit.t),
+                dir,
+                time: ray.time,
+            },
+        )
+    }
+
+    fn eval(self, _wo: Vec3, _wi: Vec3, hit: &HitRecord3) -> RgbLinear {
+        RgbLinear(self.albedo.value(hit.uv, hit.point).0 * uniform_sphere_pdf())
+    }
+
+    fn pdf(self, _wo: Vec3, _wi: Vec3, _hit: &HitRecord3) -> f32 {
+        uniform_sphere_pdf()
+    }
+}
+
+/**
+ * the density of the cosine between the incoming ray and the scattered one, `g` is the mean
+ * cosine, positive scatters forward
+ */
+pub fn henyey_greenstein(g: f32, cos_theta: f32) -> f32 {
+    let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * core::f32::consts::PI * denom * denom.sqrt())
+}
+
+/**
+ * a phase function for haze and clouds, `g` is in `(-1, 1)` and zero is isotropic
+ */
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[derive(Copy, Clone)]
+pub struct HenyeyGreenstein<T = RgbLinear> {
+    pub albedo: T,
+    pub g: f32,
+}
+
+impl<T: AbstractTexture + Copy> AbstractMaterial for HenyeyGreenstein<T> {
+    fn scatter(self, rng: &mut SRng, ray: Ray3, hit: HitRecord3) -> MaterialInteraction {
+        let g = self.g;
+        let u = rng.gen();
+        // inverting the cdf, it is singular at `g = 0`
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u
         } else {
-            refraction_index
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+            ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
         };
-        let v: Vec3 = ray.dir;
-        // something I don't bother to understand
-        let cos_theta = (-v).dot(hit.nor).min(1.0);
-        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
-        let cannot_refract = refrection_ratio * sin_theta > 1.0;
-        let dir = if cannot_refract || rng.gen() < schlick(cos_theta, refrection_ratio) {
-            v.reflect(hit.nor)
-        } else {
-            refract(v, hit.nor, refrection_ratio)
-        }.try_normalize_or(-hit.nor);
-        MaterialInteraction {
-            attenuation: RgbLinear(Vec3::ONE),
-            ray: Ray3 {
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * core::f32::consts::PI * rng.gen();
+        let dir = around(ray.dir.normalize(), vec3(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta));
+        MaterialInteraction::scattered(
+            self.albedo.value(hit.uv, hit.point),
+            Ray3 {
                 pos: ray.at(hit.t),
                 dir,
+                time: ray.time,
             },
+        )
+    }
+
+    fn eval(self, wo: Vec3, wi: Vec3, hit: &HitRecord3) -> RgbLinear {
+        RgbLinear(self.albedo.value(hit.uv, hit.point).0 * henyey_greenstein(self.g, -wo.dot(wi)))
+    }
+
+    fn pdf(self, wo: Vec3, wi: Vec3, _hit: &HitRecord3) -> f32 {
+        henyey_greenstein(self.g, -wo.dot(wi))
+    }
+}
+
+fn scattered_local(frame: Frame, attenuation: Vec3, wi: Vec3, ray: Ray3, hit: &HitRecord3) -> MaterialInteraction {
+    if attenuation == Vec3::ZERO {
+        return MaterialInteraction::absorbed();
+    }
+    let n = if wi.z > 0.0 { frame.n } else { -frame.n };
+    MaterialInteraction::scattered(
+        RgbLinear(attenuation),
+        Ray3 {
+            pos: offset_ray_origin(ray.at(hit.t), n),
+            dir: frame.to_world(wi).normalize(),
+            time: ray.time,
+        },
+    )
+}
+
+/**
+ * a rough metal, a GGX microfacet conductor with the complex refraction index `eta + i k` per
+ * channel. it is two sided like the other microfacet materials, the normal is flipped to face the ray
+ */
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[derive(Copy, Clone)]
+pub struct RoughConductor {
+    pub eta: Vec3,
+    pub k: Vec3,
+    pub roughness: f32,
+}
+
+impl AbstractMaterial for RoughConductor {
+    fn scatter(self, rng: &mut SRng, ray: Ray3, hit: HitRecord3) -> MaterialInteraction {
+        let frame = Frame::new(facing(hit.nor, -ray.dir));
+        let wo = frame.to_local(-ray.dir.normalize());
+        let ggx = Ggx::from_roughness(self.roughness);
+        let h = ggx.sample_visible(wo, rng.gen_vec2());
+        let wi = (-wo).reflect(h);
+        let attenuation = if wi.z > 0.0 {
+            fresnel_conductor3(wi.dot(h), self.eta, self.k) * (ggx.g2(wo, wi) / ggx.g1(wo))
+        } else {
+            Vec3::ZERO
+        };
+        scattered_local(frame, attenuation, wi, ray, &hit)
+    }
+
+    fn eval(self, wo: Vec3, wi: Vec3, hit: &HitRecord3) -> RgbLinear {
+        let frame = Frame::new(facing(hit.nor, wo));
+        let (wo, wi) = (frame.to_local(wo), frame.to_local(wi));
+        if wi.z <= 0.0 || wo.z <= 0.0 {
+            return RgbLinear(Vec3::ZERO);
+        }
+        let h = (wo + wi).normalize();
+        let ggx = Ggx::from_roughness(self.roughness);
+        let f = fresnel_conductor3(wi.dot(h), self.eta, self.k);
+        RgbLinear(f * (ggx.d(h) * ggx.g2(wo, wi) / (4.0 * wo.z)))
+    }
+
+    fn pdf(self, wo: Vec3, wi: Vec3, hit: &HitRecord3) -> f32 {
+        let frame = Frame::new(facing(hit.nor, wo));
+        let (wo, wi) = (frame.to_local(wo), frame.to_local(wi));
+        if wi.z <= 0.0 || wo.z <= 0.0 {
+            return 0.0;
+        }
+        let h = (wo + wi).normalize();
+        Ggx::from_roughness(self.roughness).visible_d(wo, h) / (4.0 * wo.dot(h))
+    }
+}
+
+/**
+ * rough glass, it reflects or refracts through GGX microfacets by their fresnel reflectance.
+ * `eval` leaves out the eta² scaling of radiance going through, so a path keeps its energy
+ * like with `Dielectric`
+ */
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[derive(Copy, Clone)]
+pub struct RoughDielectric {
+    pub ref_idx: f32,
+    pub roughness: f32,
+}
+
+impl RoughDielectric {
+    fn eta(self, hit: &HitRecord3) -> f32 {
+        if hit.from_outside {
+            1.0 / self.ref_idx
+        } else {
+            self.ref_idx
+        }
+    }
+
+    // the half vector of the two directions, facing `wo`
+    fn half_vector(eta: f32, wo: Vec3, wi: Vec3) -> Vec3 {
+        let h = if wi.z > 0.0 { wo + wi } else { eta * wo + wi };
+        let h = h.normalize();
+        if h.z < 0.0 {
+            -h
+        } else {
+            h
+        }
+    }
+}
+
+impl AbstractMaterial for RoughDielectric {
+    fn scatter(self, rng: &mut SRng, ray: Ray3, hit: HitRecord3) -> MaterialInteraction {
+        let frame = Frame::new(facing(hit.nor, -ray.dir));
+        let wo = frame.to_local(-ray.dir.normalize());
+        let eta = self.eta(&hit);
+        let ggx = Ggx::from_roughness(self.roughness);
+        let h = ggx.sample_visible(wo, rng.gen_vec2());
+        let cos_o = wo.dot(h);
+        let reflected = rng.gen() < fresnel_dielectric(cos_o, eta);
+        let wi = if reflected {
+            (-wo).reflect(h)
+        } else {
+            refract(-wo, h, eta)
+        };
+        // the reflection or refraction can end up on the wrong side of the surface
+        let right_side = if reflected { wi.z > 0.0 } else { wi.z < 0.0 };
+        let attenuation = if right_side {
+            Vec3::splat(ggx.g2(wo, wi) / ggx.g1(wo))
+        } else {
+            Vec3::ZERO
+        };
+        scattered_local(frame, attenuation, wi, ray, &hit)
+    }
+
+    fn eval(self, wo: Vec3, wi: Vec3, hit: &HitRecord3) -> RgbLinear {
+        let frame = Frame::new(facing(hit.nor, wo));
+        let (wo, wi) = (frame.to_local(wo), frame.to_local(wi));
+        let eta = self.eta(hit);
+        let h = Self::half_vector(eta, wo, wi);
+        let (cos_o, cos_i) = (wo.dot(h), wi.dot(h));
+        if wi.z == 0.0 || cos_o <= 0.0 {
+            return RgbLinear(Vec3::ZERO);
+        }
+        let ggx = Ggx::from_roughness(self.roughness);
+        let f = fresnel_dielectric(cos_o, eta);
+        let value = if wi.z > 0.0 {
+            f * ggx.d(h) * ggx.g2(wo, wi) / (4.0 * wo.z)
+        } else if cos_i < 0.0 {
+            let denom = eta * cos_o + cos_i;
+            (1.0 - f) * ggx.d(h) * ggx.g2(wo, wi) * cos_o * -cos_i / (wo.z * denom * denom)
+        } else {
+            0.0
+        };
+        RgbLinear(Vec3::splat(value))
+    }
+
+    fn pdf(self, wo: Vec3, wi: Vec3, hit: &HitRecord3) -> f32 {
+        let frame = Frame::new(facing(hit.nor, wo));
+        let (wo, wi) = (frame.to_local(wo), frame.to_local(wi));
+        let eta = self.eta(hit);
+        let h = Self::half_vector(eta, wo, wi);
+        let (cos_o, cos_i) = (wo.dot(h), wi.dot(h));
+        if wi.z == 0.0 || cos_o <= 0.0 {
+            return 0.0;
+        }
+        let visible = Ggx::from_roughness(self.roughness).visible_d(wo, h);
+        let f = fresnel_dielectric(cos_o, eta);
+        if wi.z > 0.0 {
+            f * visible / (4.0 * cos_o)
+        } else if cos_i < 0.0 {
+            let denom = eta * cos_o + cos_i;
+            (1.0 - f) * visible * -cos_i / (denom * denom)
+        } else {
+            0.0
+        }
+    }
+}
+
+/**
+ * a diffuse base under a rough clear coat with refraction index `ref_idx`. it is not a real layered
+ * model, the diffuse part is scaled by the light the coat lets in and out
+ */
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[derive(Copy, Clone)]
+pub struct Plastic<T = RgbLinear> {
+    pub albedo: T,
+    pub ref_idx: f32,
+    pub roughness: f32,
+}
+
+impl<T> Plastic<T> {
+    // the probability to sample the coat
+    fn specular_weight(&self, albedo: RgbLinear, wo: Vec3) -> f32 {
+        let f = fresnel_dielectric(wo.z, 1.0 / self.ref_idx);
+        let diffuse = (1.0 - f) * albedo.0.max_element();
+        if f + diffuse > 0.0 {
+            f / (f + diffuse)
+        } else {
+            1.0
+        }
+    }
+}
+
+impl<T: AbstractTexture + Copy> AbstractMaterial for Plastic<T> {
+    fn scatter(self, rng: &mut SRng, ray: Ray3, hit: HitRecord3) -> MaterialInteraction {
+        let wo = -ray.dir.normalize();
+        let frame = Frame::new(facing(hit.nor, wo));
+        let local_wo = frame.to_local(wo);
+        let u = rng.gen_vec2();
+        let albedo = self.albedo.value(hit.uv, hit.point);
+        let local_wi = if rng.gen() < self.specular_weight(albedo, local_wo) {
+            let h = Ggx::from_roughness(self.roughness).sample_visible(local_wo, u);
+            (-local_wo).reflect(h)
+        } else {
+            cosine_hemisphere(u)
+        };
+        let wi = frame.to_world(local_wi);
+        let pdf = self.pdf(wo, wi, &hit);
+        let attenuation = if local_wi.z > 0.0 && pdf > 0.0 {
+            self.eval(wo, wi, &hit).0 / pdf
+        } else {
+            Vec3::ZERO
+        };
+        scattered_local(frame, attenuation, local_wi, ray, &hit)
+    }
+
+    fn eval(self, wo: Vec3, wi: Vec3, hit: &HitRecord3) -> RgbLinear {
+        let frame = Frame::new(facing(hit.nor, wo));
+        let (wo, wi) = (frame.to_local(wo), frame.to_local(wi));
+        if wi.z <= 0.0 || wo.z <= 0.0 {
+            return RgbLinear(Vec3::ZERO);
+        }
+        let eta = 1.0 / self.ref_idx;
+        let h = (wo + wi).normalize();
+        let ggx = Ggx::from_roughness(self.roughness);
+        let specular = fresnel_dielectric(wi.dot(h), eta) * ggx.d(h) * ggx.g2(wo, wi) / (4.0 * wo.z);
+        let transmitted = (1.0 - fresnel_dielectric(wo.z, eta)) * (1.0 - fresnel_dielectric(wi.z, eta));
+        let albedo = self.albedo.value(hit.uv, hit.point).0;
+        RgbLinear(Vec3::splat(specular) + albedo * (transmitted * wi.z / core::f32::consts::PI))
+    }
+
+    fn pdf(self, wo: Vec3, wi: Vec3, hit: &HitRecord3) -> f32 {
+        let frame = Frame::new(facing(hit.nor, wo));
+        let (wo, wi) = (frame.to_local(wo), frame.to_local(wi));
+        if wi.z <= 0.0 || wo.z <= 0.0 {
+            return 0.0;
+        }
+        let h = (wo + wi).normalize();
+        let specular = Ggx::from_roughness(self.roughness).visible_d(wo, h) / (4.0 * wo.dot(h));
+        let weight = self.specular_weight(self.albedo.value(hit.uv, hit.point), wo);
+        weight * specular + (1.0 - weight) * cosine_hemisphere_pdf(wi.z)
+    }
+}
+
+pub fn fresnel_schlick(cos_i: f32, f0: Vec3) -> Vec3 {
+    let m = (1.0 - cos_i).clamp(0.0, 1.0);
+    let m2 = m * m;
+    f0 + (Vec3::ONE - f0) * (m2 * m2 * m)
+}
+
+/**
+ * the Disney principled BSDF, "Physically Based Shading at Disney" and "Extending the Disney BRDF
+ * to a BSDF with Integrated Subsurface Scattering", Burley. all parameters but `base_color` are
+ * in `[0, 1]`.
+ *
+ * it is a clear coat over a mix of a metal, a rough glass and a dielectric over a diffuse base,
+ * weighted by `metallic` and `transmission`. the lobes are arranged so it never gains energy,
+ * that is where it differs from the original:
+ *
+ * * the diffuse is lambertian scaled by the light the specular lets in and out, like `Plastic`,
+ *   instead of Burley's retro reflection
+ * * sheen moves the diffuse color to the sheen color at grazing angles instead of adding to it
+ * * the clear coat is GGX instead of GTR1, and the layers under it are scaled by what it lets in
+ *   and out
+ *
+ * `specular` sets the normal reflectance `0.08 * specular`, the refraction index of the glass is
+ * the one with that reflectance, 1.5 for the default 0.5. `anisotropic` stretches the roughness
+ * along the tangent of `Frame`, as shapes don't have tangents yet
+ */
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[derive(Copy, Clone)]
+pub struct Principled<T = RgbLinear> {
+    pub base_color: T,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub anisotropic: f32,
+    pub specular: f32,
+    pub specular_tint: f32,
+    pub sheen: f32,
+    pub sheen_tint: f32,
+    pub clearcoat: f32,
+    pub clearcoat_gloss: f32,
+    pub transmission: f32,
+}
+
+impl Default for Principled {
+    fn default() -> Self {
+        Principled {
+            base_color: RgbLinear(Vec3::splat(0.8)),
+            metallic: 0.0,
+            roughness: 0.5,
+            anisotropic: 0.0,
+            specular: 0.5,
+            specular_tint: 0.0,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_gloss: 1.0,
+            transmission: 0.0,
         }
     }
 }
 
-pub enum Material {
-    Lambertian(Lambertian),
-    Metal(Metal),
+// the lobes of `Principled` at one point, in the local frame of the side of `wo`
+#[derive(Copy, Clone)]
+struct PrincipledLobes {
+    base_color: Vec3,
+    specular_color: Vec3,
+    sheen_color: Vec3,
+    sheen: f32,
+    ggx: Ggx,
+    clearcoat: f32,
+    clearcoat_ggx: Ggx,
+    // of the glass, going through from the side of `wo`
+    eta: f32,
+    metal: f32,
+    glass: f32,
+    dielectric: f32,
+}
+
+// the refraction index of the clear coat
+const CLEARCOAT_ETA: f32 = 1.0 / 1.5;
+
+impl PrincipledLobes {
+    fn clearcoat_fresnel(self, cos: f32) -> f32 {
+        self.clearcoat * fresnel_dielectric(cos.abs(), CLEARCOAT_ETA)
+    }
+
+    // the probability to sample the clear coat, the metal and dielectric specular, the glass and
+    // the diffuse
+    fn weights(self, wo: Vec3) -> [f32; 4] {
+        let coat = self.clearcoat_fresnel(wo.z);
+        let under = 1.0 - coat;
+        let specular = fresnel_schlick(wo.z, self.specular_color);
+        let weights = [
+            coat,
+            under * (self.metal + self.dielectric * RgbLinear(specular).luminance()),
+            under * self.glass,
+            under * self.dielectric * RgbLinear(self.base_color * (Vec3::ONE - specular)).luminance(),
+        ];
+        let sum: f32 = weights[0] + weights[1] + weights[2] + weights[3];
+        if sum > 0.0 {
+            [weights[0] / sum, weights[1] / sum, weights[2] / sum, weights[3] / sum]
+        } else {
+            [0.0, 0.0, 0.0, 1.0]
+        }
+    }
+
+    fn eval(self, wo: Vec3, wi: Vec3) -> Vec3 {
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return Vec3::ZERO;
+        }
+        let under = (1.0 - self.clearcoat_fresnel(wo.z)) * (1.0 - self.clearcoat_fresnel(wi.z));
+        if wi.z < 0.0 {
+            let h = RoughDielectric::half_vector(self.eta, wo, wi);
+            let (cos_o, cos_i) = (wo.dot(h), wi.dot(h));
+            if cos_o <= 0.0 || cos_i >= 0.0 {
+                return Vec3::ZERO;
+            }
+            let denom = self.eta * cos_o + cos_i;
+            let f = fresnel_dielectric(cos_o, self.eta);
+            let value = (1.0 - f) * self.ggx.d(h) * self.ggx.g2(wo, wi) * cos_o * -cos_i / (wo.z * denom * denom);
+            return self.base_color * (under * self.glass * value);
+        }
+        let h = (wo + wi).normalize();
+        let cos_h = wi.dot(h);
+        let microfacet = |ggx: Ggx| ggx.d(h) * ggx.g2(wo, wi) / (4.0 * wo.z);
+        let coat = self.clearcoat_fresnel(cos_h) * microfacet(self.clearcoat_ggx);
+        let specular = microfacet(self.ggx);
+        let metal = fresnel_schlick(cos_h, self.base_color);
+        let glass = fresnel_dielectric(wo.dot(h), self.eta);
+        let dielectric = fresnel_schlick(cos_h, self.specular_color);
+        let m = 1.0 - cos_h;
+        let m2 = m * m;
+        let diffuse_color = self.base_color.lerp(self.sheen_color, self.sheen * m2 * m2 * m);
+        let transmitted = (Vec3::ONE - fresnel_schlick(wo.z, self.specular_color)) * (Vec3::ONE - fresnel_schlick(wi.z, self.specular_color));
+        let diffuse = diffuse_color * transmitted * (wi.z / core::f32::consts::PI);
+        let layers = (metal * self.metal + Vec3::splat(glass * self.glass) + dielectric * self.dielectric) * specular + diffuse * self.dielectric;
+        Vec3::splat(coat) + layers * under
+    }
+
+    fn pdf(self, wo: Vec3, wi: Vec3) -> f32 {
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return 0.0;
+        }
+        let [coat, specular, glass, diffuse] = self.weights(wo);
+        if wi.z < 0.0 {
+            let h = RoughDielectric::half_vector(self.eta, wo, wi);
+            let (cos_o, cos_i) = (wo.dot(h), wi.dot(h));
+            if cos_o <= 0.0 || cos_i >= 0.0 {
+                return 0.0;
+            }
+            let denom = self.eta * cos_o + cos_i;
+            let f = fresnel_dielectric(cos_o, self.eta);
+            return glass * (1.0 - f) * self.ggx.visible_d(wo, h) * -cos_i / (denom * denom);
+        }
+        let h = (wo + wi).normalize();
+        let reflected = |ggx: Ggx| ggx.visible_d(wo, h) / (4.0 * wo.dot(h));
+        let f = fresnel_dielectric(wo.dot(h), self.eta);
+        coat * reflected(self.clearcoat_ggx)
+            + (specular + glass * f) * reflected(self.ggx)
+            + diffuse * cosine_hemisphere_pdf(wi.z)
+    }
+
+    fn sample(self, wo: Vec3, u_lobe: f32, u: Vec2) -> Vec3 {
+        let [coat, specular, glass, _] = self.weights(wo);
+        if u_lobe < coat {
+            (-wo).reflect(self.clearcoat_ggx.sample_visible(wo, u))
+        } else if u_lobe < coat + specular {
+            (-wo).reflect(self.ggx.sample_visible(wo, u))
+        } else if u_lobe < coat + specular + glass {
+            // the rest of `u_lobe` picks reflection or refraction
+            let u_glass = (u_lobe - coat - specular) / glass;
+            let h = self.ggx.sample_visible(wo, u);
+            if u_glass < fresnel_dielectric(wo.dot(h), self.eta) {
+                (-wo).reflect(h)
+            } else {
+                refract(-wo, h, self.eta)
+            }
+        } else {
+            cosine_hemisphere(u)
+        }
+    }
+}
+
+impl<T: AbstractTexture + Copy> Principled<T> {
+    fn lobes(self, hit: &HitRecord3) -> PrincipledLobes {
+        let base_color = self.base_color.value(hit.uv, hit.point).0;
+        let luminance = RgbLinear(base_color).luminance();
+        let tint = if luminance > 0.0 { base_color / luminance } else { Vec3::ONE };
+        let f0 = 0.08 * self.specular;
+        let ref_idx = (1.0 + f0.sqrt()) / (1.0 - f0.sqrt()).max(1e-3);
+        let clearcoat_alpha = 0.1 + (0.001 - 0.1) * self.clearcoat_gloss;
+        PrincipledLobes {
+            base_color,
+            specular_color: Vec3::ONE.lerp(tint, self.specular_tint) * f0,
+            sheen_color: Vec3::ONE.lerp(tint, self.sheen_tint),
+            sheen: self.sheen,
+            ggx: Ggx::anisotropic(self.roughness, self.anisotropic),
+            clearcoat: self.clearcoat,
+            clearcoat_ggx: Ggx { alpha: Vec2::splat(clearcoat_alpha) },
+            eta: if hit.from_outside { 1.0 / ref_idx } else { ref_idx },
+            metal: self.metallic,
+            glass: (1.0 - self.metallic) * self.transmission,
+            dielectric: (1.0 - self.metallic) * (1.0 - self.transmission),
+        }
+    }
+}
+
+impl<T: AbstractTexture + Copy> AbstractMaterial for Principled<T> {
+    fn scatter(self, rng: &mut SRng, ray: Ray3, hit: HitRecord3) -> MaterialInteraction {
+        let wo = -ray.dir.normalize();
+        let frame = Frame::new(facing(hit.nor, wo));
+        let lobes = self.lobes(&hit);
+        let local_wo = frame.to_local(wo);
+        let u_lobe = rng.gen();
+        // through the world frame, so it is exactly what `eval` and `pdf` see
+        let wi = frame.to_world(lobes.sample(local_wo, u_lobe, rng.gen_vec2())).normalize();
+        let local_wi = frame.to_local(wi);
+        let pdf = lobes.pdf(local_wo, local_wi);
+        let attenuation = if pdf > 0.0 {
+            lobes.eval(local_wo, local_wi) / pdf
+        } else {
+            Vec3::ZERO
+        };
+        scattered_local(frame, attenuation, local_wi, ray, &hit)
+    }
+
+    fn eval(self, wo: Vec3, wi: Vec3, hit: &HitRecord3) -> RgbLinear {
+        let frame = Frame::new(facing(hit.nor, wo));
+        RgbLinear(self.lobes(hit).eval(frame.to_local(wo), frame.to_local(wi)))
+    }
+
+    fn pdf(self, wo: Vec3, wi: Vec3, hit: &HitRecord3) -> f32 {
+        let frame = Frame::new(facing(hit.nor, wo));
+        self.lobes(hit).pdf(frame.to_local(wo), frame.to_local(wi))
+    }
+}
+
+/**
+ * any of the materials, the colors are textures of type `T`. only constant colors can be packed
+ * into `GpuMaterial`.
+ *
+ * rust-gpu can't match on enums with fields, so in shaders `Material` is `GpuMaterial` instead:
+ * the enum lowered to its tag and parameters by `GpuMaterial::from`, dispatched by the tag
+ */
+#[cfg(not(target_arch = "spirv"))]
+#[derive(Copy, Clone, Debug)]
+pub enum Material<T = RgbLinear> {
+    Lambertian(Lambertian<T>),
+    Metal(Metal<T>),
     Dielectric(Dielectric),
+    AbsorbingDielectric(AbsorbingDielectric),
+    Isotropic(Isotropic<T>),
+    HenyeyGreenstein(HenyeyGreenstein<T>),
+    DiffuseLight(DiffuseLight<T>),
+    RoughConductor(RoughConductor),
+    RoughDielectric(RoughDielectric),
+    Plastic(Plastic<T>),
+    Principled(Principled<T>),
+}
+
+#[cfg(target_arch = "spirv")]
+pub type Material = GpuMaterial;
+
+/**
+ * a area light, it emits from the front face and absorbs everything
+ */
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[derive(Copy, Clone)]
+pub struct DiffuseLight<T = RgbLinear> {
+    pub emit: T,
+}
+
+impl<T: AbstractTexture + Copy> AbstractMaterial for DiffuseLight<T> {
+    fn scatter(self, _rng: &mut SRng, _ray: Ray3, hit: HitRecord3) -> MaterialInteraction {
+        if hit.from_outside {
+            MaterialInteraction::emitted(self.emit.value(hit.uv, hit.point))
+        } else {
+            MaterialInteraction::absorbed()
+        }
+    }
+}
+
+// on rust-gpu this is the impl of `GpuMaterial`
+#[cfg(not(target_arch = "spirv"))]
+impl<T: AbstractTexture + Copy> AbstractMaterial for Material<T> {
+    fn scatter(self, rng: &mut SRng, ray: Ray3, hit: HitRecord3) -> MaterialInteraction {
+        match self {
+            Material::Lambertian(i) => i.scatter(rng, ray, hit),
+            Material::Metal(i) => i.scatter(rng, ray, hit),
+            Material::Dielectric(i) => i.scatter(rng, ray, hit),
+            Material::AbsorbingDielectric(i) => i.scatter(rng, ray, hit),
+            Material::Isotropic(i) => i.scatter(rng, ray, hit),
+            Material::HenyeyGreenstein(i) => i.scatter(rng, ray, hit),
+            Material::DiffuseLight(i) => i.scatter(rng, ray, hit),
+            Material::RoughConductor(i) => i.scatter(rng, ray, hit),
+            Material::RoughDielectric(i) => i.scatter(rng, ray, hit),
+            Material::Plastic(i) => i.scatter(rng, ray, hit),
+            Material::Principled(i) => i.scatter(rng, ray, hit),
+        }
+    }
+
+    fn eval(self, wo: Vec3, wi: Vec3, hit: &HitRecord3) -> RgbLinear {
+        match self {
+            Material::Lambertian(i) => i.eval(wo, wi, hit),
+            Material::Metal(i) => i.eval(wo, wi, hit),
+            Material::Dielectric(i) => i.eval(wo, wi, hit),
+            Material::AbsorbingDielectric(i) => i.eval(wo, wi, hit),
+            Material::Isotropic(i) => i.eval(wo, wi, hit),
+            Material::HenyeyGreenstein(i) => i.eval(wo, wi, hit),
+            Material::DiffuseLight(i) => i.eval(wo, wi, hit),
+            Material::RoughConductor(i) => i.eval(wo, wi, hit),
+            Material::RoughDielectric(i) => i.eval(wo, wi, hit),
+            Material::Plastic(i) => i.eval(wo, wi, hit),
+            Material::Principled(i) => i.eval(wo, wi, hit),
+        }
+    }
+
+    fn pdf(self, wo: Vec3, wi: Vec3, hit: &HitRecord3) -> f32 {
+        match self {
+            Material::Lambertian(i) => i.pdf(wo, wi, hit),
+            Material::Metal(i) => i.pdf(wo, wi, hit),
+            Material::Dielectric(i) => i.pdf(wo, wi, hit),
+            Material::AbsorbingDielectric(i) => i.pdf(wo, wi, hit),
+            Material::Isotropic(i) => i.pdf(wo, wi, hit),
+            Material::HenyeyGreenstein(i) => i.pdf(wo, wi, hit),
+            Material::DiffuseLight(i) => i.pdf(wo, wi, hit),
+            Material::RoughConductor(i) => i.pdf(wo, wi, hit),
+            Material::RoughDielectric(i) => i.pdf(wo, wi, hit),
+            Material::Plastic(i) => i.pdf(wo, wi, hit),
+            Material::Principled(i) => i.pdf(wo, wi, hit),
+        }
+    }
+}
+
+pub const MATERIAL_LAMBERTIAN: u32 = 0;
+pub const MATERIAL_METAL: u32 = 1;
+pub const MATERIAL_DIELECTRIC: u32 = 2;
+pub const MATERIAL_ISOTROPIC: u32 = 3;
+pub const MATERIAL_HENYEY_GREENSTEIN: u32 = 4;
+pub const MATERIAL_DIFFUSE_LIGHT: u32 = 5;
+pub const MATERIAL_ABSORBING_DIELECTRIC: u32 = 6;
+pub const MATERIAL_ROUGH_CONDUCTOR: u32 = 7;
+pub const MATERIAL_ROUGH_DIELECTRIC: u32 = 8;
+pub const MATERIAL_PLASTIC: u32 = 9;
+pub const MATERIAL_PRINCIPLED: u32 = 10;
+
+/**
+ * a `Material` as a tagged struct, so a storage buffer can hold an array of them and shaders can
+ * index it by material id. `params` depends on `tag`:
+ *
+ * * metal: `x` is the fuzz
+ * * dielectric: `x` is the refraction index
+ * * absorbing dielectric: `x` is the refraction index, `albedo` is the absorption
+ * * henyey greenstein: `x` is `g`
+ * * rough conductor: `albedo` is `eta`, `xyz` is `k` and `w` is the roughness
+ * * rough dielectric and plastic: `x` is the refraction index, `y` is the roughness
+ * * principled: `albedo` is the base color, `params` is `(metallic, roughness, anisotropic,
+ *   transmission)`, `more[0]` is `(specular, specular_tint, sheen, sheen_tint)` and `xy` of
+ *   `more[1]` are `(clearcoat, clearcoat_gloss)`
+ *
+ * `more` is only used by the principled material
+ */
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[repr(C)]
+#[derive(Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GpuMaterial {
+    pub albedo: Vec3,
+    pub tag: u32,
+    pub params: Vec4,
+    pub more: [Vec4; 2],
 }
 
-// impl AbstractMaterial for Material {
-//     fn scatter(self, rng: &mut SRng, ray: Ray3, hit: HitRecord3) -> MaterialInteraction {
-//         match self {
-//             Material::Lambertian(i) => i.scatter(rng, ray, hit),
-//             Material::Metal(i) => i.scatter(rng, ray, hit),
-//             Material::Dielectric(i) => i.scatter(rng, ray, hit),
-//         }
-//     }
-// }
+impl AbstractMaterial for GpuMaterial {
+    fn scatter(self, rng: &mut SRng, ray: Ray3, hit: HitRecord3) -> MaterialInteraction {
+        let albedo = RgbLinear(self.albedo);
+        if self.tag == MATERIAL_METAL {
+            Metal { albedo, fuzz: self.params.x }.scatter(rng, ray, hit)
+        } else if self.tag == MATERIAL_DIELECTRIC {
+            Dielectric { ref_idx: self.params.x }.scatter(rng, ray, hit)
+        } else if self.tag == MATERIAL_ABSORBING_DIELECTRIC {
+            AbsorbingDielectric { ref_idx: self.params.x, absorption: self.albedo }.scatter(rng, ray, hit)
+        } else if self.tag == MATERIAL_ISOTROPIC {
+            Isotropic { albedo }.scatter(rng, ray, hit)
+        } else if self.tag == MATERIAL_HENYEY_GREENSTEIN {
+            HenyeyGreenstein { albedo, g: self.params.x }.scatter(rng, ray, hit)
+        } else if self.tag == MATERIAL_DIFFUSE_LIGHT {
+            DiffuseLight { emit: albedo }.scatter(rng, ray, hit)
+        } else if self.tag == MATERIAL_ROUGH_CONDUCTOR {
+            RoughConductor { eta: self.albedo, k: self.params.xyz(), roughness: self.params.w }.scatter(rng, ray, hit)
+        } else if self.tag == MATERIAL_ROUGH_DIELECTRIC {
+            RoughDielectric { ref_idx: self.params.x, roughness: self.params.y }.scatter(rng, ray, hit)
+        } else if self.tag == MATERIAL_PLASTIC {
+            Plastic { albedo, ref_idx: self.params.x, roughness: self.params.y }.scatter(rng, ray, hit)
+        } else if self.tag == MATERIAL_PRINCIPLED {
+            self.principled().scatter(rng, ray, hit)
+        } else {
+            Lambertian { albedo }.scatter(rng, ray, hit)
+        }
+    }
+
+    fn eval(self, wo: Vec3, wi: Vec3, hit: &HitRecord3) -> RgbLinear {
+        let albedo = RgbLinear(self.albedo);
+        if self.is_delta() {
+            RgbLinear(Vec3::ZERO)
+        } else if self.tag == MATERIAL_ISOTROPIC {
+            Isotropic { albedo }.eval(wo, wi, hit)
+        } else if self.tag == MATERIAL_HENYEY_GREENSTEIN {
+            HenyeyGreenstein { albedo, g: self.params.x }.eval(wo, wi, hit)
+        } else if self.tag == MATERIAL_ROUGH_CONDUCTOR {
+            RoughConductor { eta: self.albedo, k: self.params.xyz(), roughness: self.params.w }.eval(wo, wi, hit)
+        } else if self.tag == MATERIAL_ROUGH_DIELECTRIC {
+            RoughDielectric { ref_idx: self.params.x, roughness: self.params.y }.eval(wo, wi, hit)
+        } else if self.tag == MATERIAL_PLASTIC {
+            Plastic { albedo, ref_idx: self.params.x, roughness: self.params.y }.eval(wo, wi, hit)
+        } else if self.tag == MATERIAL_PRINCIPLED {
+            self.principled().eval(wo, wi, hit)
+        } else {
+            Lambertian { albedo }.eval(wo, wi, hit)
+        }
+    }
+
+    fn pdf(self, wo: Vec3, wi: Vec3, hit: &HitRecord3) -> f32 {
+        let albedo = RgbLinear(self.albedo);
+        if self.is_delta() {
+            0.0
+        } else if self.tag == MATERIAL_ISOTROPIC {
+            Isotropic { albedo }.pdf(wo, wi, hit)
+        } else if self.tag == MATERIAL_HENYEY_GREENSTEIN {
+            HenyeyGreenstein { albedo, g: self.params.x }.pdf(wo, wi, hit)
+        } else if self.tag == MATERIAL_ROUGH_CONDUCTOR {
+            RoughConductor { eta: self.albedo, k: self.params.xyz(), roughness: self.params.w }.pdf(wo, wi, hit)
+        } else if self.tag == MATERIAL_ROUGH_DIELECTRIC {
+            RoughDielectric { ref_idx: self.params.x, roughness: self.params.y }.pdf(wo, wi, hit)
+        } else if self.tag == MATERIAL_PLASTIC {
+            Plastic { albedo, ref_idx: self.params.x, roughness: self.params.y }.pdf(wo, wi, hit)
+        } else if self.tag == MATERIAL_PRINCIPLED {
+            self.principled().pdf(wo, wi, hit)
+        } else {
+            Lambertian { albedo }.pdf(wo, wi, hit)
+        }
+    }
+}
+
+impl GpuMaterial {
+    // the ones keeping the zero `eval` and `pdf`
+    fn is_delta(self) -> bool {
+        self.tag == MATERIAL_METAL
+            || self.tag == MATERIAL_DIELECTRIC
+            || self.tag == MATERIAL_ABSORBING_DIELECTRIC
+            || self.tag == MATERIAL_DIFFUSE_LIGHT
+    }
+
+    fn principled(self) -> Principled {
+        let [more, clearcoat] = self.more;
+        Principled {
+            base_color: RgbLinear(self.albedo),
+            metallic: self.params.x,
+            roughness: self.params.y,
+            anisotropic: self.params.z,
+            specular: more.x,
+            specular_tint: more.y,
+            sheen: more.z,
+            sheen_tint: more.w,
+            clearcoat: clearcoat.x,
+            clearcoat_gloss: clearcoat.y,
+            transmission: self.params.w,
+        }
+    }
+}
+
+#[cfg(not(target_arch = "spirv"))]
+impl From<Material> for GpuMaterial {
+    fn from(material: Material) -> Self {
+        let packed = |albedo: RgbLinear, tag, x| GpuMaterial {
+            albedo: albedo.0,
+            tag,
+            params: vec4(x, 0.0, 0.0, 0.0),
+            more: [Vec4::ZERO; 2],
+        };
+        let none = RgbLinear(Vec3::ONE);
+        match material {
+            Material::Lambertian(m) => packed(m.albedo, MATERIAL_LAMBERTIAN, 0.0),
+            Material::Metal(m) => packed(m.albedo, MATERIAL_METAL, m.fuzz),
+            Material::Dielectric(m) => packed(none, MATERIAL_DIELECTRIC, m.ref_idx),
+            Material::AbsorbingDielectric(m) => packed(RgbLinear(m.absorption), MATERIAL_ABSORBING_DIELECTRIC, m.ref_idx),
+            Material::Isotropic(m) => packed(m.albedo, MATERIAL_ISOTROPIC, 0.0),
+            Material::HenyeyGreenstein(m) => packed(m.albedo, MATERIAL_HENYEY_GREENSTEIN, m.g),
+            Material::DiffuseLight(m) => packed(m.emit, MATERIAL_DIFFUSE_LIGHT, 0.0),
+            Material::RoughConductor(m) => GpuMaterial {
+                albedo: m.eta,
+                tag: MATERIAL_ROUGH_CONDUCTOR,
+                params: m.k.extend(m.roughness),
+                more: [Vec4::ZERO; 2],
+            },
+            Material::RoughDielectric(m) => GpuMaterial {
+                albedo: Vec3::ONE,
+                tag: MATERIAL_ROUGH_DIELECTRIC,
+                params: vec4(m.ref_idx, m.roughness, 0.0, 0.0),
+                more: [Vec4::ZERO; 2],
+            },
+            Material::Plastic(m) => GpuMaterial {
+                albedo: m.albedo.0,
+                tag: MATERIAL_PLASTIC,
+                params: vec4(m.ref_idx, m.roughness, 0.0, 0.0),
+                more: [Vec4::ZERO; 2],
+            },
+            Material::Principled(m) => GpuMaterial {
+                albedo: m.base_color.0,
+                tag: MATERIAL_PRINCIPLED,
+                params: vec4(m.metallic, m.roughness, m.anisotropic, m.transmission),
+                more: [
+                    vec4(m.specular, m.specular_tint, m.sheen, m.sheen_tint),
+                    vec4(m.clearcoat, m.clearcoat_gloss, 0.0, 0.0),
+                ],
+            },
+        }
+    }
+}
+
+#[cfg(not(target_arch = "spirv"))]
+impl From<GpuMaterial> for Material {
+    fn from(m: GpuMaterial) -> Self {
+        let albedo = RgbLinear(m.albedo);
+        match m.tag {
+            MATERIAL_METAL => Material::Metal(Metal { albedo, fuzz: m.params.x }),
+            MATERIAL_DIELECTRIC => Material::Dielectric(Dielectric { ref_idx: m.params.x }),
+            MATERIAL_ABSORBING_DIELECTRIC => Material::AbsorbingDielectric(AbsorbingDielectric {
+                ref_idx: m.params.x,
+                absorption: m.albedo,
+            }),
+            MATERIAL_ISOTROPIC => Material::Isotropic(Isotropic { albedo }),
+            MATERIAL_HENYEY_GREENSTEIN => Material::HenyeyGreenstein(HenyeyGreenstein { albedo, g: m.params.x }),
+            MATERIAL_DIFFUSE_LIGHT => Material::DiffuseLight(DiffuseLight { emit: albedo }),
+            MATERIAL_ROUGH_CONDUCTOR => Material::RoughConductor(RoughConductor {
+                eta: m.albedo,
+                k: m.params.xyz(),
+                roughness: m.params.w,
+            }),
+            MATERIAL_ROUGH_DIELECTRIC => Material::RoughDielectric(RoughDielectric {
+                ref_idx: m.params.x,
+                roughness: m.params.y,
+            }),
+            MATERIAL_PLASTIC => Material::Plastic(Plastic {
+                albedo,
+                ref_idx: m.params.x,
+                roughness: m.params.y,
+            }),
+            MATERIAL_PRINCIPLED => Material::Principled(m.principled()),
+            _ => Material::Lambertian(Lambertian { albedo }),
+        }
+    }
+}
+
+/**
+ * the storage buffer of `materials`, the material id is the index
+ */
+#[cfg(not(target_arch = "spirv"))]
+pub fn pack_materials(materials: &[Material]) -> Vec<GpuMaterial> {
+    materials.iter().map(|m| GpuMaterial::from(*m)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+
+    fn mean_cos(material: impl AbstractMaterial + Copy) -> f32 {
+        let mut rng = SRng::new(7);
+        let ray = Ray3 { pos: Vec3::ZERO, dir: vec3(1.0, 2.0, -2.0) / 3.0, time: 0.0 };
+        let hit = HitRecord3 { is_hit: true, t: 1.0, from_outside: true, nor: Vec3::X, ..Default::default() };
+        let n = 100000;
+        let mut sum = 0.0;
+        for _ in 0..n {
+            let scattered = material.scatter(&mut rng, ray, hit);
+            assert!((scattered.ray.dir.length() - 1.0).abs() < 1e-4);
+            assert_eq!(scattered.ray.pos, ray.at(1.0));
+            sum += scattered.ray.dir.dot(ray.dir);
+        }
+        sum / n as f32
+    }
+
+    #[test]
+    fn phase_functions_mean_cosine() {
+        let albedo = RgbLinear(Vec3::ONE);
+        assert!(mean_cos(Isotropic { albedo }).abs() < 0.01);
+        for g in [-0.7, -0.2, 0.0, 0.3, 0.9] {
+            let mean = mean_cos(HenyeyGreenstein { albedo, g });
+            assert!((mean - g).abs() < 0.01, "g {} mean {}", g, mean);
+        }
+        // it is a density on the sphere
+        for g in [-0.5, 0.0, 0.8] {
+            let n = 10000;
+            let integral: f32 = (0..n)
+                .map(|i| henyey_greenstein(g, -1.0 + 2.0 * (i as f32 + 0.5) / n as f32) * 2.0 / n as f32)
+                .sum::<f32>()
+                * 2.0
+                * core::f32::consts::PI;
+            assert!((integral - 1.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn diffuse_light_emits_from_the_front() {
+        let light = DiffuseLight { emit: RgbLinear(vec3(4.0, 2.0, 1.0)) };
+        let ray = Ray3 { pos: Vec3::ZERO, dir: -Vec3::Z, time: 0.0 };
+        let hit = |from_outside| HitRecord3 { is_hit: true, t: 1.0, from_outside, nor: Vec3::Z, ..Default::default() };
+        let front = light.scatter(&mut SRng::new(0), ray, hit(true));
+        assert!(!front.is_scattered);
+        assert_eq!(front.emission.0, vec3(4.0, 2.0, 1.0));
+        let back = light.scatter(&mut SRng::new(0), ray, hit(false));
+        assert!(!back.is_scattered);
+        assert_eq!(back.emission.0, Vec3::ZERO);
+        let lambertian = Lambertian { albedo: RgbLinear(Vec3::ONE) }.scatter(&mut SRng::new(0), ray, hit(true));
+        assert!(lambertian.is_scattered);
+        assert_eq!(lambertian.emission.0, Vec3::ZERO);
+    }
+
+    // the fraction reflected, checking every scattered ray on the way
+    fn dielectric_reflectance(ref_idx: f32, cos_i: f32, from_outside: bool) -> f32 {
+        let mut rng = SRng::new(3);
+        let material = Dielectric { ref_idx };
+        let dir = vec3((1.0 - cos_i * cos_i).sqrt(), 0.0, -cos_i);
+        // hits the origin
+        let ray = Ray3 { pos: -dir, dir, time: 0.0 };
+        let n = 20000;
+        let mut reflected = 0;
+        for i in 0..n {
+            // the normal can face either way
+            let nor = if i % 2 == 0 { Vec3::Z } else { -Vec3::Z };
+            let hit = HitRecord3 { is_hit: true, t: 1.0, from_outside, nor, ..Default::default() };
+            let scattered = material.scatter(&mut rng, ray, hit);
+            assert!(scattered.is_scattered);
+            assert_eq!(scattered.attenuation.0, Vec3::ONE);
+            assert!((scattered.ray.dir.length() - 1.0).abs() < 1e-4);
+            if scattered.ray.dir.z > 0.0 {
+                reflected += 1;
+                assert!((scattered.ray.dir - dir.reflect(Vec3::Z)).length() < 1e-4);
+                assert!(scattered.ray.pos.z > 0.0);
+            } else {
+                let eta = if from_outside { 1.0 / ref_idx } else { ref_idx };
+                // snell's law
+                assert!((scattered.ray.dir.x - dir.x * eta).abs() < 1e-4);
+                assert!(scattered.ray.pos.z < 0.0);
+            }
+        }
+        reflected as f32 / n as f32
+    }
+
+    #[test]
+    fn dielectric_fresnel() {
+        // reciprocal, the same going in or out along the same path
+        for cos_i in [1.0f32, 0.8, 0.5, 0.1] {
+            let sin_t = (1.0 - cos_i * cos_i).sqrt() / 1.5;
+            let cos_t = (1.0 - sin_t * sin_t).sqrt();
+            assert!((fresnel_dielectric(cos_i, 1.0 / 1.5) - fresnel_dielectric(cos_t, 1.5)).abs() < 1e-5);
+        }
+        assert!((fresnel_dielectric(1.0, 1.0 / 1.5) - 0.04).abs() < 1e-5);
+        assert_eq!(fresnel_dielectric(0.5, 1.0), 0.0);
+        for (cos_i, from_outside) in [(1.0, true), (0.5, true), (0.1, true), (0.9, false)] {
+            let eta = if from_outside { 1.0 / 1.5 } else { 1.5 };
+            let expected = fresnel_dielectric(cos_i, eta);
+            assert!((dielectric_reflectance(1.5, cos_i, from_outside) - expected).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn dielectric_total_internal_reflection() {
+        // the critical angle of 1.5 is about 41.8 degrees
+        let critical = (1.0f32 - 1.0 / (1.5 * 1.5)).sqrt();
+        assert_eq!(fresnel_dielectric(critical - 0.01, 1.5), 1.0);
+        assert!(fresnel_dielectric(critical + 0.01, 1.5) < 1.0);
+        assert_eq!(dielectric_reflectance(1.5, 0.5, false), 1.0);
+        assert_eq!(dielectric_reflectance(1.5, 0.05, false), 1.0);
+    }
+
+    #[test]
+    fn absorbing_dielectric_attenuation() {
+        let material = AbsorbingDielectric { ref_idx: 1.5, absorption: vec3(0.0, 0.5, 2.0) };
+        let ray = Ray3 { pos: Vec3::ZERO, dir: vec3(0.0, 0.0, 2.0), time: 0.0 };
+        for from_outside in [true, false] {
+            let hit = HitRecord3 { is_hit: true, t: 1.5, from_outside, nor: -Vec3::Z, ..Default::default() };
+            let scattered = material.scatter(&mut SRng::new(1), ray, hit);
+            let expected = if from_outside { Vec3::ONE } else { Beer_Lambert3(material.absorption, 3.0) };
+            assert!((scattered.attenuation.0 - expected).length() < 1e-5);
+            assert!(scattered.attenuation.0.max_element() <= 1.0);
+        }
+    }
+
+    const GOLD: RoughConductor = RoughConductor {
+        eta: const_vec3!([0.143, 0.374, 1.442]),
+        k: const_vec3!([3.983, 2.385, 1.603]),
+        roughness: 0.4,
+    };
+
+    // checks `scatter` against `eval` and `pdf` from a few directions, returns the mean attenuation
+    fn check_eval_pdf(integrate: bool, material: impl AbstractMaterial + Copy) -> Vec3 {
+        let mut rng = SRng::new(11);
+        let mut uniform = rand_pcg::Pcg32::seed_from_u64(11);
+        let n = 50000;
+        let mut mean = Vec3::ZERO;
+        for (i, dir) in [vec3(0.0, 0.0, -1.0), vec3(0.6, 0.0, -0.8), vec3(-0.3, 0.9, -0.3).normalize()].iter().enumerate() {
+            let ray = Ray3 { pos: -*dir, dir: *dir, time: 0.0 };
+            let hit = HitRecord3 { is_hit: true, t: 1.0, from_outside: i != 1, nor: if i == 2 { -Vec3::Z } else { Vec3::Z }, ..Default::default() };
+            let mut valid = 0;
+            for _ in 0..n {
+                let scattered = material.scatter(&mut rng, ray, hit);
+                mean += scattered.attenuation.0;
+                if scattered.is_scattered {
+                    valid += 1;
+                    let wi = scattered.ray.dir;
+                    let p = material.pdf(-*dir, wi, &hit);
+                    let expected = material.eval(-*dir, wi, &hit).0 / p;
+                    assert!(p > 0.0);
+                    assert!(
+                        (expected - scattered.attenuation.0).length() < 1e-3 * (1.0 + expected.length()),
+                        "{} {} {:?} {}",
+                        expected,
+                        scattered.attenuation.0,
+                        dir,
+                        wi
+                    );
+                }
+            }
+            // the pdf integrated over the sphere is the probability to scatter
+            if integrate {
+                let mut integrated = 0.0;
+                for _ in 0..n * 4 {
+                    let z = 1.0 - 2.0 * uniform.gen::<f32>();
+                    let phi = 2.0 * core::f32::consts::PI * uniform.gen::<f32>();
+                    let r = (1.0 - z * z).sqrt();
+                    integrated += material.pdf(-*dir, vec3(r * phi.cos(), r * phi.sin(), z), &hit);
+                }
+                let integrated = integrated * 4.0 * core::f32::consts::PI / (n * 4) as f32;
+                assert!((integrated - valid as f32 / n as f32).abs() < 0.05, "{} {}", integrated, valid);
+            }
+        }
+        mean / (3 * n) as f32
+    }
+
+    #[test]
+    fn eval_and_pdf() {
+        let albedo = RgbLinear(vec3(0.2, 0.4, 0.8));
+        let lambertian = check_eval_pdf(true, Lambertian { albedo });
+        assert!((lambertian - albedo.0).length() < 1e-2);
+        check_eval_pdf(true, Isotropic { albedo });
+        check_eval_pdf(true, HenyeyGreenstein { albedo, g: 0.5 });
+        // deltas
+        let hit = HitRecord3 { is_hit: true, t: 1.0, from_outside: true, nor: Vec3::Z, ..Default::default() };
+        let (wo, wi) = (vec3(0.6, 0.0, 0.8), vec3(-0.6, 0.0, 0.8));
+        assert_eq!(Metal { albedo, fuzz: 0.0 }.pdf(wo, wi, &hit), 0.0);
+        assert_eq!(Dielectric { ref_idx: 1.5 }.eval(wo, wi, &hit).0, Vec3::ZERO);
+    }
+
+    #[test]
+    fn microfacet_eval_and_pdf() {
+        let m = GOLD;
+        let gold = check_eval_pdf(true, m);
+        assert!(gold.x > gold.z && gold.x > 0.5);
+        let m = RoughDielectric { ref_idx: 1.5, roughness: 0.5 };
+        check_eval_pdf(true, m);
+        let m = Plastic { albedo: RgbLinear(vec3(0.9, 0.5, 0.1)), ref_idx: 1.5, roughness: 0.3 };
+        check_eval_pdf(true, m);
+    }
+
+    #[test]
+    fn microfacet_energy() {
+        // almost smooth glass keeps almost everything
+        let m = RoughDielectric { ref_idx: 1.5, roughness: 0.05 };
+        // too sharp to integrate the pdf by uniform samples
+        let glass = check_eval_pdf(false, m);
+        assert!(glass.min_element() > 0.97, "{}", glass);
+        // a white plastic loses only to the coat
+        let m = Plastic { albedo: RgbLinear(Vec3::ONE), ref_idx: 1.5, roughness: 0.3 };
+        let white = check_eval_pdf(true, m);
+        assert!(white.min_element() > 0.8 && white.max_element() <= 1.01, "{}", white);
+    }
+
+    #[test]
+    fn principled_eval_and_pdf() {
+        let base_color = RgbLinear(vec3(0.9, 0.5, 0.1));
+        let principled = Principled { base_color, ..Default::default() };
+        check_eval_pdf(true, principled);
+        check_eval_pdf(true, Principled { metallic: 1.0, anisotropic: 0.8, ..principled });
+        check_eval_pdf(true, Principled { transmission: 1.0, ..principled });
+        // a glossy clear coat is too sharp to integrate the pdf by uniform samples
+        check_eval_pdf(true, Principled { sheen: 1.0, clearcoat: 1.0, clearcoat_gloss: 0.0, specular_tint: 1.0, ..principled });
+        check_eval_pdf(false, Principled { clearcoat: 1.0, ..principled });
+        check_eval_pdf(
+            true,
+            Principled { metallic: 0.3, transmission: 0.5, clearcoat: 0.5, clearcoat_gloss: 0.0, anisotropic: 0.5, roughness: 0.7, ..principled },
+        );
+    }
+
+    #[test]
+    fn principled_furnace() {
+        // with a white base color it may lose energy but never gains any
+        let white = Principled { base_color: RgbLinear(Vec3::ONE), ..Default::default() };
+        for roughness in [0.1, 0.5, 1.0] {
+            for m in [
+                Principled { roughness, ..white },
+                Principled { roughness, sheen: 1.0, ..white },
+                Principled { roughness, specular: 1.0, clearcoat: 1.0, clearcoat_gloss: 0.0, ..white },
+                Principled { roughness, metallic: 1.0, anisotropic: 0.5, ..white },
+                Principled { roughness, transmission: 1.0, clearcoat: 1.0, ..white },
+                Principled { roughness, metallic: 0.5, transmission: 0.5, sheen: 0.5, clearcoat: 0.5, ..white },
+            ] {
+                let mean = check_eval_pdf(false, m);
+                assert!(mean.max_element() <= 1.01, "{:?} {}", m, mean);
+                // single scattering microfacets lose some when rough
+                assert!(mean.min_element() > 0.3, "{:?} {}", m, mean);
+            }
+        }
+        // the lossless ones keep almost everything
+        let metal = check_eval_pdf(false, Principled { roughness: 0.1, metallic: 1.0, ..white });
+        assert!(metal.min_element() > 0.97, "{}", metal);
+        let glass = check_eval_pdf(false, Principled { roughness: 0.1, transmission: 1.0, ..white });
+        assert!(glass.min_element() > 0.97, "{}", glass);
+    }
+
+    #[test]
+    fn gpu_material_same_as_material() {
+        let albedo = RgbLinear(vec3(0.2, 0.4, 0.8));
+        let materials = [
+            Material::Lambertian(Lambertian { albedo }),
+            Material::Metal(Metal { albedo, fuzz: 0.3 }),
+            Material::Dielectric(Dielectric { ref_idx: 1.5 }),
+            Material::AbsorbingDielectric(AbsorbingDielectric { ref_idx: 1.3, absorption: vec3(0.1, 0.2, 0.3) }),
+            Material::Isotropic(Isotropic { albedo }),
+            Material::HenyeyGreenstein(HenyeyGreenstein { albedo, g: 0.6 }),
+            Material::DiffuseLight(DiffuseLight { emit: RgbLinear(vec3(4.0, 2.0, 1.0)) }),
+            Material::RoughConductor(GOLD),
+            Material::RoughDielectric(RoughDielectric { ref_idx: 1.5, roughness: 0.3 }),
+            Material::Plastic(Plastic { albedo, ref_idx: 1.5, roughness: 0.2 }),
+            // not multiples of 1 / 255, so they would change if quantized
+            Material::Principled(Principled {
+                base_color: albedo,
+                metallic: 0.123,
+                roughness: 0.377,
+                anisotropic: 0.61,
+                specular: 0.5,
+                specular_tint: 0.03,
+                sheen: 0.999,
+                sheen_tint: 0.4142,
+                clearcoat: 0.8,
+                clearcoat_gloss: 0.271,
+                transmission: 0.333,
+            }),
+            Material::Principled(Principled { base_color: albedo, ..Default::default() }),
+        ];
+        let packed = pack_materials(&materials);
+        assert_eq!(bytemuck::cast_slice::<GpuMaterial, u8>(&packed).len(), materials.len() * 64);
+        let ray = Ray3 { pos: vec3(0.0, 0.0, 1.0), dir: vec3(1.0, -2.0, -2.0) / 3.0, time: 0.5 };
+        for (material, gpu) in materials.iter().zip(&packed) {
+            assert_eq!(format!("{:?}", Material::from(*gpu)), format!("{:?}", material));
+            for seed in 0..100 {
+                let hit = || HitRecord3 { is_hit: true, t: 1.0, from_outside: seed % 2 == 0, nor: Vec3::Z, ..Default::default() };
+                let expected = material.scatter(&mut SRng::new(seed), ray, hit());
+                let got = gpu.scatter(&mut SRng::new(seed), ray, hit());
+                assert_eq!(expected.attenuation.0, got.attenuation.0);
+                assert_eq!(expected.emission.0, got.emission.0);
+                assert_eq!(expected.is_scattered, got.is_scattered);
+                if got.is_scattered {
+                    assert_eq!(expected.ray.pos, got.ray.pos);
+                    assert_eq!(expected.ray.dir, got.ray.dir);
+                    assert_eq!(got.ray.time, 0.5);
+                    let (wo, wi) = (-ray.dir, got.ray.dir);
+                    assert_eq!(material.eval(wo, wi, &hit()).0, gpu.eval(wo, wi, &hit()).0);
+                    assert_eq!(material.pdf(wo, wi, &hit()), gpu.pdf(wo, wi, &hit()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn textured_materials() {
+        let checker = CheckerTexture {
+            even: RgbLinear(Vec3::splat(0.8)),
+            odd: RgbLinear(Vec3::splat(0.2)),
+            scale: 1.0,
+        };
+        let mut rng = SRng::new(3);
+        let ray = Ray3 { pos: vec3(0.0, 0.0, 1.0), dir: -Vec3::Z, time: 0.0 };
+        for (point, expected) in [(vec3(0.5, 0.5, 0.0), 0.8), (vec3(1.5, 0.5, 0.0), 0.2)] {
+            let hit = HitRecord3 { is_hit: true, t: 1.0, from_outside: true, nor: Vec3::Z, point, uv: Vec2::ZERO };
+            let materials: [Material<CheckerTexture>; 3] = [
+                Material::Lambertian(Lambertian { albedo: checker }),
+                Material::Metal(Metal { albedo: checker, fuzz: 0.0 }),
+                Material::Plastic(Plastic { albedo: checker, ref_idx: 1.5, roughness: 0.5 }),
+            ];
+            for material in materials {
+                let wi = vec3(0.3, 0.0, 1.0).normalize();
+                let eval = material.eval(Vec3::Z, wi, &hit).0;
+                assert!((eval.x - eval.z).abs() < 1e-6);
+                let scattered = material.scatter(&mut rng, ray, hit);
+                assert!(scattered.is_scattered);
+                if let Material::Plastic(_) = material {
+                    continue;
+                }
+                assert!((scattered.attenuation.0 - Vec3::splat(expected)).length() < 1e-5);
+            }
+            let light = DiffuseLight { emit: checker };
+            assert_eq!(light.scatter(&mut rng, ray, hit).emission.0, Vec3::splat(expected));
+            assert!(light.emit.constant().is_none());
+        }
+    }
+}
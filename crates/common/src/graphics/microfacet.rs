@@ -0,0 +1,196 @@
+use crate::math::*;
+
+/**
+ * GGX (Trowbridge-Reitz) in a local frame where the normal is +z, "Microfacet Models for
+ * Refraction through Rough Surfaces", Walter et al. `alpha` is the square of the roughness along
+ * x and y, they differ for anisotropic ones
+ */
+#[derive(Copy, Clone)]
+pub struct Ggx {
+    pub alpha: Vec2,
+}
+
+impl Ggx {
+    pub fn from_roughness(roughness: f32) -> Ggx {
+        // a perfectly smooth one is a delta, which `eval` can't hit
+        Ggx { alpha: Vec2::splat((roughness * roughness).max(1e-3)) }
+    }
+
+    /**
+     * stretched along x by `anisotropic` in `[0, 1]`, the mapping of the Disney BRDF
+     */
+    pub fn anisotropic(roughness: f32, anisotropic: f32) -> Ggx {
+        let aspect = (1.0 - 0.9 * anisotropic).sqrt();
+        let alpha = roughness * roughness;
+        Ggx { alpha: vec2(alpha / aspect, alpha * aspect).max(Vec2::splat(1e-3)) }
+    }
+
+    /// the distribution of microfacet normals
+    pub fn d(self, h: Vec3) -> f32 {
+        if h.z <= 0.0 {
+            return 0.0;
+        }
+        let x = h.x / self.alpha.x;
+        let y = h.y / self.alpha.y;
+        let t = x * x + y * y + h.z * h.z;
+        1.0 / (core::f32::consts::PI * self.alpha.x * self.alpha.y * t * t)
+    }
+
+    pub fn lambda(self, w: Vec3) -> f32 {
+        let cos2 = w.z * w.z;
+        if cos2 <= 0.0 {
+            return 0.0;
+        }
+        let x = self.alpha.x * w.x;
+        let y = self.alpha.y * w.y;
+        (((x * x + y * y) / cos2 + 1.0).sqrt() - 1.0) * 0.5
+    }
+
+    /// the masking of one direction
+    pub fn g1(self, w: Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// the height correlated masking and shadowing
+    pub fn g2(self, wo: Vec3, wi: Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /**
+     * the distribution of normals visible from `wo`, it needs `wo.z > 0`
+     */
+    pub fn visible_d(self, wo: Vec3, h: Vec3) -> f32 {
+        self.g1(wo) * wo.dot(h).max(0.0) * self.d(h) / wo.z
+    }
+
+    /**
+     * samples `visible_d`, "Sampling the GGX Distribution of Visible Normals", Heitz
+     */
+    pub fn sample_visible(self, wo: Vec3, u: Vec2) -> Vec3 {
+        let v = vec3(self.alpha.x * wo.x, self.alpha.y * wo.y, wo.z).normalize();
+        let len2 = v.x * v.x + v.y * v.y;
+        let t1 = if len2 > 0.0 {
+            vec3(-v.y, v.x, 0.0) / len2.sqrt()
+        } else {
+            Vec3::X
+        };
+        let t2 = v.cross(t1);
+        let r = u.x.sqrt();
+        let phi = 2.0 * core::f32::consts::PI * u.y;
+        let p1 = r * phi.cos();
+        let p2 = r * phi.sin();
+        let s = 0.5 * (1.0 + v.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * p2;
+        let n = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * v;
+        vec3(self.alpha.x * n.x, self.alpha.y * n.y, n.z.max(1e-6)).normalize()
+    }
+}
+
+/**
+ * the unpolarized fresnel reflectance of a conductor with complex refraction index `eta + i k`
+ */
+pub fn fresnel_conductor(cos_i: f32, eta: f32, k: f32) -> f32 {
+    let cos2 = (cos_i * cos_i).min(1.0);
+    let sin2 = 1.0 - cos2;
+    let t0 = eta * eta - k * k - sin2;
+    let a2b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+    let a = (0.5 * (a2b2 + t0)).max(0.0).sqrt();
+    let t1 = a2b2 + cos2;
+    let t2 = 2.0 * cos_i * a;
+    let r_s = (t1 - t2) / (t1 + t2);
+    let t3 = cos2 * a2b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let r_p = r_s * (t3 - t4) / (t3 + t4);
+    (r_s + r_p) * 0.5
+}
+
+pub fn fresnel_conductor3(cos_i: f32, eta: Vec3, k: Vec3) -> Vec3 {
+    vec3(
+        fresnel_conductor(cos_i, eta.x, k.x),
+        fresnel_conductor(cos_i, eta.y, k.y),
+        fresnel_conductor(cos_i, eta.z, k.z),
+    )
+}
+
+/**
+ * a local frame around the normal `n`, `to_local` and `to_world` move directions between them
+ */
+#[derive(Copy, Clone)]
+pub struct Frame {
+    pub t: Vec3,
+    pub b: Vec3,
+    pub n: Vec3,
+}
+
+impl Frame {
+    pub fn new(n: Vec3) -> Frame {
+        let (t, b) = n.orthonormal_basis();
+        Frame { t, b, n }
+    }
+
+    pub fn to_local(self, v: Vec3) -> Vec3 {
+        vec3(v.dot(self.t), v.dot(self.b), v.dot(self.n))
+    }
+
+    pub fn to_world(self, v: Vec3) -> Vec3 {
+        self.t * v.x + self.b * v.y + self.n * v.z
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn ggx_normalized() {
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(1);
+        let wo = vec3(0.6, 0.0, 0.8);
+        let n = 400000;
+        for (roughness, anisotropic) in [(0.3, 0.0), (0.6, 0.0), (1.0, 0.0), (0.5, 0.8)] {
+            let ggx = Ggx::anisotropic(roughness, anisotropic);
+            let (mut projected, mut visible) = (0.0, 0.0);
+            for _ in 0..n {
+                let h = uniform_sphere(vec2(rng.gen(), rng.gen()));
+                projected += ggx.d(h) * h.z.max(0.0);
+                visible += ggx.visible_d(wo, h);
+            }
+            let scale = 4.0 * core::f32::consts::PI / n as f32;
+            assert!((projected * scale - 1.0).abs() < 0.03, "{}", projected * scale);
+            assert!((visible * scale - 1.0).abs() < 0.03, "{}", visible * scale);
+        }
+    }
+
+    #[test]
+    fn ggx_sample_visible() {
+        // the mean of the sampled normals against the one integrated from `visible_d`
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(2);
+        let ggx = Ggx::anisotropic(0.7, 0.6);
+        let wo = vec3(-0.48, 0.6, 0.64);
+        let n = 400000;
+        let mut sampled = Vec3::ZERO;
+        let mut integrated = Vec3::ZERO;
+        for _ in 0..n {
+            let h = ggx.sample_visible(wo, vec2(rng.gen(), rng.gen()));
+            assert!(h.z > 0.0 && (h.length() - 1.0).abs() < 1e-4);
+            sampled += h;
+            let h = uniform_sphere(vec2(rng.gen(), rng.gen()));
+            integrated += h * ggx.visible_d(wo, h);
+        }
+        let sampled = sampled / n as f32;
+        let integrated = integrated * 4.0 * core::f32::consts::PI / n as f32;
+        assert!((sampled - integrated).length() < 0.02, "{} {}", sampled, integrated);
+    }
+
+    #[test]
+    fn conductor_fresnel() {
+        // gold at about 600nm
+        let (eta, k) = (0.2, 3.0);
+        let normal = ((eta - 1.0) * (eta - 1.0) + k * k) / ((eta + 1.0) * (eta + 1.0) + k * k);
+        assert!((fresnel_conductor(1.0, eta, k) - normal).abs() < 1e-5);
+        assert!((fresnel_conductor(0.0, eta, k) - 1.0).abs() < 1e-5);
+        // without absorption it is a dielectric
+        let dielectric = crate::graphics::material::fresnel_dielectric(0.6, 1.0 / 1.5);
+        assert!((fresnel_conductor(0.6, 1.5, 0.0) - dielectric).abs() < 1e-5);
+    }
+}
@@ -1,4 +1,8 @@
 pub mod color;
 pub use color::*;
 pub mod beer_lambert;
-pub mod material;
\ No newline at end of file
+pub mod material;
+pub mod microfacet;
+pub mod texture;
+#[cfg(not(target_arch = "spirv"))]
+pub mod integrator;
@@ -0,0 +1,275 @@
+use super::color::*;
+use crate::math::*;
+
+/**
+ * a color looked up at a surface point and its `uv`. materials take one wherever they take a
+ * color, a `RgbLinear` is a constant one
+ */
+pub trait AbstractTexture {
+    fn value(self, uv: Vec2, point: Vec3) -> RgbLinear;
+
+    /**
+     * the color if it is the same everywhere. only lights with a constant emission are sampled
+     * by the integrator
+     */
+    fn constant(self) -> Option<RgbLinear>
+    where
+        Self: Sized,
+    {
+        None
+    }
+}
+
+impl AbstractTexture for RgbLinear {
+    fn value(self, _uv: Vec2, _point: Vec3) -> RgbLinear {
+        self
+    }
+
+    fn constant(self) -> Option<RgbLinear> {
+        Some(self)
+    }
+}
+
+/**
+ * 3D checker cubes of size `1 / scale`, so it doesn't need uvs
+ */
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[derive(Copy, Clone)]
+pub struct CheckerTexture {
+    pub even: RgbLinear,
+    pub odd: RgbLinear,
+    pub scale: f32,
+}
+
+impl AbstractTexture for CheckerTexture {
+    fn value(self, _uv: Vec2, point: Vec3) -> RgbLinear {
+        let p = (point * self.scale).floor();
+        if (p.x as i32 + p.y as i32 + p.z as i32) & 1 == 0 {
+            self.even
+        } else {
+            self.odd
+        }
+    }
+}
+
+// pcg hash, "Hash Functions for GPU Rendering", Jarzynski & Olano
+fn hash(x: u32) -> u32 {
+    let state = x.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28).wrapping_add(4))) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+
+// the gradient of the lattice point dotted with `p`, from "Improving Noise", Perlin
+fn gradient(h: u32, p: Vec3) -> f32 {
+    let h = h & 15;
+    let u = if h < 8 { p.x } else { p.y };
+    let v = if h < 4 {
+        p.y
+    } else if h == 12 || h == 14 {
+        p.x
+    } else {
+        p.z
+    };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+fn lattice_gradient(i: Vec3, p: Vec3) -> f32 {
+    let h = hash(i.x as i32 as u32 ^ hash(i.y as i32 as u32 ^ hash(i.z as i32 as u32)));
+    gradient(h, p)
+}
+
+/**
+ * Perlin gradient noise, in about `[-1, 1]` and zero at integer points. the gradients are hashed
+ * from the lattice points instead of a permutation table
+ */
+pub fn perlin(p: Vec3) -> f32 {
+    let i = p.floor();
+    let f = p - i;
+    let fade = f * f * f * (f * (f * 6.0 - 15.0) + 10.0);
+    let corner = |x: f32, y: f32, z: f32| lattice_gradient(i + vec3(x, y, z), f - vec3(x, y, z));
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let x00 = lerp(corner(0.0, 0.0, 0.0), corner(1.0, 0.0, 0.0), fade.x);
+    let x10 = lerp(corner(0.0, 1.0, 0.0), corner(1.0, 1.0, 0.0), fade.x);
+    let x01 = lerp(corner(0.0, 0.0, 1.0), corner(1.0, 0.0, 1.0), fade.x);
+    let x11 = lerp(corner(0.0, 1.0, 1.0), corner(1.0, 1.0, 1.0), fade.x);
+    lerp(lerp(x00, x10, fade.y), lerp(x01, x11, fade.y), fade.z)
+}
+
+/**
+ * the sum of `depth` octaves of `|perlin|`, each with double the frequency and half the weight
+ */
+pub fn turbulence(p: Vec3, depth: u32) -> f32 {
+    let mut sum = 0.0;
+    let mut p = p;
+    let mut weight = 1.0;
+    for _ in 0..depth {
+        sum += weight * perlin(p).abs();
+        weight *= 0.5;
+        p *= 2.0;
+    }
+    sum
+}
+
+/**
+ * Perlin noise scaled by `scale`. with a `turbulence` depth it is the marble of "The Next Week",
+ * stripes along z bent by the turbulence
+ */
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[derive(Copy, Clone)]
+pub struct NoiseTexture {
+    pub color: RgbLinear,
+    pub scale: f32,
+    pub turbulence: u32,
+}
+
+impl AbstractTexture for NoiseTexture {
+    fn value(self, _uv: Vec2, point: Vec3) -> RgbLinear {
+        let p = point * self.scale;
+        let t = if self.turbulence == 0 {
+            0.5 * (1.0 + perlin(p))
+        } else {
+            0.5 * (1.0 + (p.z + 10.0 * turbulence(p, self.turbulence)).sin())
+        };
+        RgbLinear(self.color.0 * t)
+    }
+}
+
+/**
+ * 8 bit sRGB rgba pixels, row by row from the top. `v` goes up and it repeats outside `[0, 1]`.
+ * texels are decoded to linear before they are filtered bilinearly
+ */
+#[cfg(not(target_arch = "spirv"))]
+#[derive(Copy, Clone, Debug)]
+pub struct ImageTexture<'a> {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: &'a [[u8; 4]],
+}
+
+#[cfg(not(target_arch = "spirv"))]
+impl<'a> ImageTexture<'a> {
+    fn texel(self, x: i32, y: i32) -> Vec3 {
+        let x = x.rem_euclid(self.width as i32) as usize;
+        let y = y.rem_euclid(self.height as i32) as usize;
+        let [r, g, b, _] = self.pixels[y * self.width as usize + x];
+        let decode = |c: u8| (c as f32 / 255.0).nonlinear_to_linear_srgb();
+        vec3(decode(r), decode(g), decode(b))
+    }
+}
+
+#[cfg(not(target_arch = "spirv"))]
+impl<'a> From<&'a crate::geometry::load::LoadedImage> for ImageTexture<'a> {
+    fn from(image: &'a crate::geometry::load::LoadedImage) -> Self {
+        ImageTexture {
+            width: image.width,
+            height: image.height,
+            pixels: &image.pixels,
+        }
+    }
+}
+
+#[cfg(not(target_arch = "spirv"))]
+impl<'a> AbstractTexture for ImageTexture<'a> {
+    fn value(self, uv: Vec2, _point: Vec3) -> RgbLinear {
+        // relative to the texel centers
+        let x = uv.x * self.width as f32 - 0.5;
+        let y = (1.0 - uv.y) * self.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i32, y0 as i32);
+        let top = self.texel(x0, y0).lerp(self.texel(x0 + 1, y0), tx);
+        let bottom = self.texel(x0, y0 + 1).lerp(self.texel(x0 + 1, y0 + 1), tx);
+        RgbLinear(top.lerp(bottom, ty))
+    }
+}
+
+/**
+ * any of the textures, for materials that mix them
+ */
+#[cfg(not(target_arch = "spirv"))]
+#[derive(Copy, Clone, Debug)]
+pub enum Texture<'a> {
+    Constant(RgbLinear),
+    Checker(CheckerTexture),
+    Noise(NoiseTexture),
+    Image(ImageTexture<'a>),
+}
+
+#[cfg(not(target_arch = "spirv"))]
+impl<'a> AbstractTexture for Texture<'a> {
+    fn value(self, uv: Vec2, point: Vec3) -> RgbLinear {
+        match self {
+            Texture::Constant(t) => t.value(uv, point),
+            Texture::Checker(t) => t.value(uv, point),
+            Texture::Noise(t) => t.value(uv, point),
+            Texture::Image(t) => t.value(uv, point),
+        }
+    }
+
+    fn constant(self) -> Option<RgbLinear> {
+        match self {
+            Texture::Constant(t) => Some(t),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn checker() {
+        let checker = CheckerTexture {
+            even: RgbLinear(Vec3::ONE),
+            odd: RgbLinear(Vec3::ZERO),
+            scale: 2.0,
+        };
+        let at = |p: Vec3| checker.value(Vec2::ZERO, p).0.x;
+        assert_eq!(at(vec3(0.1, 0.1, 0.1)), 1.0);
+        assert_eq!(at(vec3(0.6, 0.1, 0.1)), 0.0);
+        assert_eq!(at(vec3(0.6, 0.6, 0.1)), 1.0);
+        assert_eq!(at(vec3(-0.1, 0.1, 0.1)), 0.0);
+        assert_eq!(at(vec3(-0.1, -0.1, -0.1)), 0.0);
+    }
+
+    #[test]
+    fn perlin_noise() {
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(1);
+        let n = 10000;
+        let mut sum = 0.0;
+        let mut differs = false;
+        for _ in 0..n {
+            let p = (vec3(rng.gen(), rng.gen(), rng.gen()) * 2.0 - 1.0) * 50.0;
+            let v = perlin(p);
+            assert!(v.abs() <= 1.1);
+            assert_eq!(perlin(p.floor()), 0.0);
+            // continuous
+            assert!((perlin(p + Vec3::splat(1e-3)) - v).abs() < 1e-2);
+            differs |= (perlin(p + Vec3::splat(0.5)) - v).abs() > 0.1;
+            sum += v;
+            let t = turbulence(p, 5);
+            assert!((0.0..2.1).contains(&t));
+        }
+        assert!(differs);
+        assert!((sum / n as f32).abs() < 0.02);
+    }
+
+    #[test]
+    fn image_bilinear() {
+        // 2 x 1, black and white in sRGB
+        let pixels = [[0, 0, 0, 255], [255, 128, 255, 255]];
+        let image = ImageTexture { width: 2, height: 1, pixels: &pixels };
+        let at = |u: f32| image.value(vec2(u, 0.5), Vec3::ZERO).0;
+        let white = vec3(1.0, (128.0f32 / 255.0).nonlinear_to_linear_srgb(), 1.0);
+        // texel centers
+        assert!(at(0.25).length() < 1e-6);
+        assert!((at(0.75) - white).length() < 1e-6);
+        // filtered after decoding
+        assert!((at(0.5) - white * 0.5).length() < 1e-6);
+        // it repeats
+        assert!((at(1.0) - white * 0.5).length() < 1e-6);
+        assert!((at(-0.25) - white).length() < 1e-6);
+    }
+}
@@ -9,9 +9,4 @@ pub mod math;
 pub use math::*;
 pub mod graphics;
 pub mod shader;
-
-
-// legacy code
-
-// #[cfg(not(target_arch = "spirv"))]
-// pub mod geometry;
\ No newline at end of file
+pub mod geometry;
@@ -96,6 +96,29 @@ impl Aabb3 {
         }
     }
 
+    // touching boxes don't overlap
+    pub fn overlaps(&self, b: &Aabb3) -> bool {
+        self.min.x < b.max.x
+            && b.min.x < self.max.x
+            && self.min.y < b.max.y
+            && b.min.y < self.max.y
+            && self.min.z < b.max.z
+            && b.min.z < self.max.z
+    }
+
+    pub fn closest_point(&self, p: Vec3) -> Vec3 {
+        p.max(self.min).min(self.max)
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let d = (self.max - self.min).max(Vec3::ZERO);
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
     pub fn hit_fast1(&self, ray: &Ray3) -> f32 {
         let rdinv = 1.0 / ray.dir;
         let t1 = (self.min - ray.pos) * rdinv;
@@ -162,6 +185,7 @@ impl Aabb3 {
                     candidate.from_outside = false;
                     candidate.nor = axis;
                     candidate.t = t0;
+                    candidate.point = point;
                 }
             } else if t_min < t1 && t_max1 > t1 {
                 let point = ray.at(t1);
@@ -170,6 +194,7 @@ impl Aabb3 {
                     candidate.from_outside = true;
                     candidate.nor = axis;
                     candidate.t = t1;
+                    candidate.point = point;
                 }
             } else if t_max <= t0 || t_min >= t1 {
                 return candidate;
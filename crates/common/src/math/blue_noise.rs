@@ -0,0 +1,282 @@
+use super::sampler::*;
+use super::srng::*;
+use super::vec::*;
+
+/**
+ * a tileable blue noise texture, `ranks` has `size * size` different numbers in `[0, size * size)`
+ * by rows, like from `void_and_cluster`. as a storage buffer it works in shaders too.
+ *
+ * as a `Sampler` the index is the frame: each frame adds the golden ratio to every pixel, so a
+ * pixel goes evenly over `[0, 1)` in time while each frame stays blue noise. each dimension reads
+ * the texture from a different offset so they don't line up
+ */
+#[derive(Copy, Clone)]
+pub struct BlueNoise<'a> {
+    pub size: u32,
+    pub ranks: &'a [u32],
+}
+
+// the golden ratio in 32 bit fixed point
+const GOLDEN_RATIO: u32 = 0x9e3779b9;
+
+impl<'a> BlueNoise<'a> {
+    // in 32 bit fixed point, at the centers of the `size * size` steps
+    fn fixed(&self, pixel: UVec2) -> u32 {
+        let pixel = pixel % self.size;
+        let step = (u32::MAX / (self.size * self.size)).wrapping_add(1);
+        self.ranks[(pixel.y * self.size + pixel.x) as usize] * step + step / 2
+    }
+
+    /**
+     * the texture itself, wrapped around
+     */
+    pub fn value(&self, pixel: UVec2) -> f32 {
+        to_unit_float(self.fixed(pixel))
+    }
+}
+
+impl<'a> Sampler for BlueNoise<'a> {
+    fn sample(&self, pixel: UVec2, index: u32, dimension: u32) -> f32 {
+        let offset = hash(dimension);
+        let pixel = pixel + uvec2(offset, hash(offset)) % self.size;
+        to_unit_float(self.fixed(pixel).wrapping_add(index.wrapping_mul(GOLDEN_RATIO)))
+    }
+}
+
+// the energy a pixel gets from a pixel `d` away, a gaussian wrapped around the texture
+#[cfg(not(target_arch = "spirv"))]
+fn energy_filter(size: u32) -> Vec<f32> {
+    let sigma = 1.5f32;
+    let mut filter = Vec::with_capacity((size * size) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let d = vec2(x.min(size - x) as f32, y.min(size - y) as f32);
+            filter.push((-d.length_squared() / (2.0 * sigma * sigma)).exp());
+        }
+    }
+    filter
+}
+
+// the pixels that are on, with the energy they give to every pixel
+#[cfg(not(target_arch = "spirv"))]
+struct Pattern<'a> {
+    size: u32,
+    filter: &'a [f32],
+    on: Vec<bool>,
+    energy: Vec<f32>,
+}
+
+#[cfg(not(target_arch = "spirv"))]
+impl<'a> Pattern<'a> {
+    fn new(size: u32, filter: &'a [f32]) -> Self {
+        let n = (size * size) as usize;
+        Pattern { size, filter, on: vec![false; n], energy: vec![0.0; n] }
+    }
+
+    fn set(&mut self, i: usize, on: bool) {
+        self.on[i] = on;
+        let sign = if on { 1.0 } else { -1.0 };
+        let size = self.size as usize;
+        let (px, py) = (i % size, i / size);
+        for y in 0..size {
+            let dy = (y + size - py) % size;
+            for x in 0..size {
+                let dx = (x + size - px) % size;
+                self.energy[y * size + x] += sign * self.filter[dy * size + dx];
+            }
+        }
+    }
+
+    // the pixel that is on with the most energy around it
+    fn tightest_cluster(&self) -> usize {
+        let on = (0..self.on.len()).filter(|&i| self.on[i]);
+        on.max_by(|&a, &b| self.energy[a].partial_cmp(&self.energy[b]).unwrap()).unwrap()
+    }
+
+    // the pixel that is off with the least energy around it
+    fn largest_void(&self) -> usize {
+        let off = (0..self.on.len()).filter(|&i| !self.on[i]);
+        off.min_by(|&a, &b| self.energy[a].partial_cmp(&self.energy[b]).unwrap()).unwrap()
+    }
+}
+
+/**
+ * the ranks of a `size * size` tileable blue noise texture, "The void-and-cluster method for
+ * dither array generation", Ulichney. it takes `O(size^4)`, so it is better cached with
+ * `load_or_generate_blue_noise`
+ */
+#[cfg(not(target_arch = "spirv"))]
+pub fn void_and_cluster(size: u32, seed: u32) -> Vec<u32> {
+    let n = (size * size) as usize;
+    let filter = energy_filter(size);
+    // a random pattern with a tenth of the pixels on, moved around until it is evenly spread
+    let mut pattern = Pattern::new(size, &filter);
+    let mut rng = SRng::new(seed);
+    let initial = (n / 10).max(1);
+    let mut count = 0;
+    while count < initial {
+        let i = rng.gen_u32() as usize % n;
+        if !pattern.on[i] {
+            pattern.set(i, true);
+            count += 1;
+        }
+    }
+    for _ in 0..n {
+        let cluster = pattern.tightest_cluster();
+        pattern.set(cluster, false);
+        let void = pattern.largest_void();
+        pattern.set(void, true);
+        if void == cluster {
+            break;
+        }
+    }
+    let mut ranks = vec![0; n];
+    // the pixels of the pattern get ranked by taking away the tightest cluster each time
+    let mut removing = Pattern::new(size, &filter);
+    for i in 0..n {
+        if pattern.on[i] {
+            removing.set(i, true);
+        }
+    }
+    for rank in (0..initial).rev() {
+        let cluster = removing.tightest_cluster();
+        removing.set(cluster, false);
+        ranks[cluster] = rank as u32;
+    }
+    // and the others by filling the largest void. past half of the pixels this is the same as
+    // taking the tightest cluster of the pixels that are off
+    for rank in initial..n {
+        let void = pattern.largest_void();
+        pattern.set(void, true);
+        ranks[void] = rank as u32;
+    }
+    ranks
+}
+
+/**
+ * `void_and_cluster` of `size`, read from `path` if it was already written there and generated
+ * and written there if not
+ */
+#[cfg(not(target_arch = "spirv"))]
+pub fn load_or_generate_blue_noise(path: &std::path::Path, size: u32) -> std::io::Result<Vec<u32>> {
+    let n = (size * size) as usize;
+    if let Ok(bytes) = std::fs::read(path) {
+        let words: Vec<u32> = bytes.chunks_exact(4).map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]])).collect();
+        if bytes.len() == 4 * (n + 1) && words[0] == size && words[1..].iter().all(|&r| (r as usize) < n) {
+            return Ok(words[1..].to_vec());
+        }
+    }
+    let ranks = void_and_cluster(size, 0);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let bytes: Vec<u8> = core::iter::once(size).chain(ranks.iter().copied()).flat_map(u32::to_le_bytes).collect();
+    std::fs::write(path, bytes)?;
+    Ok(ranks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the mean distance from the pixels ranked below `count` to the nearest other one, wrapped
+    // around
+    fn mean_nearest_distance(ranks: &[u32], size: u32, count: u32) -> f32 {
+        let points: Vec<UVec2> = (0..size * size).filter(|&i| ranks[i as usize] < count).map(|i| uvec2(i % size, i / size)).collect();
+        let mut sum = 0.0;
+        for (i, p) in points.iter().enumerate() {
+            let mut nearest = f32::MAX;
+            for (j, q) in points.iter().enumerate() {
+                let d = (p.as_ivec2() - q.as_ivec2()).abs().as_uvec2();
+                let d = d.min(UVec2::splat(size) - d);
+                if i != j {
+                    nearest = nearest.min(d.as_vec2().length());
+                }
+            }
+            sum += nearest;
+        }
+        sum / points.len() as f32
+    }
+
+    // a random permutation, for white noise
+    fn white_ranks(size: u32) -> Vec<u32> {
+        let mut ranks: Vec<u32> = (0..size * size).collect();
+        let mut rng = SRng::new(7);
+        for i in (1..ranks.len()).rev() {
+            ranks.swap(i, rng.gen_u32() as usize % (i + 1));
+        }
+        ranks
+    }
+
+    // the mean difference between the values of pixels next to each other, with both the pixels
+    // and values wrapped around. for white noise it is 1/4
+    fn mean_neighbour_difference(size: u32, value: impl Fn(UVec2) -> f32) -> f32 {
+        let difference = |a: f32, b: f32| (a - b).abs().min(1.0 - (a - b).abs());
+        let mut sum = 0.0;
+        for y in 0..size {
+            for x in 0..size {
+                let v = value(uvec2(x, y));
+                sum += difference(v, value(uvec2((x + 1) % size, y))) + difference(v, value(uvec2(x, (y + 1) % size)));
+            }
+        }
+        sum / (2 * size * size) as f32
+    }
+
+    #[test]
+    fn void_and_cluster_is_blue_noise() {
+        let size = 32;
+        let ranks = void_and_cluster(size, 1);
+        let mut sorted = ranks.clone();
+        sorted.sort_unstable();
+        assert!(sorted.iter().enumerate().all(|(i, &r)| r == i as u32));
+        // every level is evenly spread, white noise has pixels right next to each other
+        let white = white_ranks(size);
+        for count in [size * size / 16, size * size / 8, size * size / 4] {
+            let blue = mean_nearest_distance(&ranks, size, count);
+            let white = mean_nearest_distance(&white, size, count);
+            assert!(blue > 1.2 * white, "{} {} {}", count, blue, white);
+        }
+        // and neighbours are far apart
+        let noise = BlueNoise { size, ranks: &ranks };
+        let difference = mean_neighbour_difference(size, |p| noise.value(p));
+        assert!(difference > 0.28, "{}", difference);
+        assert!((mean_neighbour_difference(size, |p| BlueNoise { size, ranks: &white }.value(p)) - 0.25).abs() < 0.02);
+        // the same for the same seed
+        assert_eq!(void_and_cluster(8, 3), void_and_cluster(8, 3));
+    }
+
+    #[test]
+    fn blue_noise_samples() {
+        let size = 16;
+        let ranks = void_and_cluster(size, 2);
+        let noise = BlueNoise { size, ranks: &ranks };
+        assert_eq!(noise.value(uvec2(3, 5)), noise.value(uvec2(3 + size, 5 + 2 * size)));
+        // each frame is a shift of the texture, and a pixel goes evenly over the frames
+        let pixel = uvec2(4, 7);
+        let mut frames: Vec<f32> = (0..64).map(|frame| noise.sample(pixel, frame, 0)).collect();
+        assert!(frames.iter().all(|&v| (0.0..1.0).contains(&v)));
+        frames.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let largest_gap = frames.windows(2).map(|w| w[1] - w[0]).fold(0.0, f32::max);
+        assert!(largest_gap < 0.05, "{}", largest_gap);
+        let difference = mean_neighbour_difference(size, |p| noise.sample(p, 5, 1));
+        assert!(difference > 0.28, "{}", difference);
+        // the dimensions are shifted apart
+        let same = (0..size * size).map(|i| uvec2(i % size, i / size)).filter(|&p| noise.sample(p, 0, 0) == noise.sample(p, 0, 1)).count();
+        assert!(same < 4, "{}", same);
+    }
+
+    #[test]
+    fn blue_noise_is_cached() {
+        let dir = std::env::temp_dir().join("common-math-blue-noise");
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("noise_8.bin");
+        let generated = load_or_generate_blue_noise(&path, 8).unwrap();
+        assert_eq!(generated, void_and_cluster(8, 0));
+        assert_eq!(std::fs::read(&path).unwrap().len(), 4 * 65);
+        // read back, and made again when it doesn't fit
+        assert_eq!(load_or_generate_blue_noise(&path, 8).unwrap(), generated);
+        assert_eq!(load_or_generate_blue_noise(&path, 4).unwrap(), void_and_cluster(4, 0));
+        std::fs::write(&path, [1, 2, 3]).unwrap();
+        assert_eq!(load_or_generate_blue_noise(&path, 4).unwrap(), void_and_cluster(4, 0));
+    }
+}
@@ -1,6 +1,8 @@
-use glam::UVec3;
+use glam::{UVec3, Vec3};
 
 use super::aabb::*;
+use super::axis::*;
+use super::ray::*;
 use super::sign::*;
 
 
@@ -13,6 +15,16 @@ pub struct BlockRayIntersectionInfo {
 pub trait Grid3 {
 }
 
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum TraverseRayError {
+    /// ran out of the iteration budget before the closure stopped
+    MaxIteration,
+    /// the ray didn't leave the current block
+    NoProgress,
+    ZeroDirection,
+}
+
 // #[derive(Copy, Clone)]
 // pub struct GidRayHitInfo<T : Copy + Clone> {
 //     pub in_at: BlockRayIntersectionInfo,
@@ -23,7 +35,9 @@ pub trait Grid3 {
 #[derive(Copy, Clone)]
 pub struct TraverseRayInfo<T : Copy + Clone> {
     pub in_info: BlockRayIntersectionInfo,
+    pub out_info: BlockRayIntersectionInfo,
     pub pos: UVec3,
+    pub level: u32,
     pub data: T
 }
 
@@ -31,4 +45,43 @@ impl <T : Copy + Clone> TraverseRayInfo<T> {
     pub fn first_face_aap(self) -> Aap3 {
         Aap3::from_unit_pos_face(self.pos.as_vec3(), self.in_info.mask.first_face())
     }
-}
\ No newline at end of file
+}
+
+/**
+ * a ray hitting a non-empty block. `voxel` is the unit voxel the ray enters,
+ * the block itself is at `level` so it might be larger than that.
+ */
+#[derive(Copy, Clone)]
+pub struct VoxelHit<T : Copy + Clone> {
+    pub t: f32,
+    pub position: Vec3,
+    pub t_exit: f32,
+    pub exit_position: Vec3,
+    pub voxel: UVec3,
+    /// points against the ray, zero if the ray starts inside the block
+    pub normal: Vec3,
+    pub face: Face3,
+    pub level: u32,
+    pub data: T,
+}
+
+impl <T : Copy + Clone> VoxelHit<T> {
+    pub fn from_info(ray: &Ray3, info: TraverseRayInfo<T>) -> Self {
+        VoxelHit {
+            t: info.in_info.t,
+            position: ray.at(info.in_info.t),
+            t_exit: info.out_info.t,
+            exit_position: ray.at(info.out_info.t),
+            voxel: info.pos,
+            normal: info.in_info.mask.as_vec3(),
+            face: info.in_info.mask.first_face(),
+            level: info.level,
+            data: info.data,
+        }
+    }
+
+    /// the unit quad the ray goes through, same as `TraverseRayInfo::first_face_aap`
+    pub fn first_face_aap(self) -> Aap3 {
+        Aap3::from_unit_pos_face(self.voxel.as_vec3(), self.face)
+    }
+}
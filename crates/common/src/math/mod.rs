@@ -10,11 +10,23 @@ pub mod ray;
 pub use ray::*;
 pub mod aabb;
 pub use aabb::*;
+pub mod sampling;
+pub use sampling::*;
 pub mod srng;
 pub use srng::*;
+pub mod sampler;
+pub use sampler::*;
+pub mod blue_noise;
+pub use blue_noise::*;
 pub mod srng_alt;
 pub mod grid;
 pub use grid::*;
 pub mod zzz_deprecated_svt;
 #[cfg(not(target_arch = "spirv"))]
-mod zzz_deprecated_svt_std;
\ No newline at end of file
+mod zzz_deprecated_svt_std;
+#[cfg(not(target_arch = "spirv"))]
+mod zzz_deprecated_svt_query;
+#[cfg(not(target_arch = "spirv"))]
+mod zzz_deprecated_svt_mesh;
+#[cfg(not(target_arch = "spirv"))]
+mod zzz_deprecated_svt_distance;
\ No newline at end of file
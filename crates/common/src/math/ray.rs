@@ -9,6 +9,8 @@ use super::vec::*;
 pub struct Ray3 {
     pub pos: Vec3,
     pub dir: Vec3,
+    /// when in the shutter interval, for moving shapes
+    pub time: f32,
 }
 
 impl Ray3 {
@@ -27,6 +29,7 @@ impl Add<Vec3> for Ray3 {
         Ray3 {
             pos: self.pos + v,
             dir: self.dir,
+            time: self.time,
         }
     }
 }
@@ -34,10 +37,13 @@ impl Add<Vec3> for Ray3 {
 /**
  * nor is always the surface normal
  */
-#[derive(Default)]
+#[derive(Copy, Clone, Default)]
 pub struct HitRecord3 {
     pub is_hit: bool,
     pub t: f32,
     pub from_outside: bool,
     pub nor: Vec3,
+    /// where textures are looked up, `uv` is zero for shapes without them
+    pub point: Vec3,
+    pub uv: Vec2,
 }
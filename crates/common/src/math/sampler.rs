@@ -0,0 +1,347 @@
+use super::srng::*;
+use super::vec::*;
+
+/**
+ * the numbers a renderer uses, by pixel, sample index and dimension. each dimension of the samples
+ * of a pixel is in `[0, 1)` and evenly spread, and different pixels and dimensions don't line up.
+ * a path should use a new dimension for each number it takes, in the same order for every sample
+ */
+pub trait Sampler {
+    fn sample(&self, pixel: UVec2, index: u32, dimension: u32) -> f32;
+
+    /// `dimension` and the one after it
+    fn sample_2d(&self, pixel: UVec2, index: u32, dimension: u32) -> Vec2 {
+        vec2(self.sample(pixel, index, dimension), self.sample(pixel, index, dimension + 1))
+    }
+}
+
+// the largest float below 1
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON * 0.5;
+
+// pcg hash, "Hash Functions for GPU Rendering", Jarzynski & Olano
+pub(super) fn hash(x: u32) -> u32 {
+    let state = x.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28).wrapping_add(4))) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+
+fn hash_pixel(pixel: UVec2, seed: u32) -> u32 {
+    hash(pixel.x ^ hash(pixel.y ^ hash(seed)))
+}
+
+// the top 24 bits as a float in [0, 1)
+pub(super) fn to_unit_float(x: u32) -> f32 {
+    (x >> 8) as f32 * (1.0 / (1u32 << 24) as f32)
+}
+
+/**
+ * plain random numbers from `SRng`, what the others are compared against
+ */
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[derive(Copy, Clone, Default)]
+pub struct RandomSampler {
+    pub seed: u32,
+}
+
+impl Sampler for RandomSampler {
+    fn sample(&self, pixel: UVec2, index: u32, dimension: u32) -> f32 {
+        SRng::new(hash(hash_pixel(pixel, self.seed) ^ hash(index ^ hash(dimension)))).gen()
+    }
+}
+
+// the direction numbers of a sobol dimension with the primitive polynomial of degree `s`, its inner
+// coefficients `a` and the initial numbers `m`, from Joe & Kuo
+const fn sobol_directions(s: usize, a: u32, m: [u32; 3]) -> [u32; 32] {
+    let mut v = [0u32; 32];
+    let mut i = 0;
+    while i < 32 {
+        if i < s {
+            v[i] = m[i] << (31 - i);
+        } else {
+            v[i] = v[i - s] ^ (v[i - s] >> s);
+            let mut k = 1;
+            while k < s {
+                if (a >> (s - 1 - k)) & 1 == 1 {
+                    v[i] ^= v[i - k];
+                }
+                k += 1;
+            }
+        }
+        i += 1;
+    }
+    v
+}
+
+// the first dimension is the van der corput sequence, so it starts from the second
+const SOBOL_DIRECTIONS: [[u32; 32]; 3] = [
+    sobol_directions(1, 0, [1, 0, 0]),
+    sobol_directions(2, 1, [1, 3, 0]),
+    sobol_directions(3, 1, [1, 3, 1]),
+];
+
+/**
+ * the `index`th point of the sobol sequence in one of its first 4 dimensions, as 32 bit fixed point
+ */
+pub fn sobol(index: u32, dimension: u32) -> u32 {
+    if dimension == 0 {
+        return index.reverse_bits();
+    }
+    let directions = SOBOL_DIRECTIONS[dimension as usize - 1];
+    let mut x = 0;
+    let mut i = index;
+    let mut k = 0;
+    while i != 0 {
+        if i & 1 == 1 {
+            x ^= directions[k];
+        }
+        i >>= 1;
+        k += 1;
+    }
+    x
+}
+
+// "Practical Hash-based Owen Scrambling", Burley
+fn laine_karras_permutation(x: u32, seed: u32) -> u32 {
+    let mut x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+/**
+ * a random permutation of the bits of `x` that swaps whole subtrees from the top bit, so a
+ * stratified set of points stays stratified
+ */
+pub fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+/**
+ * Owen scrambled sobol points. the dimensions go in groups of 4, each group is a shuffled and
+ * scrambled copy of the first 4 sobol dimensions. the first 2^k samples of a pixel are stratified
+ * in each dimension and each pair of the first 2
+ */
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[derive(Copy, Clone, Default)]
+pub struct SobolSampler {
+    pub seed: u32,
+}
+
+impl Sampler for SobolSampler {
+    fn sample(&self, pixel: UVec2, index: u32, dimension: u32) -> f32 {
+        let seed = hash(hash_pixel(pixel, self.seed) ^ (dimension / 4));
+        let index = nested_uniform_scramble(index, seed);
+        let x = nested_uniform_scramble(sobol(index, dimension % 4), hash(seed ^ dimension));
+        to_unit_float(x)
+    }
+}
+
+const HALTON_PRIMES: [u32; 16] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53];
+
+/**
+ * the digits of `index` in `base` mirrored around the point
+ */
+pub fn radical_inverse(base: u32, index: u32) -> f32 {
+    let inv_base = 1.0 / base as f32;
+    let mut inv = inv_base;
+    let mut result = 0.0;
+    let mut i = index;
+    while i != 0 {
+        result += (i % base) as f32 * inv;
+        i /= base;
+        inv *= inv_base;
+    }
+    result.min(ONE_MINUS_EPSILON)
+}
+
+/**
+ * halton points, dimension `d` is the radical inverse in the `d`th prime. each pixel and dimension
+ * is shifted by a random offset (Cranley-Patterson rotation). after the 16 primes the dimensions
+ * repeat with other offsets
+ */
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[derive(Copy, Clone, Default)]
+pub struct HaltonSampler {
+    pub seed: u32,
+}
+
+impl Sampler for HaltonSampler {
+    fn sample(&self, pixel: UVec2, index: u32, dimension: u32) -> f32 {
+        let base = HALTON_PRIMES[(dimension % HALTON_PRIMES.len() as u32) as usize];
+        let offset = to_unit_float(hash(hash_pixel(pixel, self.seed) ^ dimension));
+        let x = radical_inverse(base, index) + offset;
+        (if x >= 1.0 { x - 1.0 } else { x }).min(ONE_MINUS_EPSILON)
+    }
+}
+
+// the fractional parts of `1 / g^i` for the generalized golden ratios `g` of 2 and 3 dimensions,
+// "The Unreasonable Effectiveness of Quasirandom Sequences", Roberts. in 32 bit fixed point so
+// they stay exact for large indices
+const R2_ALPHA: [u32; 2] = [0xc13fa9a9, 0x91e10da6];
+const R3_ALPHA: [u32; 3] = [0xd1b54a33, 0xabc98389, 0x8cb92ba7];
+
+// the `dimension`th one of a sequence with `alpha` for each dimension, randomly offset by pixel.
+// shifting the index only rotates the points, so more of the same sequence would be correlated
+// with the first. the rest are random instead
+fn roberts(alpha: &[u32], pixel: UVec2, seed: u32, index: u32, dimension: u32) -> f32 {
+    if dimension as usize >= alpha.len() {
+        return RandomSampler { seed }.sample(pixel, index, dimension);
+    }
+    let offset = hash(hash_pixel(pixel, seed) ^ dimension);
+    to_unit_float(offset.wrapping_add(alpha[dimension as usize].wrapping_mul(index)))
+}
+
+/**
+ * the R2 sequence in the first 2 dimensions, each pixel has a random offset. the other dimensions
+ * are random
+ */
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[derive(Copy, Clone, Default)]
+pub struct R2Sampler {
+    pub seed: u32,
+}
+
+impl Sampler for R2Sampler {
+    fn sample(&self, pixel: UVec2, index: u32, dimension: u32) -> f32 {
+        roberts(&R2_ALPHA, pixel, self.seed, index, dimension)
+    }
+}
+
+/**
+ * the R3 sequence, like `R2Sampler` in the first 3 dimensions
+ */
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[derive(Copy, Clone, Default)]
+pub struct R3Sampler {
+    pub seed: u32,
+}
+
+impl Sampler for R3Sampler {
+    fn sample(&self, pixel: UVec2, index: u32, dimension: u32) -> f32 {
+        roberts(&R3_ALPHA, pixel, self.seed, index, dimension)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the L2 star discrepancy of 2D points, by Warnock's formula
+    fn l2_star_discrepancy(points: &[Vec2]) -> f32 {
+        let n = points.len() as f64;
+        let mut single = 0.0;
+        let mut pairs = 0.0;
+        for p in points {
+            single += (1.0 - p.x as f64 * p.x as f64) * (1.0 - p.y as f64 * p.y as f64);
+            for q in points {
+                pairs += (1.0 - p.x.max(q.x) as f64) * (1.0 - p.y.max(q.y) as f64);
+            }
+        }
+        (1.0 / 9.0 - single / (2.0 * n) + pairs / (n * n)).sqrt() as f32
+    }
+
+    // the mean over pixels of the discrepancy of the first `n` samples in `dimension` and the next
+    fn mean_discrepancy(sampler: &impl Sampler, n: u32, dimension: u32) -> f32 {
+        let pixels = 16;
+        let mut sum = 0.0;
+        for p in 0..pixels {
+            let pixel = uvec2(p * 7, p * 13 + 1);
+            let points: Vec<Vec2> = (0..n).map(|i| sampler.sample_2d(pixel, i, dimension)).collect();
+            sum += l2_star_discrepancy(&points);
+        }
+        sum / pixels as f32
+    }
+
+    // the root mean squared error over pixels of integrating a smooth function of `dimensions`
+    fn rms_error(sampler: &impl Sampler, n: u32, dimensions: u32) -> f32 {
+        let f = |pixel, i| (0..dimensions).map(|d| (core::f32::consts::PI * sampler.sample(pixel, i, d)).sin()).product::<f32>();
+        let expected = (2.0 / core::f32::consts::PI).powi(dimensions as i32);
+        let pixels = 64;
+        let mut sum = 0.0;
+        for p in 0..pixels {
+            let pixel = uvec2(p, 3 * p);
+            let estimate = (0..n).map(|i| f(pixel, i)).sum::<f32>() / n as f32;
+            sum += (estimate - expected) * (estimate - expected);
+        }
+        (sum / pixels as f32).sqrt()
+    }
+
+    #[test]
+    fn sobol_directions_are_sobol() {
+        // the first points of the second dimension, and the third is not the second
+        let points: Vec<u32> = (0..4).map(|i| sobol(i, 1) >> 30).collect();
+        assert_eq!(points, vec![0, 2, 3, 1]);
+        assert_eq!(SOBOL_DIRECTIONS[1][..3], [1 << 31, 3 << 30, 3 << 29]);
+        assert_eq!(SOBOL_DIRECTIONS[2][..4], [1 << 31, 3 << 30, 1 << 29, 5 << 28]);
+        // scrambling keeps the top bits a permutation
+        let mut top: Vec<u32> = (0..16).map(|i| nested_uniform_scramble(i << 28, 5) >> 28).collect();
+        top.sort_unstable();
+        assert_eq!(top, (0..16).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn samples_are_stratified() {
+        let n = 64;
+        for pixel in [uvec2(0, 0), uvec2(5, 9)] {
+            for dimension in 0..8 {
+                let mut strata = [false; 64];
+                for i in 0..n {
+                    let x = SobolSampler { seed: 1 }.sample(pixel, i, dimension);
+                    assert!((0.0..1.0).contains(&x));
+                    strata[(x * n as f32) as usize] = true;
+                }
+                assert!(strata.iter().all(|s| *s));
+            }
+            // the first 2 dimensions are a (0, 2)-sequence
+            let mut strata = [false; 64];
+            for i in 0..n {
+                let p = SobolSampler { seed: 1 }.sample_2d(pixel, i, 0);
+                strata[(p.x * 8.0) as usize * 8 + (p.y * 8.0) as usize] = true;
+            }
+            assert!(strata.iter().all(|s| *s));
+            // base 2 halton is rotated but still one in each
+            let mut strata = [false; 64];
+            for i in 0..n {
+                strata[(HaltonSampler { seed: 1 }.sample(pixel, i, 0) * n as f32) as usize] = true;
+            }
+            assert!(strata.iter().all(|s| *s));
+        }
+    }
+
+    #[test]
+    fn discrepancy_lower_than_random() {
+        let n = 256;
+        let random = mean_discrepancy(&RandomSampler { seed: 1 }, n, 0);
+        let lower = |name, d: f32, times: f32| assert!(d * times < random, "{} {} {}", name, d, random);
+        for dimension in [0, 2, 6] {
+            lower("sobol", mean_discrepancy(&SobolSampler { seed: 1 }, n, dimension), 5.0);
+        }
+        lower("halton", mean_discrepancy(&HaltonSampler { seed: 1 }, n, 0), 3.0);
+        lower("halton", mean_discrepancy(&HaltonSampler { seed: 1 }, n, 2), 3.0);
+        // the larger primes need more samples
+        lower("halton", mean_discrepancy(&HaltonSampler { seed: 1 }, n, 6), 1.5);
+        lower("r2", mean_discrepancy(&R2Sampler { seed: 1 }, n, 0), 3.0);
+        lower("r3", mean_discrepancy(&R3Sampler { seed: 1 }, n, 0), 3.0);
+        lower("r3", mean_discrepancy(&R3Sampler { seed: 1 }, n, 1), 3.0);
+    }
+
+    #[test]
+    fn converges_faster_than_random() {
+        let n = 256;
+        for dimensions in [2, 3, 4] {
+            let random = rms_error(&RandomSampler { seed: 2 }, n, dimensions);
+            // about 1 / sqrt(n)
+            assert!(random > 0.005 && random < 0.05, "{}", random);
+            assert!(rms_error(&SobolSampler { seed: 2 }, n, dimensions) * 4.0 < random);
+            assert!(rms_error(&HaltonSampler { seed: 2 }, n, dimensions) * 4.0 < random);
+            if dimensions <= 2 {
+                assert!(rms_error(&R2Sampler { seed: 2 }, n, dimensions) * 4.0 < random);
+            }
+            if dimensions <= 3 {
+                assert!(rms_error(&R3Sampler { seed: 2 }, n, dimensions) * 4.0 < random);
+            }
+        }
+    }
+}
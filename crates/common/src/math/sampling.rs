@@ -0,0 +1,155 @@
+use super::vec::*;
+
+// closed form warps of uniform numbers in [0, 1)², so they also work with low discrepancy points.
+// the directions are around +z, `around` turns them to be around another one
+
+/**
+ * `local` in the frame of `orthonormal_basis` around `dir`, which must be normalized
+ */
+pub fn around(dir: Vec3, local: Vec3) -> Vec3 {
+    let (t, b) = dir.orthonormal_basis();
+    t * local.x + b * local.y + dir * local.z
+}
+
+/**
+ * on the unit disk with the areas kept, "A Low Distortion Map Between Disk and Square", Shirley &
+ * Chiu. the pdf is `concentric_disk_pdf`
+ */
+pub fn concentric_disk(u: Vec2) -> Vec2 {
+    let p = 2.0 * u - Vec2::ONE;
+    if p.x == 0.0 && p.y == 0.0 {
+        return Vec2::ZERO;
+    }
+    let quarter = core::f32::consts::FRAC_PI_4;
+    let (r, theta) = if p.x.abs() > p.y.abs() {
+        (p.x, quarter * (p.y / p.x))
+    } else {
+        (p.y, 2.0 * quarter - quarter * (p.x / p.y))
+    };
+    vec2(theta.cos(), theta.sin()) * r
+}
+
+pub fn concentric_disk_pdf() -> f32 {
+    1.0 / core::f32::consts::PI
+}
+
+/**
+ * cosine weighted on the hemisphere, by lifting `concentric_disk` (Malley's method). the pdf is
+ * `cosine_hemisphere_pdf` of `z`
+ */
+pub fn cosine_hemisphere(u: Vec2) -> Vec3 {
+    let d = concentric_disk(u);
+    d.extend((1.0 - d.length_squared()).max(0.0).sqrt())
+}
+
+pub fn cosine_hemisphere_pdf(cos: f32) -> f32 {
+    cos.max(0.0) / core::f32::consts::PI
+}
+
+/**
+ * uniform on the unit sphere, the pdf is `uniform_sphere_pdf`
+ */
+pub fn uniform_sphere(u: Vec2) -> Vec3 {
+    let z = 1.0 - 2.0 * u.x;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * core::f32::consts::PI * u.y;
+    vec3(r * phi.cos(), r * phi.sin(), z)
+}
+
+pub fn uniform_sphere_pdf() -> f32 {
+    0.25 / core::f32::consts::PI
+}
+
+/**
+ * uniform on the directions within the cone with the cosine of its half angle `cos_max`, the pdf
+ * is `uniform_cone_pdf`
+ */
+pub fn uniform_cone(u: Vec2, cos_max: f32) -> Vec3 {
+    let z = 1.0 - u.x * (1.0 - cos_max);
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * core::f32::consts::PI * u.y;
+    vec3(r * phi.cos(), r * phi.sin(), z)
+}
+
+pub fn uniform_cone_pdf(cos_max: f32) -> f32 {
+    1.0 / (2.0 * core::f32::consts::PI * (1.0 - cos_max))
+}
+
+/**
+ * the barycentric coordinates of the second and third vertex of a point uniform on a triangle,
+ * "Global Illumination Compendium" 77. the pdf is one over the area of the triangle
+ */
+pub fn uniform_triangle(u: Vec2) -> Vec2 {
+    let s = u.x.sqrt();
+    vec2(s * (1.0 - u.y), s * u.y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+
+    // integrates `f` over the sphere by the samples of `sample` with their `pdf`
+    fn integrate(sample: impl Fn(Vec2) -> Vec3, pdf: impl Fn(Vec3) -> f32, f: impl Fn(Vec3) -> f32) -> f32 {
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(1);
+        let n = 100000;
+        let mut sum = 0.0;
+        for _ in 0..n {
+            let w = sample(vec2(rng.gen(), rng.gen()));
+            assert!((w.length() - 1.0).abs() < 1e-4);
+            sum += f(w) / pdf(w);
+        }
+        sum / n as f32
+    }
+
+    #[test]
+    fn directions_match_their_pdfs() {
+        let pi = core::f32::consts::PI;
+        // the solid angles
+        let one = |_| 1.0;
+        let hemisphere = integrate(cosine_hemisphere, |w| cosine_hemisphere_pdf(w.z), one);
+        assert!((hemisphere - 2.0 * pi).abs() < 0.05, "{}", hemisphere);
+        let sphere = integrate(uniform_sphere, |_| uniform_sphere_pdf(), one);
+        assert!((sphere - 4.0 * pi).abs() < 1e-2);
+        let cone = integrate(|u| uniform_cone(u, 0.8), |_| uniform_cone_pdf(0.8), one);
+        assert!((cone - 0.4 * pi).abs() < 1e-2);
+        // the moments, so they are where they should be
+        let z2 = |w: Vec3| w.z * w.z;
+        let x = |w: Vec3| w.x;
+        assert!((integrate(cosine_hemisphere, |w| cosine_hemisphere_pdf(w.z), z2) - 2.0 * pi / 3.0).abs() < 0.05);
+        assert!(integrate(cosine_hemisphere, |w| cosine_hemisphere_pdf(w.z), x).abs() < 0.05);
+        assert!((integrate(uniform_sphere, |_| uniform_sphere_pdf(), z2) - 4.0 * pi / 3.0).abs() < 0.05);
+        assert!(integrate(uniform_sphere, |_| uniform_sphere_pdf(), x).abs() < 0.05);
+        let cone_z = integrate(|u| uniform_cone(u, 0.8), |_| uniform_cone_pdf(0.8), |w| w.z);
+        assert!((cone_z - pi * (1.0 - 0.64)).abs() < 1e-2, "{}", cone_z);
+        let w = around(vec3(0.6, 0.0, 0.8), vec3(0.0, 0.0, 1.0));
+        assert!((w - vec3(0.6, 0.0, 0.8)).length() < 1e-6);
+    }
+
+    #[test]
+    fn disk_and_triangle_are_uniform() {
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(2);
+        let n = 100000;
+        let (mut disk_inner, mut disk_right) = (0, 0);
+        let (mut triangle_corner, mut triangle_mean) = (0, Vec2::ZERO);
+        for _ in 0..n {
+            let u = vec2(rng.gen(), rng.gen());
+            let d = concentric_disk(u);
+            assert!(d.length() <= 1.0 + 1e-6);
+            disk_inner += (d.length() < 0.5) as u32;
+            disk_right += (d.x > 0.0) as u32;
+            let b = uniform_triangle(u);
+            assert!(b.min_element() >= 0.0 && b.x + b.y <= 1.0 + 1e-6);
+            // the corner of the first vertex, with a quarter of the area
+            triangle_corner += (b.x + b.y < 0.5) as u32;
+            triangle_mean += b;
+        }
+        let fraction = |count: u32| count as f32 / n as f32;
+        assert!((fraction(disk_inner) - 0.25).abs() < 0.01);
+        assert!((fraction(disk_right) - 0.5).abs() < 0.01);
+        assert!((fraction(triangle_corner) - 0.25).abs() < 0.01);
+        assert!((triangle_mean / n as f32 - Vec2::splat(1.0 / 3.0)).length() < 0.01);
+        assert_eq!(concentric_disk(Vec2::splat(0.5)), Vec2::ZERO);
+        assert!((concentric_disk_pdf() * core::f32::consts::PI - 1.0).abs() < 1e-6);
+    }
+}
@@ -1,3 +1,4 @@
+use super::sampling::*;
 use super::vec::*;
 // shader rng
 #[repr(transparent)]
@@ -29,6 +30,31 @@ impl SRng {
         rng
     }
 
+    /**
+     * skips the next `delta` numbers in `O(log delta)` steps, "Random Number Generation with
+     * Arbitrary Strides", Brown. the period is `2^32`, so with the same seed, advancing by
+     * `i.wrapping_mul(n)` for the `i`th pixel gives them non-overlapping runs of `n` numbers, as
+     * long as `pixels * n <= 2^32`. past that the runs wrap around and overlap. the runs are parts
+     * of one sequence, not statistically independent streams
+     */
+    pub fn advance(&mut self, delta: u32) {
+        let mut acc_mult = 1u32;
+        let mut acc_plus = 0u32;
+        let mut cur_mult = Self::PCG_DEFAULT_MULTIPLIER_32;
+        let mut cur_plus = Self::PCG_DEFAULT_INCREMENT_32;
+        let mut delta = delta;
+        while delta > 0 {
+            if delta & 1 == 1 {
+                acc_mult = acc_mult.wrapping_mul(cur_mult);
+                acc_plus = acc_plus.wrapping_mul(cur_mult).wrapping_add(cur_plus);
+            }
+            cur_plus = cur_mult.wrapping_add(1).wrapping_mul(cur_plus);
+            cur_mult = cur_mult.wrapping_mul(cur_mult);
+            delta >>= 1;
+        }
+        self.state = acc_mult.wrapping_mul(self.state).wrapping_add(acc_plus);
+    }
+
     pub fn gen_u32(&mut self) -> u32 {
         let old_state = self.state;
         self.pcg_oneseq_32_step_r();
@@ -53,18 +79,17 @@ impl SRng {
         vec2(self.gen(), self.gen())
     }
 
+    /**
+     * uniform in the unit ball
+     */
     pub fn gen_in_unit_sphere(&mut self) -> Vec3 {
-        let mut p;
-        loop {
-            let rv = vec3(self.gen(), self.gen(), self.gen());
-            p = 2.0 * rv - Vec3::ONE;
-            let p_len2 = p.length_squared();
-            if p_len2 < 1.0 {
-                break p;
-            }
-        }
+        let dir = uniform_sphere(self.gen_vec2());
+        dir * self.gen().powf(1.0 / 3.0)
     }
 
+    /**
+     * uniform in the unit ball, flipped to the side of `nor`
+     */
     pub fn gen_in_hemisphere(&mut self, nor: Vec3) -> Vec3 {
         let sp = self.gen_in_unit_sphere();
         if sp.dot(nor) > 0.0 {
@@ -73,4 +98,222 @@ impl SRng {
             -sp
         }
     }
+
+    /**
+     * the pdf is `uniform_sphere_pdf`
+     */
+    pub fn gen_on_unit_sphere(&mut self) -> Vec3 {
+        uniform_sphere(self.gen_vec2())
+    }
+
+    /**
+     * the pdf is `concentric_disk_pdf`
+     */
+    pub fn gen_in_unit_disk(&mut self) -> Vec2 {
+        concentric_disk(self.gen_vec2())
+    }
+
+    /**
+     * cosine weighted around the normalized `nor`, the pdf is `cosine_hemisphere_pdf` of the cosine
+     */
+    pub fn gen_cosine_hemisphere(&mut self, nor: Vec3) -> Vec3 {
+        around(nor, cosine_hemisphere(self.gen_vec2()))
+    }
+
+    /**
+     * uniform within the cone around the normalized `dir`, the pdf is `uniform_cone_pdf`
+     */
+    pub fn gen_in_cone(&mut self, dir: Vec3, cos_max: f32) -> Vec3 {
+        around(dir, uniform_cone(self.gen_vec2(), cos_max))
+    }
+
+    /**
+     * uniform on the triangle, the pdf is one over its area
+     */
+    pub fn gen_in_triangle(&mut self, a: Vec3, b: Vec3, c: Vec3) -> Vec3 {
+        let bary = uniform_triangle(self.gen_vec2());
+        a * (1.0 - bary.x - bary.y) + b * bary.x + c * bary.y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::srng_alt;
+    use super::*;
+
+    // both rngs get the same tests, the sin hash one from the seed a pixel might give it
+    const N: usize = 200000;
+
+    fn alt() -> srng_alt::SRng {
+        srng_alt::SRng { seed: vec2(123.0, 456.0) }
+    }
+
+    // pearson's chi-squared statistic of `counts` against the same count in every bin
+    fn chi_squared(counts: &[u32]) -> f64 {
+        let expected = counts.iter().sum::<u32>() as f64 / counts.len() as f64;
+        counts.iter().map(|&c| (c as f64 - expected).powi(2) / expected).sum()
+    }
+
+    // what the statistic of uniform numbers in `bins` bins goes above one time in a thousand, by
+    // the Wilson-Hilferty approximation
+    fn chi_squared_limit(bins: usize) -> f64 {
+        let k = (bins - 1) as f64;
+        let z = 3.09;
+        k * (1.0 - 2.0 / (9.0 * k) + z * (2.0 / (9.0 * k)).sqrt()).powi(3)
+    }
+
+    // the largest difference of a bin from the mean, relative to it
+    fn max_deviation(counts: &[u32]) -> f64 {
+        let expected = counts.iter().sum::<u32>() as f64 / counts.len() as f64;
+        counts.iter().map(|&c| (c as f64 - expected).abs() / expected).fold(0.0, f64::max)
+    }
+
+    fn bin(u: f32, bins: usize) -> usize {
+        assert!((0.0..=1.0).contains(&u), "{}", u);
+        ((u * bins as f32) as usize).min(bins - 1)
+    }
+
+    // how the numbers, and pairs of numbers one after the other, fall in `bins` and `bins^2` bins
+    fn histograms(mut gen: impl FnMut() -> f32, bins: usize) -> (Vec<u32>, Vec<u32>) {
+        let mut counts = vec![0; bins];
+        let mut pairs = vec![0; bins * bins];
+        for _ in 0..N / 2 {
+            let (a, b) = (gen(), gen());
+            counts[bin(a, bins)] += 1;
+            counts[bin(b, bins)] += 1;
+            pairs[bin(a, bins) * bins + bin(b, bins)] += 1;
+        }
+        (counts, pairs)
+    }
+
+    // the correlation of the numbers with the ones `lag` after them is within 4 standard
+    // deviations of 0
+    fn assert_uncorrelated(mut gen: impl FnMut() -> f32) {
+        let xs: Vec<f64> = (0..N).map(|_| gen() as f64).collect();
+        let mean = xs.iter().sum::<f64>() / N as f64;
+        let variance = xs.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / N as f64;
+        for lag in [1, 2, 3, 7] {
+            let n = N - lag;
+            let covariance = (0..n).map(|i| (xs[i] - mean) * (xs[i + lag] - mean)).sum::<f64>() / n as f64;
+            let correlation = covariance / variance;
+            assert!(correlation.abs() < 4.0 / (n as f64).sqrt(), "{} {}", lag, correlation);
+        }
+    }
+
+    // points uniform in the half ball on the side of `nor`: the cosine of their direction with
+    // `nor`, the angle around it and the cube of the radius are uniform
+    fn assert_hemisphere(mut gen: impl FnMut(Vec3) -> Vec3) {
+        let nor = vec3(0.6, 0.0, 0.8);
+        let (t, b) = nor.orthonormal_basis();
+        let bins = 20;
+        let (mut cos, mut phi, mut radius) = (vec![0; bins], vec![0; bins], vec![0; bins]);
+        for _ in 0..N / 4 {
+            let p = gen(nor);
+            let r = p.length();
+            assert!(r <= 1.0 + 1e-5 && p.dot(nor) >= 0.0);
+            let d = p / r;
+            cos[bin(d.dot(nor).max(0.0), bins)] += 1;
+            let angle = d.dot(b).atan2(d.dot(t)) / (2.0 * core::f32::consts::PI) + 0.5;
+            phi[bin(angle, bins)] += 1;
+            radius[bin(r.powi(3), bins)] += 1;
+        }
+        for counts in [cos, phi, radius] {
+            assert!(chi_squared(&counts) < chi_squared_limit(bins), "{}", chi_squared(&counts));
+        }
+    }
+
+    #[test]
+    fn uniform() {
+        let mut rng = SRng::new(1);
+        let (counts, _) = histograms(|| rng.gen(), 100);
+        assert!(chi_squared(&counts) < chi_squared_limit(100), "{}", chi_squared(&counts));
+        let (_, pairs) = histograms(|| rng.gen(), 16);
+        assert!(chi_squared(&pairs) < chi_squared_limit(256), "{}", chi_squared(&pairs));
+        // the float conversion keeps the top 24 bits, so it is below 1 and hits every step
+        let mut rng = SRng { state: 0 };
+        let steps: std::collections::HashSet<u32> = (0..N).map(|_| (rng.gen() * (1 << 24) as f32) as u32).collect();
+        assert!(steps.iter().all(|&s| s < 1 << 24));
+        assert!(steps.len() > N * 99 / 100);
+    }
+
+    #[test]
+    fn alt_is_roughly_uniform() {
+        // the sin hash is multiplied up to 43758 before taking the fraction, so only about 8 bits
+        // of it are left. it fails the chi-squared tests of `uniform` even with 16 bins, and
+        // pairs are worse than single numbers
+        let mut rng = alt();
+        let (counts, _) = histograms(|| rng.gen(), 16);
+        assert!(max_deviation(&counts) < 0.04, "{}", max_deviation(&counts));
+        let (_, pairs) = histograms(|| rng.gen(), 8);
+        assert!(max_deviation(&pairs) < 0.15, "{}", max_deviation(&pairs));
+    }
+
+    #[test]
+    fn serial_correlation() {
+        let mut rng = SRng::new(2);
+        assert_uncorrelated(|| rng.gen());
+        let mut rng = alt();
+        assert_uncorrelated(|| rng.gen());
+        // pixels next to each other get seeds next to each other
+        let mut pixel = 0;
+        assert_uncorrelated(|| {
+            pixel += 1;
+            SRng::new(pixel).gen()
+        });
+    }
+
+    #[test]
+    fn hemisphere() {
+        let mut rng = SRng::new(3);
+        assert_hemisphere(|nor| rng.gen_in_hemisphere(nor));
+        let mut rng = alt();
+        assert_hemisphere(|nor| rng.gen_in_hemisphere(nor));
+    }
+
+    #[test]
+    fn advance_and_period() {
+        for delta in [0, 1, 2, 5, 1000, 12345] {
+            let mut stepped = SRng::new(4);
+            let mut advanced = SRng::new(4);
+            for _ in 0..delta {
+                stepped.gen_u32();
+            }
+            advanced.advance(delta);
+            assert_eq!(stepped.state, advanced.state);
+        }
+        // the period is 2^32: it comes back after that, and not after any half of it
+        let start = SRng::new(5).state;
+        let mut rng = SRng { state: start };
+        rng.advance(u32::MAX);
+        rng.gen_u32();
+        assert_eq!(rng.state, start);
+        let mut rng = SRng { state: start };
+        rng.advance(1 << 31);
+        assert_ne!(rng.state, start);
+        // so pixels advanced by `n` apart get runs of the same sequence one after the other
+        let n = 64;
+        let run = |pixel: u32| {
+            let mut rng = SRng::new(6);
+            rng.advance(pixel.wrapping_mul(n));
+            (0..n).map(|_| rng.gen_u32()).collect::<Vec<_>>()
+        };
+        let mut rng = SRng::new(6);
+        let sequence: Vec<u32> = (0..3 * n).map(|_| rng.gen_u32()).collect();
+        assert_eq!([run(0), run(1), run(2)].concat(), sequence);
+        // and past `2^32 / n` pixels they wrap around to the first ones
+        assert_eq!(run((1 << 26) + 1), run(1));
+    }
+
+    #[test]
+    fn alt_has_no_short_cycles() {
+        let mut rng = alt();
+        let mut seeds = std::collections::HashSet::new();
+        let mut values = std::collections::HashSet::new();
+        for _ in 0..N {
+            assert!(seeds.insert((rng.seed.x.to_bits(), rng.seed.y.to_bits())));
+            values.insert(rng.gen().to_bits());
+        }
+        // see `alt_is_roughly_uniform`
+        assert!(values.len() > 1 << 13, "{}", values.len());
+    }
 }
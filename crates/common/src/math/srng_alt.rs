@@ -1,6 +1,10 @@
 use super::vec::*;
 // shader rng
 
+/**
+ * a sin hash, it keeps only about 8 bits of randomness and is worse on GPUs with a less precise
+ * `sin`, see the tests in `srng`. `super::SRng` is better
+ */
 #[derive(Clone, Default)]
 pub struct SRng {
     pub seed: Vec2,
@@ -84,6 +84,7 @@ pub trait MyVec3Ext {
     fn margin_unit(self, margin: f32) -> Sign3;
     fn leq(self, other: f32) -> Sign3;
     fn de_eps(self, eps: f32) -> Vec3;
+    fn orthonormal_basis(self) -> (Vec3, Vec3);
 }
 
 impl MyVec3Ext for Vec3 {
@@ -138,6 +139,20 @@ impl MyVec3Ext for Vec3 {
         }
     }
 
+    /**
+     * two vectors that make a right handed basis with this normalized one,
+     * "Building an Orthonormal Basis, Revisited", Duff et al.
+     */
+    fn orthonormal_basis(self) -> (Vec3, Vec3) {
+        let sign = if self.z >= 0.0 { 1.0 } else { -1.0 };
+        let a = -1.0 / (sign + self.z);
+        let b = self.x * self.y * a;
+        (
+            vec3(1.0 + sign * self.x * self.x * a, sign * b, -sign * self.x),
+            vec3(b, sign + self.y * self.y * a, -self.y),
+        )
+    }
+
     fn reflect(self, normal: Vec3) -> Self {
         self - 2.0 * normal * self.dot(normal)
     }
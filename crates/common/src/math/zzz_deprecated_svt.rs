@@ -5,8 +5,6 @@ use core::mem::MaybeUninit;
 use core::ops::Deref;
 use spirv_std::arch::*;
 
-use crate::ifelse;
-
 use super::grid::*;
 use super::sign::*;
 
@@ -150,8 +148,16 @@ impl<T: SvtData, REF: Deref<Target = [usvt]>, const BLOCK_DIM: usvt, const LEVEL
     const EPS: f32 = 3.552713678800501e-15;
 
     // from https://github.com/AdamYuan/SparseVoxelOctree
-    pub fn cast_ray_oct(&self, max_count: u32, ray: Ray3) -> (i32, BlockRayIntersectionInfo, T) {
+    // only works for `BLOCK_DIM == 2` and `LEVEL_COUNT < STACK_SIZE`, should agree with `cast_ray`
+    pub fn cast_ray_oct(
+        &self,
+        max_count: u32,
+        ray: Ray3,
+    ) -> Result<Option<VoxelHit<T>>, TraverseRayError> {
         const STACK_SIZE: usize = 23;
+        if ray.dir == Vec3::ZERO {
+            return Err(TraverseRayError::ZeroDirection);
+        }
         let mut stack: [StackItem; STACK_SIZE] = [StackItem {
             node: MaybeUninit::uninit(),
             t_max: MaybeUninit::uninit(),
@@ -159,8 +165,8 @@ impl<T: SvtData, REF: Deref<Target = [usvt]>, const BLOCK_DIM: usvt, const LEVEL
         let mut iter = 0;
         // The octree is assumed to reside at coordinates [1, 2].
         let o = ray.pos / (Self::TOTAL_DIM as f32) + 1.0;
-        let d = ray.dir; //.de_eps(Self::EPS);
-                         // Precompute the coefficients of tx(x), ty(y), and tz(z).
+        let d = ray.dir.de_eps(Self::EPS);
+        // Precompute the coefficients of tx(x), ty(y), and tz(z).
         let t_coef = 1.0 / -d.abs();
         let mut t_bias = t_coef * o;
 
@@ -210,6 +216,9 @@ impl<T: SvtData, REF: Deref<Target = [usvt]>, const BLOCK_DIM: usvt, const LEVEL
 
         while scale < STACK_SIZE {
             iter += 1;
+            if iter > max_count {
+                return Err(TraverseRayError::MaxIteration);
+            }
             if cur == 0u32 {
                 cur = unsafe {
                     *self
@@ -305,7 +314,11 @@ impl<T: SvtData, REF: Deref<Target = [usvt]>, const BLOCK_DIM: usvt, const LEVEL
                         float_bits_to_uint(pos.z) ^ float_bits_to_uint(pos.z + scale_exp2);
                 }
                 scale = find_msb(differing_bits);
-                scale_exp2 = uint_bits_to_float(((scale - STACK_SIZE + 127usize) as u32) << 23u32); // exp2f(scale - s_max)
+                if scale >= STACK_SIZE {
+                    // left the root
+                    break;
+                }
+                scale_exp2 = uint_bits_to_float(((scale + 127usize - STACK_SIZE) as u32) << 23u32); // exp2f(scale - s_max)
 
                 // Restore parent voxel from the stack.
                 unsafe {
@@ -328,9 +341,16 @@ impl<T: SvtData, REF: Deref<Target = [usvt]>, const BLOCK_DIM: usvt, const LEVEL
                 cur = 0;
             }
         }
+        if scale >= STACK_SIZE || t_min > t_max {
+            return Ok(None);
+        }
+        let t_exit = (pos * t_coef - t_bias).min_element();
         let t_corner = t_coef * (pos + scale_exp2) - t_bias;
 
-        let mut norm = if t_corner.x > t_corner.y && t_corner.x > t_corner.z {
+        let mut norm = if t_min <= 0.0 {
+            // started inside
+            Vec3::ZERO
+        } else if t_corner.x > t_corner.y && t_corner.x > t_corner.z {
             vec3(-1.0, 0.0, 0.0)
         } else {
             if t_corner.y > t_corner.z {
@@ -359,79 +379,73 @@ impl<T: SvtData, REF: Deref<Target = [usvt]>, const BLOCK_DIM: usvt, const LEVEL
         if (oct_mask & 4u32) != 0u32 {
             pos.z = 3.0 - scale_exp2 - pos.z;
         }
-        let EPS = Self::EPS;
-        // Output results.
-        let mut o_pos = (o + t_min * d).clamp(pos, pos + Vec3::splat(scale_exp2));
-        if norm.x != 0.0 {
-            o_pos.x = ifelse!(norm.x > 0.0, pos.x + scale_exp2 + EPS * 2.0, pos.x - EPS);
-        }
-        if norm.y != 0.0 {
-            o_pos.y = ifelse!(norm.y > 0.0, pos.y + scale_exp2 + EPS * 2.0, pos.y - EPS);
-        }
-        if norm.z != 0.0 {
-            o_pos.z = ifelse!(norm.z > 0.0, pos.z + scale_exp2 + EPS * 2.0, pos.z - EPS);
-        }
-        let hit = scale < STACK_SIZE && t_min <= t_max;
-        if hit {
-            let t = (((o_pos - 1.0) * (Self::TOTAL_DIM as f32) - ray.pos) / ray.dir).min_element();
-            return (
-                iter,
-                BlockRayIntersectionInfo {
-                    mask: Sign3::new(norm.x, norm.y, norm.z),
-                    t,
-                },
-                cur.into(),
-            );
-        } else {
-            return (
-                iter,
-                BlockRayIntersectionInfo {
-                    mask: Sign3::ZERO,
-                    t: -1.0,
-                },
-                T::EMPTY,
-            );
-        }
+        // the octree is scaled by `TOTAL_DIM`, so is `t`
+        let total = Self::TOTAL_DIM as f32;
+        let t = t_min * total;
+        let t_exit = t_exit * total;
+        let position = ray.at(t);
+        let block_min = (pos - 1.0) * total;
+        let block_max = block_min + Vec3::splat(scale_exp2 * total - 0.5);
+        let mask = Sign3::new(norm.x, norm.y, norm.z);
+        Ok(Some(VoxelHit {
+            t,
+            position,
+            t_exit,
+            exit_position: ray.at(t_exit),
+            voxel: vec3_to_usvt3((position - norm * 0.5).clamp(block_min, block_max)),
+            normal: norm,
+            face: mask.first_face(),
+            level: (STACK_SIZE - 1 - scale) as u32,
+            data: cur.into(),
+        }))
     }
 
-    pub fn cast_ray_max(&self, ray: Ray3, max_distance: f32) -> Option<TraverseRayInfo<T>> {
-        let mut result_info = None;
+    pub fn cast_ray_max(
+        &self,
+        ray: Ray3,
+        max_distance: f32,
+    ) -> Result<Option<VoxelHit<T>>, TraverseRayError> {
+        let mut result = None;
         self.traverse_ray(1000000, ray, |info| {
             if info.in_info.t > max_distance {
                 true
             } else if info.data != T::EMPTY {
-                result_info = Some(info);
+                result = Some(VoxelHit::from_info(&ray, info));
                 true
             } else {
                 false
             }
-        });
-        result_info
+        })?;
+        Ok(result)
     }
 
-    pub fn cast_ray(&self, max_iter: u32, ray: Ray3) -> (i32, BlockRayIntersectionInfo, T) {
-        let mut in_info = BlockRayIntersectionInfo {
-            mask: Sign3::ZERO,
-            t: -1.0,
-        };
-        let mut block_info = T::EMPTY;
-        let error_code = self.traverse_ray(max_iter, ray, |info| {
+    pub fn cast_ray(
+        &self,
+        max_iter: u32,
+        ray: Ray3,
+    ) -> Result<Option<VoxelHit<T>>, TraverseRayError> {
+        let mut result = None;
+        self.traverse_ray(max_iter, ray, |info| {
             if info.data != T::EMPTY {
-                in_info = info.in_info;
-                block_info = info.data;
+                result = Some(VoxelHit::from_info(&ray, info));
                 true
             } else {
                 false
             }
-        });
-        (error_code, in_info, block_info)
+        })?;
+        Ok(result)
     }
 
     /**
      * assume normalized ray
      */
     #[inline]
-    pub fn traverse_ray<C>(&self, max_count: u32, ray: Ray3, mut closure: C) -> i32
+    pub fn traverse_ray<C>(
+        &self,
+        max_count: u32,
+        ray: Ray3,
+        mut closure: C,
+    ) -> Result<u32, TraverseRayError>
     where
         C: FnMut(TraverseRayInfo<T>) -> bool,
     {
@@ -440,7 +454,7 @@ impl<T: SvtData, REF: Deref<Target = [usvt]>, const BLOCK_DIM: usvt, const LEVEL
         // https://itectec.com/matlab-ref/matlab-function-flintmax-largest-consecutive-integer-in-floating-point-format/
         // de_eps(&mut ray.dir);
         if ray.dir == Vec3::ZERO {
-            return -3;
+            return Err(TraverseRayError::ZeroDirection);
         }
         let ray_dir_inv = 1.0 / ray.dir;
         let ray_pos_div_ray_dir = ray.pos / ray.dir;
@@ -452,11 +466,22 @@ impl<T: SvtData, REF: Deref<Target = [usvt]>, const BLOCK_DIM: usvt, const LEVEL
         let aabb = Aabb3::new(Vec3::ZERO, Vec3::splat(Self::TOTAL_DIM as f32));
         let res = aabb.hit_fast1(&ray);
         if res < 0.0 {
-            return 0;
+            return Ok(0);
         } else {
             position = ray.at(res);
         }
-        let mut t = 0 as f32;
+        let mut t = res;
+        if res > 0.0 {
+            // the face we enter the whole tree from
+            let block_entry = (-ray_dir_signum).non_neg_mul(Vec3::splat(Self::TOTAL_DIM as f32));
+            let ts = block_entry * ray_dir_inv - ray_pos_div_ray_dir;
+            mask = -(-ts).leq(-ts.max_element()) * ray_dir_signum;
+            // this used to be off by one: entering from a max face gives `TOTAL_DIM` on that axis,
+            // which wraps around to the first block of the level. step into the tree like we do
+            // for every other block, and clamp the other axes which can round to `TOTAL_DIM` too
+            position = (position - mask.as_vec3() * 0.5)
+                .min(Vec3::splat(Self::TOTAL_DIM as f32 - 0.5));
+        }
         let mut block_indexs = [0usize; LEVEL_COUNT];
         block_indexs[0] = self.root_block_index() * (Self::BLOCK_SIZE as usize);
 
@@ -464,8 +489,6 @@ impl<T: SvtData, REF: Deref<Target = [usvt]>, const BLOCK_DIM: usvt, const LEVEL
         let ts = block_limit * ray_dir_inv - ray_pos_div_ray_dir;
         let ts_min = ts.x.min(ts.y).min(ts.z);
         let mut block_limits = [ts_min; LEVEL_COUNT];
-        // there is a off by one error...
-        //
         // block aabbs is terminal block
         let mut level: usvt = 0;
         let mut level_dim_div = Self::TOTAL_DIM / BLOCK_DIM;
@@ -490,18 +513,20 @@ impl<T: SvtData, REF: Deref<Target = [usvt]>, const BLOCK_DIM: usvt, const LEVEL
                     t = ts_min;
                     mask = -ts.leq(t) * ray_dir_signum;
                     if mask == Sign3::ZERO {
-                        return -2;
+                        return Err(TraverseRayError::NoProgress);
                     }
                     let block_info: T = index.into();
                     let out_info = BlockRayIntersectionInfo { t, mask };
                     let info = TraverseRayInfo {
                         in_info: incident,
+                        out_info,
                         pos: position_u,
+                        level,
                         data: block_info,
                     };
                     let ret = closure(info);
                     if ret {
-                        return count as i32;
+                        return Ok(count);
                     }
                     break;
                 } else {
@@ -531,14 +556,14 @@ impl<T: SvtData, REF: Deref<Target = [usvt]>, const BLOCK_DIM: usvt, const LEVEL
             };
             count += 1;
             if count > max_count {
-                return -1;
+                return Err(TraverseRayError::MaxIteration);
             }
             loop {
                 let block_limit = block_limits[level as usize];
                 if t < block_limit {
                     break;
                 } else if level == 0 {
-                    return count as i32;
+                    return Ok(count);
                 } else {
                     level -= 1;
                     level_dim_div *= BLOCK_DIM;
@@ -584,7 +609,10 @@ fn find_msb(bits: u32) -> usize {
         result
     }
     #[cfg(not(target_arch = "spirv"))]
-    todo!()
+    {
+        // same as FindUMsb, -1 for 0
+        31u32.wrapping_sub(bits.leading_zeros()) as usize
+    }
 }
 
 fn uint_bits_to_float(bits: u32) -> f32 {
@@ -629,3 +657,9 @@ pub fn float_bits_to_uint(bits: f32) -> u32 {
 
 #[cfg(not(target_arch = "spirv"))]
 pub use super::zzz_deprecated_svt_std::*;
+#[cfg(not(target_arch = "spirv"))]
+pub use super::zzz_deprecated_svt_query::*;
+#[cfg(not(target_arch = "spirv"))]
+pub use super::zzz_deprecated_svt_mesh::*;
+#[cfg(not(target_arch = "spirv"))]
+pub use super::zzz_deprecated_svt_distance::*;
@@ -0,0 +1,292 @@
+use core::ops::Deref;
+
+use super::aabb::*;
+use super::grid::*;
+use super::ray::*;
+use super::vec::*;
+use super::zzz_deprecated_svt::*;
+
+/**
+ * distance to the nearest solid voxel, one texel per block at some level of a `Svt`.
+ *
+ * the texels are already conservative: from any point inside a cell, you can move `texel` voxels
+ * in any direction and not hit anything. the layout is x first then y then z, same as `Svt::encode`,
+ * so `as_bytes` can be uploaded as a `R32Float` 3d texture directly
+ */
+#[derive(Clone)]
+pub struct SvtDistanceField {
+    pub dim: u32,
+    pub cell_size: u32,
+    pub distances: Vec<f32>,
+}
+
+const FAR: f32 = 1e20;
+
+// squared distance transform of a sampled function, see
+// "Distance Transforms of Sampled Functions", Felzenszwalb & Huttenlocher
+fn distance_transform_1d(f: &[f32], d: &mut [f32], v: &mut [usize], z: &mut [f32]) {
+    let n = f.len();
+    let mut k = 0;
+    v[0] = 0;
+    z[0] = f32::NEG_INFINITY;
+    z[1] = f32::INFINITY;
+    for q in 1..n {
+        let qf = q as f32;
+        let mut s;
+        loop {
+            let p = v[k] as f32;
+            s = ((f[q] + qf * qf) - (f[v[k]] + p * p)) / (2.0 * qf - 2.0 * p);
+            if s <= z[k] {
+                k -= 1;
+            } else {
+                break;
+            }
+        }
+        k += 1;
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = f32::INFINITY;
+    }
+    k = 0;
+    for (q, d) in d.iter_mut().enumerate() {
+        let qf = q as f32;
+        while z[k + 1] < qf {
+            k += 1;
+        }
+        let p = v[k] as f32;
+        *d = (qf - p) * (qf - p) + f[v[k]];
+    }
+}
+
+impl SvtDistanceField {
+    pub fn extent(&self) -> UVec3 {
+        UVec3::splat(self.dim)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        bytemuck::cast_slice(&self.distances)
+    }
+
+    pub fn get(&self, cell: UVec3) -> f32 {
+        self.distances[(cell.x + cell.y * self.dim + cell.z * self.dim * self.dim) as usize]
+    }
+
+    /**
+     * how far we can go from `position` (in voxels) without touching anything solid.
+     * outside of the tree, this is 0
+     */
+    pub fn sample(&self, position: Vec3) -> f32 {
+        let total = (self.dim * self.cell_size) as f32;
+        if position.min_element() < 0.0 || position.max_element() > total {
+            return 0.0;
+        }
+        let cell = (position / (self.cell_size as f32)).min(Vec3::splat((self.dim - 1) as f32));
+        self.get(vec3_to_usvt3(cell))
+    }
+}
+
+impl<T: SvtData, REF: Deref<Target = [usvt]>, const BLOCK_DIM: usvt, const LEVEL_COUNT: usize>
+    Svt<T, REF, BLOCK_DIM, LEVEL_COUNT>
+{
+    /**
+     * `level` decides the resolution, each cell is a block at that level. it is in
+     * `0..LEVEL_COUNT`, the last one has a cell for each voxel
+     */
+    pub fn distance_field(&self, level: usvt) -> SvtDistanceField {
+        assert!(level < LEVEL_COUNT as usvt, "level {} is not below {}", level, LEVEL_COUNT);
+        let dim = BLOCK_DIM.pow(level + 1);
+        let cell_size = Self::TOTAL_DIM / dim;
+        let n = dim as usize;
+        let mut grid = vec![FAR; n * n * n];
+        self.visit_solid_blocks(&mut |_| true, &mut |block, _, _| {
+            let min = vec3_to_usvt3(block.min) / cell_size;
+            // a block might be smaller than a cell
+            let max = (vec3_to_usvt3(block.max) + cell_size - 1) / cell_size;
+            for z in min.z..max.z {
+                for y in min.y..max.y {
+                    for x in min.x..max.x {
+                        grid[(x + y * dim + z * dim * dim) as usize] = 0.0;
+                    }
+                }
+            }
+            false
+        });
+
+        // one pass per axis
+        let mut f = vec![0.0; n];
+        let mut d = vec![0.0; n];
+        let mut v = vec![0; n];
+        let mut z = vec![0.0; n + 1];
+        for stride in [1, n, n * n] {
+            for i in 0..n * n {
+                // the first cell of this line
+                let start = if stride == 1 {
+                    i * n
+                } else if stride == n {
+                    (i / n) * n * n + i % n
+                } else {
+                    i
+                };
+                for k in 0..n {
+                    f[k] = grid[start + k * stride];
+                }
+                distance_transform_1d(&f, &mut d, &mut v, &mut z);
+                for k in 0..n {
+                    grid[start + k * stride] = d[k];
+                }
+            }
+        }
+
+        // cells are centered, so points in the two cells can be closer by a diagonal
+        let max_distance = Self::TOTAL_DIM as f32 * 3.0f32.sqrt();
+        let distances = grid
+            .iter()
+            .map(|d2| ((d2.sqrt() - 3.0f32.sqrt()).max(0.0) * cell_size as f32).min(max_distance))
+            .collect();
+        SvtDistanceField {
+            dim,
+            cell_size,
+            distances,
+        }
+    }
+
+    /**
+     * same as `cast_ray`, but skips empty space using `field`.
+     * assume normalized ray
+     */
+    pub fn cast_ray_with_distance_field(
+        &self,
+        field: &SvtDistanceField,
+        max_iter: u32,
+        ray: Ray3,
+    ) -> Result<Option<VoxelHit<T>>, TraverseRayError> {
+        let aabb = Aabb3::new(Vec3::ZERO, Vec3::splat(Self::TOTAL_DIM as f32));
+        let mut t = aabb.hit_fast1(&ray);
+        if t < 0.0 {
+            return Ok(None);
+        }
+        // where the ray leaves the tree
+        let block_limit = ray.dir.sign().non_neg_mul(Vec3::splat(Self::TOTAL_DIM as f32));
+        let t_far = ((block_limit - ray.pos) / ray.dir).min_element();
+        let mut count = 0;
+        // we move the origin instead of only `t`, so the position we checked in the closure
+        // is exactly where we skip from, `ray.at(t + dt)` can round back into the old cell
+        let mut pos = ray.at(t);
+        loop {
+            // skip, `sample` is 0 when we leave the tree
+            loop {
+                let step = field.sample(pos);
+                if step < 1.0 {
+                    break;
+                }
+                t += step;
+                pos += ray.dir * step;
+                count += 1;
+                if count > max_iter {
+                    return Err(TraverseRayError::MaxIteration);
+                }
+            }
+            if t >= t_far {
+                return Ok(None);
+            }
+            // walk the tree until we are in empty space again
+            let sub = Ray3 { pos, dir: ray.dir, time: ray.time };
+            let mut result = None;
+            let mut resume = None;
+            let steps = self.traverse_ray(max_iter.saturating_sub(count), sub, |info| {
+                if info.data != T::EMPTY {
+                    let mut hit = VoxelHit::from_info(&sub, info);
+                    hit.t += t;
+                    hit.t_exit += t;
+                    result = Some(hit);
+                    true
+                } else if field.sample(sub.at(info.out_info.t)) >= 1.0 {
+                    resume = Some(info.out_info.t);
+                    true
+                } else {
+                    false
+                }
+            })?;
+            count += steps.max(1);
+            match resume {
+                Some(dt) if result.is_none() => {
+                    t += dt;
+                    pos = sub.at(dt);
+                }
+                _ => return Ok(result),
+            }
+            if count > max_iter {
+                return Err(TraverseRayError::MaxIteration);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    type MyGrid = SvtMut<usvt, 4, 3>;
+
+    fn random_grid() -> MyGrid {
+        let mut svt = MyGrid::new(0);
+        let mut rng = rand::thread_rng();
+        for _ in 0..20 {
+            let v = uvec3(rng.gen_range(0..64), rng.gen_range(0..64), rng.gen_range(0..64));
+            svt.set(v, rng.gen_range(1..4));
+        }
+        svt
+    }
+
+    #[test]
+    fn distance_field_is_conservative() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..5 {
+            let svt = random_grid();
+            for level in 0..3 {
+                let field = svt.distance_field(level);
+                assert_eq!(field.as_bytes().len(), (field.dim * field.dim * field.dim * 4) as usize);
+                for _ in 0..1000 {
+                    let p = vec3(rng.gen(), rng.gen(), rng.gen()) * 64.0;
+                    let safe = field.sample(p);
+                    let closest = svt.closest_solid(p, 1000.0).unwrap();
+                    assert!(safe <= closest.distance + 1e-4);
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn distance_field_level_out_of_range() {
+        MyGrid::new(0).distance_field(3);
+    }
+
+    #[test]
+    fn distance_field_cast_ray() {
+        let mut rng = rand::thread_rng();
+        let svt = random_grid();
+        let field = svt.distance_field(2);
+        let mut skipped = 0;
+        for _ in 0..10000 {
+            let ray = Ray3 {
+                pos: vec3(rng.gen(), rng.gen(), rng.gen()) * 128.0 - 32.0,
+                dir: (vec3(rng.gen(), rng.gen(), rng.gen()) * 2.0 - 1.0).try_normalize_or(Vec3::X),
+                time: 0.0,
+            };
+            let expected = svt.cast_ray(10000, ray).unwrap();
+            let got = svt.cast_ray_with_distance_field(&field, 10000, ray).unwrap();
+            match (expected, got) {
+                (None, None) => {}
+                (Some(e), Some(g)) => {
+                    assert_eq!(e.voxel, g.voxel);
+                    assert!((e.t - g.t).abs() < 1e-2);
+                    skipped += 1;
+                }
+                _ => panic!(),
+            }
+        }
+        assert!(skipped > 0);
+    }
+}
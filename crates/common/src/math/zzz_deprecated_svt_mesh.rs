@@ -0,0 +1,359 @@
+use core::ops::Deref;
+use std::io::{self, Write};
+
+use super::aabb::*;
+use super::axis::*;
+use super::vec::*;
+use super::zzz_deprecated_svt::*;
+
+// greedy meshing, see https://0fps.net/2012/06/30/meshing-in-a-minecraft-game/
+// and https://0fps.net/2013/07/03/ambient-occlusion-for-minecraft-like-worlds/
+
+/**
+ * all the quads of a single material, positions are in voxel units
+ */
+pub struct VoxelMeshGroup<T: SvtData> {
+    pub material: T,
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    /// per vertex, 1.0 is not occluded. empty if not asked for
+    pub ao: Vec<f32>,
+    pub indices: Vec<u32>,
+}
+
+impl<T: SvtData> VoxelMeshGroup<T> {
+    fn new(material: T) -> Self {
+        Self {
+            material,
+            positions: Vec::new(),
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            ao: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    pub fn quad_count(&self) -> usize {
+        self.indices.len() / 6
+    }
+
+    fn push_quad(&mut self, quad: Aap3, normal: Vec3, ao: Option<[u32; 4]>) {
+        let uv = [
+            quad.extent.min,
+            vec2(quad.extent.min.x, quad.extent.max.y),
+            quad.extent.max,
+            vec2(quad.extent.max.x, quad.extent.min.y),
+        ];
+        let points = quad.points();
+        let first = self.positions.len() as u32;
+        for i in 0..4 {
+            self.positions.push(points[i].to_array());
+            self.normals.push(normal.to_array());
+            self.uvs.push(uv[i].to_array());
+            if let Some(ao) = ao {
+                self.ao.push(ao[i] as f32 / 3.0);
+            }
+        }
+        // split along the brighter diagonal, so the interpolation doesn't look anisotropic
+        let flip = ao.map_or(false, |ao| ao[1] + ao[3] > ao[0] + ao[2]);
+        let tris = if flip { [1, 2, 3, 1, 3, 0] } else { [0, 1, 2, 0, 2, 3] };
+        // points of `Aap3` are not always counter clockwise
+        let ccw = (points[1] - points[0]).cross(points[2] - points[0]).dot(normal) > 0.0;
+        for t in 0..2 {
+            let a = tris[t * 3];
+            let (b, c) = if ccw {
+                (tris[t * 3 + 1], tris[t * 3 + 2])
+            } else {
+                (tris[t * 3 + 2], tris[t * 3 + 1])
+            };
+            self.indices.push(first + a);
+            self.indices.push(first + b);
+            self.indices.push(first + c);
+        }
+    }
+}
+
+pub struct VoxelMesh<T: SvtData> {
+    pub groups: Vec<VoxelMeshGroup<T>>,
+}
+
+impl<T: SvtData> VoxelMesh<T> {
+    pub fn quad_count(&self) -> usize {
+        self.groups.iter().map(|g| g.quad_count()).sum()
+    }
+
+    fn group_mut(&mut self, material: T) -> &mut VoxelMeshGroup<T> {
+        let index = match self.groups.iter().position(|g| g.material == material) {
+            Some(index) => index,
+            None => {
+                self.groups.push(VoxelMeshGroup::new(material));
+                self.groups.len() - 1
+            }
+        };
+        &mut self.groups[index]
+    }
+
+    /**
+     * one `usemtl material_{data}` per group, ao is written as vertex colors
+     */
+    pub fn write_obj<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "o voxels")?;
+        let mut offset = 1;
+        for group in &self.groups {
+            for (i, p) in group.positions.iter().enumerate() {
+                match group.ao.get(i) {
+                    Some(ao) => writeln!(w, "v {} {} {} {} {} {}", p[0], p[1], p[2], ao, ao, ao)?,
+                    None => writeln!(w, "v {} {} {}", p[0], p[1], p[2])?,
+                }
+            }
+            for uv in &group.uvs {
+                writeln!(w, "vt {} {}", uv[0], uv[1])?;
+            }
+            for n in &group.normals {
+                writeln!(w, "vn {} {} {}", n[0], n[1], n[2])?;
+            }
+            writeln!(w, "usemtl material_{}", group.material.into())?;
+            for tri in group.indices.chunks(3) {
+                write!(w, "f")?;
+                for i in tri {
+                    let i = i + offset;
+                    write!(w, " {}/{}/{}", i, i, i)?;
+                }
+                writeln!(w)?;
+            }
+            offset += group.positions.len() as u32;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct FaceKey<T: SvtData> {
+    material: T,
+    ao: [u32; 4],
+}
+
+impl<T: SvtData, REF: Deref<Target = [usvt]>, const BLOCK_DIM: usvt, const LEVEL_COUNT: usize>
+    Svt<T, REF, BLOCK_DIM, LEVEL_COUNT>
+{
+    /**
+     * a mesh of the boundary between solid and empty voxels, with quads of the same material merged.
+     * the faces are found on the sides of the solid blocks, with the neighbours looked up in the
+     * tree, then merged one slice at a time
+     */
+    pub fn greedy_mesh(&self, ambient_occlusion: bool) -> VoxelMesh<T> {
+        let dim = Self::TOTAL_DIM as i32;
+        let get = |p: IVec3| -> T {
+            if p.min_element() < 0 || p.max_element() >= dim {
+                T::EMPTY
+            } else {
+                self.get(p.as_uvec3()).into()
+            }
+        };
+        let solid = |p: IVec3| get(p) != T::EMPTY;
+        // the coordinates of `p` in the slices along `axis`, the other way of `extend_axis`
+        let in_slice = |p: IVec3, axis: Axis3| -> (i32, i32) {
+            match axis {
+                Axis3::X => (p.y, p.z),
+                Axis3::Y => (p.x, p.z),
+                _ => (p.x, p.y),
+            }
+        };
+
+        // the visible faces in each slice, by axis, side and depth. the voxels inside a block are
+        // all solid, so only the sides of the blocks can have faces
+        let mut slices: Vec<Vec<(i32, i32, FaceKey<T>)>> = vec![Vec::new(); 6 * dim as usize];
+        self.visit_solid_blocks(&mut |_| true, &mut |block, _, data| {
+            let min = block.min.as_ivec3();
+            let max = block.max.as_ivec3();
+            for a in 0..3 {
+                let axis = Axis3::from_raw(a);
+                let axis_i = axis.as_vec3().as_ivec3();
+                let (min_u, min_v) = in_slice(min, axis);
+                let (max_u, max_v) = in_slice(max, axis);
+                for positive in [false, true] {
+                    let front = if positive { axis_i } else { -axis_i };
+                    let d = if positive { max[a as usize] - 1 } else { min[a as usize] };
+                    let slice = &mut slices[((a * 2 + positive as u32) as i32 * dim + d) as usize];
+                    for v in min_v..max_v {
+                        for u in min_u..max_u {
+                            let p = vec2(u as f32, v as f32).extend_axis(axis, d as f32).as_ivec3();
+                            if solid(p + front) {
+                                continue;
+                            }
+                            let ao = if ambient_occlusion {
+                                let mut ao = [3; 4];
+                                // same order as `Aap3::points`
+                                for (i, corner) in [(-1, -1), (-1, 1), (1, 1), (1, -1)].iter().enumerate() {
+                                    let du = vec2(corner.0 as f32, 0.0).extend_axis(axis, 0.0).as_ivec3();
+                                    let dv = vec2(0.0, corner.1 as f32).extend_axis(axis, 0.0).as_ivec3();
+                                    let side1 = solid(p + front + du);
+                                    let side2 = solid(p + front + dv);
+                                    let corner = solid(p + front + du + dv);
+                                    ao[i] = if side1 && side2 {
+                                        0
+                                    } else {
+                                        3 - side1 as u32 - side2 as u32 - corner as u32
+                                    };
+                                }
+                                ao
+                            } else {
+                                [3; 4]
+                            };
+                            slice.push((u, v, FaceKey { material: data, ao }));
+                        }
+                    }
+                }
+            }
+            false
+        });
+
+        let mut mesh = VoxelMesh { groups: Vec::new() };
+        // the faces of one slice, merged faces are taken out so it is empty again after each slice
+        let mut mask: Vec<Option<FaceKey<T>>> = vec![None; (dim * dim) as usize];
+        for (s, faces) in slices.iter_mut().enumerate() {
+            if faces.is_empty() {
+                continue;
+            }
+            let axis = Axis3::from_raw(s as u32 / 2 / dim as u32);
+            let positive = s as i32 / dim % 2 == 1;
+            let d = s as i32 % dim;
+            let normal = if positive { axis.as_vec3() } else { -axis.as_vec3() };
+            for (u, v, key) in faces.iter() {
+                mask[(u + v * dim) as usize] = Some(*key);
+            }
+            // merge them, starting from the faces by rows like a scan of the whole slice
+            faces.sort_by_key(|(u, v, _)| (*v, *u));
+            for &(u, v, _) in faces.iter() {
+                let key = match mask[(u + v * dim) as usize] {
+                    Some(key) => key,
+                    None => continue,
+                };
+                // quads with varying ao can't be merged, the interpolation would be wrong
+                let mergeable = key.ao.iter().all(|x| *x == key.ao[0]);
+                let mut w = 1;
+                while mergeable && u + w < dim && mask[(u + w + v * dim) as usize] == Some(key) {
+                    w += 1;
+                }
+                let mut h = 1;
+                'grow: while mergeable && v + h < dim {
+                    for k in 0..w {
+                        if mask[(u + k + (v + h) * dim) as usize] != Some(key) {
+                            break 'grow;
+                        }
+                    }
+                    h += 1;
+                }
+                for j in 0..h {
+                    for k in 0..w {
+                        mask[(u + k + (v + j) * dim) as usize] = None;
+                    }
+                }
+                let quad = Aap3 {
+                    axis,
+                    pos: (d + positive as i32) as f32,
+                    extent: Aabb2 {
+                        min: vec2(u as f32, v as f32),
+                        max: vec2((u + w) as f32, (v + h) as f32),
+                    },
+                };
+                mesh.group_mut(key.material).push_quad(
+                    quad,
+                    normal,
+                    if ambient_occlusion { Some(key.ao) } else { None },
+                );
+            }
+        }
+        mesh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type MyGrid = SvtMut<usvt, 4, 2>;
+
+    #[test]
+    fn greedy_mesh() {
+        let mut svt = MyGrid::new(0);
+        svt.set(Usvt3::new(1, 1, 1), 1);
+        let mesh = svt.greedy_mesh(false);
+        assert_eq!(mesh.quad_count(), 6);
+        for (p, n) in mesh.groups[0].positions.iter().zip(mesh.groups[0].normals.iter()) {
+            // the center of the voxel is behind every face
+            let to_center = Vec3::splat(1.5) - Vec3::from(*p);
+            assert!(to_center.dot(Vec3::from(*n)) < 0.0);
+        }
+        // triangles face outwards
+        let g = &mesh.groups[0];
+        for tri in g.indices.chunks(3) {
+            let p = |i: u32| Vec3::from(g.positions[i as usize]);
+            let n = (p(tri[1]) - p(tri[0])).cross(p(tri[2]) - p(tri[0]));
+            assert!(n.dot(Vec3::from(g.normals[tri[0] as usize])) > 0.0);
+        }
+
+        // a bar of 3 is still 6 quads
+        svt.set(Usvt3::new(2, 1, 1), 1);
+        svt.set(Usvt3::new(3, 1, 1), 1);
+        assert_eq!(svt.greedy_mesh(false).quad_count(), 6);
+
+        // different material is not merged
+        svt.set(Usvt3::new(3, 1, 1), 2);
+        let mesh = svt.greedy_mesh(false);
+        assert_eq!(mesh.groups.len(), 2);
+        assert_eq!(mesh.quad_count(), 10);
+
+        let mut obj = Vec::new();
+        mesh.write_obj(&mut obj).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        assert_eq!(obj.lines().filter(|l| l.starts_with("f ")).count(), 20);
+        assert_eq!(obj.lines().filter(|l| l.starts_with("usemtl")).count(), 2);
+    }
+
+    #[test]
+    fn greedy_mesh_ao() {
+        let mut svt = MyGrid::new(0);
+        svt.set(Usvt3::new(1, 1, 1), 1);
+        svt.set(Usvt3::new(2, 2, 1), 1);
+        let mesh = svt.greedy_mesh(true);
+        let g = &mesh.groups[0];
+        assert_eq!(g.ao.len(), g.positions.len());
+        // the top face of (1, 1, 1) is occluded at the side of (2, 2, 1)
+        for ((p, n), ao) in g.positions.iter().zip(g.normals.iter()).zip(g.ao.iter()) {
+            if *n == [0.0, 1.0, 0.0] && p[1] == 2.0 {
+                assert_eq!(*ao, if p[0] == 2.0 { 2.0 / 3.0 } else { 1.0 });
+            }
+        }
+    }
+
+    #[test]
+    fn greedy_mesh_covers_every_face() {
+        // a ball, sampled so the inside is in bigger blocks
+        let mut svt = MyGrid::new(0);
+        svt.sample(&mut |p: UVec3| if (p.as_vec3() - 7.5).length() < 7.0 { 1 } else { 0 });
+        let mut big_blocks = 0;
+        svt.visit_solid_blocks(&mut |_| true, &mut |block, _, _| {
+            big_blocks += (block.max.x - block.min.x > 1.0) as usize;
+            false
+        });
+        assert!(big_blocks > 0);
+        let solid = |p: IVec3| p.min_element() >= 0 && p.max_element() < 16 && svt.get(p.as_uvec3()) != 0;
+        let mut faces = 0;
+        for i in 0..16 * 16 * 16 {
+            let p = ivec3(i % 16, i / 16 % 16, i / 256);
+            if solid(p) {
+                for d in [IVec3::X, -IVec3::X, IVec3::Y, -IVec3::Y, IVec3::Z, -IVec3::Z] {
+                    faces += !solid(p + d) as usize;
+                }
+            }
+        }
+        // the quads cover each face between a solid and an empty voxel once
+        let g = &svt.greedy_mesh(false).groups[0];
+        let p = |i: u32| Vec3::from(g.positions[i as usize]);
+        let area: f32 = g.indices.chunks(3).map(|t| (p(t[1]) - p(t[0])).cross(p(t[2]) - p(t[0])).length() / 2.0).sum();
+        assert_eq!(area, faces as f32);
+        assert!(g.quad_count() < faces);
+    }
+}
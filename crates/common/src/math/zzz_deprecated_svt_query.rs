@@ -0,0 +1,402 @@
+use core::ops::Deref;
+
+use super::aabb::*;
+use super::axis::*;
+use super::vec::*;
+use super::zzz_deprecated_svt::*;
+
+// host side collision queries, everything is in voxel units, outside of the tree is empty
+
+// rounded surfaces only count as hit when we are moving into them, so touching (like rolling on a floor
+// made of voxels) doesn't get stuck on the seams
+const SWEEP_EPS: f32 = 1e-4;
+
+#[derive(Copy, Clone, Debug)]
+pub struct SweepHit<T: SvtData> {
+    /// fraction of the motion vector, 0 if the shape is already overlapping
+    pub t: f32,
+    /// points away from the solid. when already overlapping, it is the direction to push out
+    pub normal: Vec3,
+    pub block: Aabb3,
+    pub level: usvt,
+    pub data: T,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ClosestSolid<T: SvtData> {
+    pub distance: f32,
+    /// closest point on the solid block
+    pub point: Vec3,
+    pub voxel: Usvt3,
+    pub block: Aabb3,
+    pub level: usvt,
+    pub data: T,
+}
+
+impl<T: SvtData, REF: Deref<Target = [usvt]>, const BLOCK_DIM: usvt, const LEVEL_COUNT: usize>
+    Svt<T, REF, BLOCK_DIM, LEVEL_COUNT>
+{
+    /**
+     * visit all non-empty terminal blocks that pass `filter`, stops when closure returns true.
+     * returns if it is stopped
+     */
+    pub(crate) fn visit_solid_blocks<F, C>(&self, filter: &mut F, closure: &mut C) -> bool
+    where
+        F: FnMut(&Aabb3) -> bool,
+        C: FnMut(Aabb3, usvt, T) -> bool,
+    {
+        self.visit_solid_blocks_rec(
+            self.root_block_index() * (Self::BLOCK_SIZE as usize),
+            0,
+            Usvt3::ZERO,
+            filter,
+            closure,
+        )
+    }
+
+    fn visit_solid_blocks_rec<F, C>(
+        &self,
+        first_block_index: usize,
+        level: usvt,
+        origin: Usvt3,
+        filter: &mut F,
+        closure: &mut C,
+    ) -> bool
+    where
+        F: FnMut(&Aabb3) -> bool,
+        C: FnMut(Aabb3, usvt, T) -> bool,
+    {
+        let size = BLOCK_DIM.pow(LEVEL_COUNT as u32 - 1 - level);
+        for z in 0..BLOCK_DIM {
+            for y in 0..BLOCK_DIM {
+                for x in 0..BLOCK_DIM {
+                    let level_position = Usvt3::new(x, y, z);
+                    let min = origin + level_position * size;
+                    let block = Aabb3::new(min.as_vec3(), (min + size).as_vec3());
+                    if !filter(&block) {
+                        continue;
+                    }
+                    let target_block = self.mem[first_block_index + Self::encode(level_position)];
+                    let index = Self::block_index_data(target_block);
+                    if Self::is_terminal_block(target_block) {
+                        let data: T = index.into();
+                        if data != T::EMPTY && closure(block, level, data) {
+                            return true;
+                        }
+                    } else if self.visit_solid_blocks_rec(
+                        index as usize * (Self::BLOCK_SIZE as usize),
+                        level + 1,
+                        min,
+                        filter,
+                        closure,
+                    ) {
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    pub fn overlaps_aabb(&self, aabb: &Aabb3) -> bool {
+        self.visit_solid_blocks(&mut |block| block.overlaps(aabb), &mut |_, _, _| true)
+    }
+
+    fn sweep_closest<C>(&self, bounds: Aabb3, mut hit: C) -> Option<SweepHit<T>>
+    where
+        C: FnMut(&Aabb3) -> Option<(f32, Vec3)>,
+    {
+        let mut result: Option<SweepHit<T>> = None;
+        self.visit_solid_blocks(&mut |block| block.overlaps(&bounds), &mut |block, level, data| {
+            if let Some((t, normal)) = hit(&block) {
+                if result.map_or(true, |r| t < r.t) {
+                    result = Some(SweepHit {
+                        t,
+                        normal,
+                        block,
+                        level,
+                        data,
+                    });
+                }
+            }
+            false
+        });
+        result
+    }
+
+    /**
+     * move `aabb` by `motion`, find the first solid block it runs into.
+     * blocks that are just touching `aabb` don't count, so sliding on a floor is ok
+     */
+    pub fn sweep_aabb(&self, aabb: &Aabb3, motion: Vec3) -> Option<SweepHit<T>> {
+        let bounds = aabb.union(&(*aabb + motion));
+        let half = (aabb.max - aabb.min) * 0.5;
+        let center = aabb.center();
+        self.sweep_closest(bounds, |block| {
+            if block.overlaps(aabb) {
+                return Some((0.0, push_out_normal(block, aabb)));
+            }
+            let expanded = Aabb3::new(block.min - half, block.max + half);
+            sweep_point_aabb(center, motion, &expanded)
+        })
+    }
+
+    pub fn sweep_sphere(&self, center: Vec3, radius: f32, motion: Vec3) -> Option<SweepHit<T>> {
+        let r = Vec3::splat(radius);
+        let aabb = Aabb3::new(center - r, center + r);
+        let bounds = aabb.union(&(aabb + motion));
+        self.sweep_closest(bounds, |block| {
+            let closest = block.closest_point(center);
+            if (center - closest).length_squared() < radius * radius {
+                let normal = (center - closest).try_normalize_or(Vec3::ZERO);
+                let normal = if normal == Vec3::ZERO {
+                    // center is inside the block
+                    push_out_normal(block, &Aabb3::new(center, center))
+                } else {
+                    normal
+                };
+                return Some((0.0, normal));
+            }
+            sweep_point_rounded_aabb(center, motion, block, radius)
+        })
+    }
+
+    /**
+     * the solid block closest to `point` within `max_distance`
+     */
+    pub fn closest_solid(&self, point: Vec3, max_distance: f32) -> Option<ClosestSolid<T>> {
+        let mut result: Option<ClosestSolid<T>> = None;
+        // shared by both closures, as the search radius shrinks while we go
+        let best = core::cell::Cell::new(max_distance * max_distance);
+        self.visit_solid_blocks(
+            &mut |block| (block.closest_point(point) - point).length_squared() <= best.get(),
+            &mut |block, level, data| {
+                let closest = block.closest_point(point);
+                let distance2 = (closest - point).length_squared();
+                // the filter already made sure it is within `best`
+                if result.is_none() || distance2 < best.get() {
+                    best.set(distance2);
+                    let voxel = vec3_to_usvt3(closest.min(block.max - 1.0));
+                    result = Some(ClosestSolid {
+                        distance: distance2.sqrt(),
+                        point: closest,
+                        voxel,
+                        block,
+                        level,
+                        data,
+                    });
+                }
+                false
+            },
+        );
+        result
+    }
+}
+
+// normal to push `aabb` out of `block` by the shortest way
+fn push_out_normal(block: &Aabb3, aabb: &Aabb3) -> Vec3 {
+    let mut best = f32::INFINITY;
+    let mut normal = Vec3::ZERO;
+    for a in 0..3 {
+        let a = Axis3::from_raw(a);
+        let positive = block.max.get(a) - aabb.min.get(a);
+        let negative = aabb.max.get(a) - block.min.get(a);
+        if positive < best {
+            best = positive;
+            normal = a.as_vec3();
+        }
+        if negative < best {
+            best = negative;
+            normal = -a.as_vec3();
+        }
+    }
+    normal
+}
+
+// first time in [0, 1] the moving point enters the box, grazing the faces doesn't count
+fn sweep_point_aabb(pos: Vec3, motion: Vec3, aabb: &Aabb3) -> Option<(f32, Vec3)> {
+    let mut t_enter = f32::NEG_INFINITY;
+    let mut t_exit = f32::INFINITY;
+    let mut normal = Vec3::ZERO;
+    for a in 0..3 {
+        let a = Axis3::from_raw(a);
+        let p = pos.get(a);
+        let d = motion.get(a);
+        let min = aabb.min.get(a);
+        let max = aabb.max.get(a);
+        if d == 0.0 {
+            if p <= min || p >= max {
+                return None;
+            }
+            continue;
+        }
+        let inv = 1.0 / d;
+        let (t0, t1, n) = if d > 0.0 {
+            ((min - p) * inv, (max - p) * inv, -a.as_vec3())
+        } else {
+            ((max - p) * inv, (min - p) * inv, a.as_vec3())
+        };
+        if t0 > t_enter {
+            t_enter = t0;
+            normal = n;
+        }
+        t_exit = t_exit.min(t1);
+    }
+    if (0.0..=1.0).contains(&t_enter) && t_enter < t_exit {
+        Some((t_enter, normal))
+    } else {
+        None
+    }
+}
+
+fn sweep_point_sphere(pos: Vec3, motion: Vec3, center: Vec3, radius: f32) -> Option<(f32, Vec3)> {
+    let oc = pos - center;
+    let a = motion.length_squared();
+    let b = motion.dot(oc);
+    let c = oc.length_squared() - radius * radius;
+    let discriminant = b * b - a * c;
+    if a == 0.0 || discriminant <= 0.0 {
+        return None;
+    }
+    let t = (-b - discriminant.sqrt()) / a;
+    let normal = (pos + motion * t - center) / radius;
+    if (0.0..=1.0).contains(&t) && normal.dot(motion) < -SWEEP_EPS * a.sqrt() {
+        Some((t, normal))
+    } else {
+        None
+    }
+}
+
+// the side of a infinite cylinder, only counts if the hit is between `from` and `to` along the axis
+fn sweep_point_edge(
+    pos: Vec3,
+    motion: Vec3,
+    axis: Axis3,
+    edge: Vec2,
+    from: f32,
+    to: f32,
+    radius: f32,
+) -> Option<(f32, Vec3)> {
+    let p = pos.omit_axis(axis);
+    let m = motion.omit_axis(axis);
+    let oc = p - edge;
+    let a = m.length_squared();
+    let b = m.dot(oc);
+    let c = oc.length_squared() - radius * radius;
+    let discriminant = b * b - a * c;
+    if a == 0.0 || discriminant <= 0.0 {
+        return None;
+    }
+    let t = (-b - discriminant.sqrt()) / a;
+    let along = pos.get(axis) + motion.get(axis) * t;
+    let normal = (p + m * t - edge) / radius;
+    if (0.0..=1.0).contains(&t) && (from..=to).contains(&along) && normal.dot(m) < -SWEEP_EPS * motion.length() {
+        Some((t, normal.extend_axis(axis, 0.0)))
+    } else {
+        None
+    }
+}
+
+// the box with rounded corners is the union of: the box expanded along each axis, the edge cylinders and the corner spheres
+fn sweep_point_rounded_aabb(pos: Vec3, motion: Vec3, aabb: &Aabb3, radius: f32) -> Option<(f32, Vec3)> {
+    let mut result: Option<(f32, Vec3)> = None;
+    let mut update = |candidate: Option<(f32, Vec3)>| {
+        if let Some((t, n)) = candidate {
+            if result.map_or(true, |(rt, _)| t < rt) {
+                result = Some((t, n));
+            }
+        }
+    };
+    for a in 0..3 {
+        let a = Axis3::from_raw(a);
+        let r = a.as_vec3() * radius;
+        update(sweep_point_aabb(pos, motion, &Aabb3::new(aabb.min - r, aabb.max + r)));
+        let from = aabb.min.get(a);
+        let to = aabb.max.get(a);
+        let side = aabb.omit(a);
+        for edge in [
+            side.min,
+            side.max,
+            vec2(side.min.x, side.max.y),
+            vec2(side.max.x, side.min.y),
+        ] {
+            update(sweep_point_edge(pos, motion, a, edge, from, to, radius));
+        }
+    }
+    for i in 0..8 {
+        let corner = vec3(
+            if i & 1 == 0 { aabb.min.x } else { aabb.max.x },
+            if i & 2 == 0 { aabb.min.y } else { aabb.max.y },
+            if i & 4 == 0 { aabb.min.z } else { aabb.max.z },
+        );
+        update(sweep_point_sphere(pos, motion, corner, radius));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type MyGrid = SvtMut<usvt, 4, 3>;
+
+    fn floor() -> MyGrid {
+        let mut svt = MyGrid::new(0);
+        for x in 0..MyGrid::TOTAL_DIM {
+            for z in 0..MyGrid::TOTAL_DIM {
+                svt.set(Usvt3::new(x, 10, z), 1);
+            }
+        }
+        svt.set(Usvt3::new(30, 11, 30), 2);
+        svt
+    }
+
+    #[test]
+    fn overlap() {
+        let svt = floor();
+        assert!(svt.overlaps_aabb(&Aabb3::new(vec3(5.0, 10.5, 5.0), vec3(6.0, 12.0, 6.0))));
+        // standing on the floor is not overlapping
+        assert!(!svt.overlaps_aabb(&Aabb3::new(vec3(5.0, 11.0, 5.0), vec3(6.0, 13.0, 6.0))));
+        assert!(!svt.overlaps_aabb(&Aabb3::new(vec3(-5.0, 20.0, 5.0), vec3(6.0, 23.0, 6.0))));
+    }
+
+    #[test]
+    fn sweep() {
+        let svt = floor();
+        let aabb = Aabb3::new(vec3(5.0, 15.0, 5.0), vec3(6.0, 17.0, 6.0));
+        let hit = svt.sweep_aabb(&aabb, vec3(0.0, -8.0, 0.0)).unwrap();
+        assert_eq!(hit.t, 0.5);
+        assert_eq!(hit.normal, Vec3::Y);
+        assert_eq!(hit.data, 1);
+        // sliding on the floor, then hitting the bump
+        let aabb = Aabb3::new(vec3(9.0, 11.0, 30.5), vec3(10.0, 13.0, 31.5));
+        let hit = svt.sweep_aabb(&aabb, vec3(40.0, 0.0, 0.0)).unwrap();
+        assert_eq!(hit.t, 0.5);
+        assert_eq!(hit.normal, -Vec3::X);
+        assert_eq!(hit.data, 2);
+        assert!(svt.sweep_aabb(&aabb, vec3(0.0, 0.0, -10.0)).is_none());
+
+        let hit = svt.sweep_sphere(vec3(5.5, 16.0, 5.5), 1.0, vec3(0.0, -8.0, 0.0)).unwrap();
+        assert_eq!(hit.t, 0.5);
+        assert_eq!(hit.normal, Vec3::Y);
+        // the sphere hits the top edge of the bump
+        let hit = svt
+            .sweep_sphere(vec3(20.0, 12.0 + 0.5f32.sqrt(), 30.5), 1.0, vec3(20.0, 0.0, 0.0))
+            .unwrap();
+        assert!((hit.normal - vec3(-1.0, 1.0, 0.0).normalize()).length() < 1e-4);
+        assert_eq!(hit.data, 2);
+    }
+
+    #[test]
+    fn closest() {
+        let svt = floor();
+        let closest = svt.closest_solid(vec3(30.5, 14.0, 30.5), 10.0).unwrap();
+        assert_eq!(closest.distance, 2.0);
+        assert_eq!(closest.voxel, Usvt3::new(30, 11, 30));
+        assert_eq!(closest.data, 2);
+        let closest = svt.closest_solid(vec3(5.5, 14.0, 5.5), 10.0).unwrap();
+        assert_eq!(closest.distance, 3.0);
+        assert_eq!(closest.voxel, Usvt3::new(5, 10, 5));
+        assert!(svt.closest_solid(vec3(5.5, 14.0, 5.5), 2.0).is_none());
+    }
+}
@@ -344,11 +344,11 @@ impl<T: SvtData, const BLOCK_DIM: usvt, const LEVEL_COUNT: usize> Svt<T, Vec<usv
 
 #[cfg(test)]
 mod tests {
-    use crate::Ray3;
+    use crate::{Axis3, Face3, Ray3, TraverseRayError, VoxelHit};
 
     use super::*;
     use image::{ImageBuffer, Rgb, RgbImage};
-    use rand::Rng;
+    use rand::{Rng, SeedableRng};
     use sdfu::SDF;
 
     #[test]
@@ -371,17 +371,18 @@ mod tests {
                         / (image_size as f32)
                         * (total as f32))
                         .normalize(),
+                    time: 0.0,
                 };
                 // let ray = Ray3 {
                 //     pos: Vec3::new(i as f32 + 0.4, j as f32 + 0.4, 0.1) / (image_size as f32) * (TOTAL as f32),
                 //     dir: Vec3::new(0.1, 0.1, 1.0),
                 // };
-                svt.traverse_ray(100, ray, |_, out_info, block| {
-                    let hit = block.data != 0;
+                let _ = svt.traverse_ray(100, ray, |info| {
+                    let hit = info.data != 0;
                     if hit {
                         let light_level = vec3(0.6, 0.75, 1.0);
                         let color =
-                            vec3(0.3, 0.7, 0.5) * light_level.dot(out_info.mask.as_vec3().abs()) * 255.0;
+                            vec3(0.3, 0.7, 0.5) * light_level.dot(info.out_info.mask.as_vec3().abs()) * 255.0;
                         image.put_pixel(i, j, Rgb([color.x as u8, color.y as u8, color.z as u8]));
                     }
                     return hit;
@@ -404,23 +405,295 @@ mod tests {
             svt.set(vec3_to_usvt3(v), i as usvt);
         }
         for _ in 0..100000 {
-            let error_code = svt.traverse_ray(
+            let result = svt.traverse_ray(
                 300,
                 Ray3 {
                     pos: vec3(rng.gen(), rng.gen(), rng.gen()) * size,
                     dir: (vec3(rng.gen(), rng.gen(), rng.gen()) * size * 2.0 - Vec3::splat(size))
                         .try_normalize_or(Vec3::X),
+                    time: 0.0,
                 },
-                |_, _, _| {
+                |_| {
                     return false;
                 },
             );
-            if error_code < 0 {
+            if result.is_err() {
                 panic!();
             }
         }
     }
 
+    #[test]
+    fn cast_ray_hit() {
+        let mut svt = MyGrid::new(0);
+        svt.set(Usvt3::new(10, 20, 30), 1);
+        let ray = Ray3 {
+            pos: vec3(10.5, 20.5, -5.0),
+            dir: Vec3::Z,
+            time: 0.0,
+        };
+        let hit = svt.cast_ray(1000, ray).unwrap().unwrap();
+        assert_eq!(hit.t, 35.0);
+        assert_eq!(hit.t_exit, 36.0);
+        assert_eq!(hit.voxel, Usvt3::new(10, 20, 30));
+        assert_eq!(hit.normal, -Vec3::Z);
+        assert!(hit.face == Face3 { axis: Axis3::Z, p: false });
+        assert_eq!(hit.level, 3);
+        assert_eq!(hit.data, 1);
+        assert_eq!(hit.first_face_aap().pos, 30.0);
+        assert!(svt.cast_ray_max(ray, 20.0).unwrap().is_none());
+        assert_eq!(
+            svt.cast_ray(1000, Ray3::default()).err(),
+            Some(TraverseRayError::ZeroDirection)
+        );
+    }
+
+    type OctGrid = Svt<usvt, Vec<usvt>, 2, 6>;
+
+    fn random_oct_grid(rng: &mut impl Rng) -> OctGrid {
+        let mut svt = OctGrid::new(0);
+        let total = OctGrid::TOTAL_DIM as f32;
+        // spheres give us large blocks at all levels
+        let spheres: Vec<(Vec3, f32, usvt)> = (0..rng.gen_range(0..8))
+            .map(|_| {
+                let center = vec3(rng.gen(), rng.gen(), rng.gen()) * total;
+                (center, rng.gen_range(2.0..total / 3.0), rng.gen_range(1..5))
+            })
+            .collect();
+        svt.sample(&mut |v| {
+            let p = v.as_vec3() + 0.5;
+            spheres
+                .iter()
+                .find(|(c, r, _)| p.distance(*c) < *r)
+                .map_or(0, |s| s.2)
+        });
+        for _ in 0..rng.gen_range(0..1000) {
+            let v = uvec3(
+                rng.gen_range(0..OctGrid::TOTAL_DIM),
+                rng.gen_range(0..OctGrid::TOTAL_DIM),
+                rng.gen_range(0..OctGrid::TOTAL_DIM),
+            );
+            svt.set(v, rng.gen_range(0..5));
+        }
+        svt
+    }
+
+    struct ReferenceHit {
+        voxel: Usvt3,
+        t: f64,
+        t_exit: f64,
+    }
+
+    // brute force Amanatides & Woo over unit voxels, in f64
+    fn reference_cast_ray(svt: &OctGrid, ray: Ray3) -> Option<ReferenceHit> {
+        let total = OctGrid::TOTAL_DIM as f64;
+        let pos = ray.pos.as_dvec3().to_array();
+        let dir = ray.dir.as_dvec3().to_array();
+        let mut t_enter = 0.0f64;
+        let mut t_leave = f64::INFINITY;
+        for a in 0..3 {
+            if dir[a] == 0.0 {
+                if pos[a] < 0.0 || pos[a] >= total {
+                    return None;
+                }
+            } else {
+                let t0 = -pos[a] / dir[a];
+                let t1 = (total - pos[a]) / dir[a];
+                t_enter = t_enter.max(t0.min(t1));
+                t_leave = t_leave.min(t0.max(t1));
+            }
+        }
+        if t_enter >= t_leave {
+            return None;
+        }
+        let mut voxel = [0i64; 3];
+        let mut step = [0i64; 3];
+        let mut t_next = [f64::INFINITY; 3];
+        for a in 0..3 {
+            voxel[a] = ((pos[a] + dir[a] * t_enter).floor() as i64).clamp(0, total as i64 - 1);
+            if dir[a] > 0.0 {
+                step[a] = 1;
+                t_next[a] = ((voxel[a] + 1) as f64 - pos[a]) / dir[a];
+            } else if dir[a] < 0.0 {
+                step[a] = -1;
+                t_next[a] = (voxel[a] as f64 - pos[a]) / dir[a];
+            }
+        }
+        let mut t = t_enter;
+        loop {
+            let a = if t_next[0] <= t_next[1] && t_next[0] <= t_next[2] {
+                0
+            } else if t_next[1] <= t_next[2] {
+                1
+            } else {
+                2
+            };
+            let current = uvec3(voxel[0] as u32, voxel[1] as u32, voxel[2] as u32);
+            if svt.get(current) != 0 {
+                return Some(ReferenceHit {
+                    voxel: current,
+                    t,
+                    t_exit: t_next[a],
+                });
+            }
+            t = t_next[a];
+            voxel[a] += step[a];
+            if voxel[a] < 0 || voxel[a] >= total as i64 {
+                return None;
+            }
+            t_next[a] += 1.0 / dir[a].abs();
+        }
+    }
+
+    // if the hit point is close to an edge of the voxel grid, the ray can legally enter another voxel
+    fn near_edge(p: Vec3) -> bool {
+        let d = (p - p.round()).abs();
+        let close = |f: f32| (f < 1e-3) as u32;
+        close(d.x) + close(d.y) + close(d.z) >= 2
+    }
+
+    fn check_against_reference(
+        name: &str,
+        svt: &OctGrid,
+        ray: Ray3,
+        got: Option<VoxelHit<usvt>>,
+        expected: Option<ReferenceHit>,
+    ) {
+        let context = || format!("{}: ray {:?} {:?}", name, ray.pos, ray.dir);
+        match (got, expected) {
+            (None, None) => {}
+            (Some(g), Some(e)) if (g.t as f64 - e.t).abs() < 1e-2 => {
+                assert_ne!(g.data, 0, "{}", context());
+                assert!(g.voxel.max_element() < OctGrid::TOTAL_DIM, "{}", context());
+                assert_eq!(svt.get(g.voxel), g.data, "{}", context());
+                assert!(
+                    g.voxel == e.voxel || near_edge(g.position),
+                    "{}: voxel {} expected {}",
+                    context(),
+                    g.voxel,
+                    e.voxel
+                );
+            }
+            (g, e) => {
+                // only fine if whichever comes first only touches its voxel
+                let g = g.map(|g| (g.t as f64, g.t_exit as f64));
+                let e = e.map(|e| (e.t, e.t_exit));
+                let first = match (g, e) {
+                    (Some(g), Some(e)) => if g.0 < e.0 { g } else { e },
+                    (Some(g), None) => g,
+                    (None, Some(e)) => e,
+                    _ => unreachable!(),
+                };
+                assert!(first.1 - first.0 < 1e-3, "{}: got {:?} expected {:?}", context(), g, e);
+            }
+        }
+    }
+
+    fn check_ray(svt: &OctGrid, ray: Ray3) {
+        let expected = || reference_cast_ray(svt, ray);
+        let traverse = svt.cast_ray(10000, ray).unwrap();
+        let oct = svt.cast_ray_oct(10000, ray).unwrap();
+        check_against_reference("cast_ray", svt, ray, traverse, expected());
+        check_against_reference("cast_ray_oct", svt, ray, oct, expected());
+        if let (Some(a), Some(b)) = (traverse, oct) {
+            if a.voxel == b.voxel {
+                // `cast_ray_oct` works in [1, 2], so it loses some bits
+                assert!((a.t - b.t).abs() < 1e-2, "ray {:?} {:?}: t {} {}", ray.pos, ray.dir, a.t, b.t);
+                assert_eq!(a.level, b.level);
+                assert_eq!(a.data, b.data);
+                if !near_edge(a.position) {
+                    assert_eq!(a.normal, b.normal, "ray {:?} {:?}", ray.pos, ray.dir);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cast_ray_oct_agrees_random() {
+        for seed in 0..20 {
+            let mut rng = rand_pcg::Pcg32::seed_from_u64(seed);
+            let svt = random_oct_grid(&mut rng);
+            for _ in 0..2000 {
+                let total = OctGrid::TOTAL_DIM as f32;
+                // half of them start inside the tree
+                let pos = if rng.gen() {
+                    vec3(rng.gen(), rng.gen(), rng.gen()) * total
+                } else {
+                    vec3(rng.gen(), rng.gen(), rng.gen()) * total * 2.0 - total * 0.5
+                };
+                let dir = (vec3(rng.gen(), rng.gen(), rng.gen()) * 2.0 - 1.0).try_normalize_or(Vec3::X);
+                check_ray(&svt, Ray3 { pos, dir, time: 0.0 });
+            }
+        }
+    }
+
+    #[test]
+    fn cast_ray_oct_agrees_axis_aligned() {
+        for seed in 0..20 {
+            let mut rng = rand_pcg::Pcg32::seed_from_u64(seed);
+            let svt = random_oct_grid(&mut rng);
+            let total = OctGrid::TOTAL_DIM as i32;
+            for _ in 0..2000 {
+                let axis = rng.gen_range(0..3);
+                let sign = if rng.gen() { 1.0 } else { -1.0 };
+                let mut dir = Vec3::ZERO;
+                dir[axis] = sign;
+                // away from the voxel boundaries, otherwise it is ambiguous which voxel we are in
+                let mut pos = vec3(
+                    rng.gen_range(0..total) as f32,
+                    rng.gen_range(0..total) as f32,
+                    rng.gen_range(0..total) as f32,
+                ) + vec3(rng.gen(), rng.gen(), rng.gen()) * 0.5
+                    + 0.25;
+                if rng.gen() {
+                    // from outside, including the max faces
+                    pos[axis] = if sign > 0.0 { -3.5 } else { total as f32 + 3.5 };
+                }
+                check_ray(&svt, Ray3 { pos, dir, time: 0.0 });
+            }
+        }
+    }
+
+    #[test]
+    fn cast_ray_oct_agrees_grazing() {
+        for seed in 0..20 {
+            let mut rng = rand_pcg::Pcg32::seed_from_u64(seed);
+            let svt = random_oct_grid(&mut rng);
+            let total = OctGrid::TOTAL_DIM as f32;
+            for _ in 0..2000 {
+                // almost parallel to a face, so we cross a lot of voxels on the other two axes
+                let axis = rng.gen_range(0..3);
+                let mut dir = vec3(rng.gen(), rng.gen(), rng.gen()) * 2.0 - 1.0;
+                dir[axis] = (rng.gen::<f32>() * 2.0 - 1.0) * 1e-3;
+                let dir = dir.try_normalize_or(Vec3::X);
+                let mut pos = vec3(rng.gen(), rng.gen(), rng.gen()) * total * 2.0 - total * 0.5;
+                pos[axis] = rng.gen_range(0..OctGrid::TOTAL_DIM) as f32 + 0.25 + rng.gen::<f32>() * 0.5;
+                check_ray(&svt, Ray3 { pos, dir, time: 0.0 });
+            }
+        }
+    }
+
+    #[test]
+    fn cast_ray_enter_from_max_face() {
+        // the "off by one", this used to report the voxel at x = 0 wrapped around
+        let mut svt = OctGrid::new(0);
+        let last = OctGrid::TOTAL_DIM - 1;
+        svt.set(Usvt3::new(0, 5, 5), 1);
+        svt.set(Usvt3::new(last - 3, 5, 5), 2);
+        let ray = Ray3 {
+            pos: vec3(100.0, 5.5, 5.5),
+            dir: -Vec3::X,
+            time: 0.0,
+        };
+        for hit in [svt.cast_ray(1000, ray), svt.cast_ray_oct(1000, ray)] {
+            let hit = hit.unwrap().unwrap();
+            assert_eq!(hit.voxel, Usvt3::new(last - 3, 5, 5));
+            assert_eq!(hit.data, 2);
+            assert_eq!(hit.t, 100.0 - (last - 2) as f32);
+            assert_eq!(hit.normal, Vec3::X);
+        }
+    }
+
     #[test]
     fn sdf1() {
         let sdf = sdfu::Sphere::new(0.45)
@@ -462,9 +735,10 @@ mod tests {
                 let ray = Ray3 {
                     pos: Vec3::new(i as f32, j as f32, 200.0) / 100.0 * 256.0,
                     dir: Vec3::new(0.1, 0.1, -1.0).normalize(),
+                    time: 0.0,
                 };
-                svt.traverse_ray(100, ray, |_, _, block| {
-                    hit = block.data == 1;
+                let _ = svt.traverse_ray(100, ray, |info| {
+                    hit = info.data == 1;
                     if hit {
                         image.put_pixel(i, j, Rgb([255, 0, 0]));
                     }
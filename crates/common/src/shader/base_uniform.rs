@@ -31,9 +31,43 @@ pub struct RayTracingViewInfo {
     pub frame_index: u32,
 } 
 impl RayTracingViewInfo {
+  /**
+   * `transform` is camera to world, `projection` is a perspective projection looking at -z, both
+   * like what a rasterizer would use. rays are for `frag_coord` in pixels from the top left
+   */
+  pub fn from_camera(transform: Mat4, projection: Mat4, size: UVec2, time: f32, frame_index: u32) -> Self {
+    let inverse_projection = projection.inverse();
+    let mut top_left = inverse_projection * vec4(-1.0, 1.0, -1.0, 1.0);
+    let bottom_right = inverse_projection * vec4(1.0, -1.0, -1.0, 1.0);
+    let mut camera_h = (bottom_right - top_left) / (size.x as f32);
+    camera_h.y = 0.0;
+    let mut camera_v = (bottom_right - top_left) / (size.y as f32);
+    camera_v.x = 0.0;
+    top_left.w = 0.0;
+    RayTracingViewInfo {
+      camera_pos: transform.w_axis.truncate(),
+      camera_look: (transform * top_left).truncate(),
+      camera_h: (transform * camera_h).truncate(),
+      camera_v: (transform * camera_v).truncate(),
+      time,
+      frame_index,
+      not_used: UVec2::ZERO,
+    }
+  }
+
   pub fn get_ray(&self, frag_coord: Vec2) -> Ray3 {
     let pos = self.camera_pos;
     let dir = self.camera_look + frag_coord.x * self.camera_h + frag_coord.y * self.camera_v;
-    Ray3 { pos, dir }
+    Ray3 { pos, dir, time: self.time }
+  }
+
+  /**
+   * for motion blur, the shutter opens at `time` and stays open for `shutter`, `u` is a random
+   * number in `[0, 1)`
+   */
+  pub fn get_ray_in_shutter(&self, frag_coord: Vec2, shutter: f32, u: f32) -> Ray3 {
+    let mut ray = self.get_ray(frag_coord);
+    ray.time = self.time + shutter * u;
+    ray
   }
 }
@@ -243,7 +243,8 @@ fn cal_normal(pos: Vec3) -> Vec3 {
     n.normalize()
 }
 
-fn cal_soft_shadow(ray: Ray3, tmin: f32, mut tmax: f32) -> f32 {
+// `jitter` in `[0, 1)` moves the steps, so with blue noise the banding turns into fine grain
+fn cal_soft_shadow(ray: Ray3, tmin: f32, mut tmax: f32, jitter: f32) -> f32 {
     // bounding volume
     let tp = (0.8-ray.pos.y)/ray.dir.y;
     if tp>0.0 {
@@ -251,7 +252,7 @@ fn cal_soft_shadow(ray: Ray3, tmin: f32, mut tmax: f32) -> f32 {
     }
 
     let mut res: f32 = 1.0;
-    let mut t = tmin;
+    let mut t = tmin + 0.02 * jitter;
     for i in 0 .. 24{
 		let h = map( ray.pos + ray.dir *t ).x;
         let s = (8.0*h/t).clamp(0.0,1.0);
@@ -264,11 +265,11 @@ fn cal_soft_shadow(ray: Ray3, tmin: f32, mut tmax: f32) -> f32 {
     return res.clamp(0.0, 1.0 );
 }
 
-fn cal_ao(pos: Vec3, nor: Vec3) -> f32 {
+fn cal_ao(pos: Vec3, nor: Vec3, jitter: f32) -> f32 {
     let mut occ = 0.0;
     let mut sca = 1.0;
     for i in 0..5 {
-        let h = 0.01 + 0.12 * (i as f32) / 4.0;
+        let h = 0.01 + 0.12 * (i as f32 + jitter) / 5.0;
         let d = map(pos + h * nor).x;
         occ += (h - d) * sca;
         sca *= 0.95;
@@ -284,7 +285,8 @@ fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
     return t * t * (3.0 - 2.0 * t);
 }
 
-fn render(ray: Ray3) -> Vec3 {
+// `jitter` is blue noise for the soft shadows and the ambient occlusion
+fn render(ray: Ray3, jitter: Vec3) -> Vec3 {
     let mut col = vec3(0.7, 0.7, 0.9) - ray.dir.y.max(0.0) * 0.3;
     let res = ray_cast(ray);
     let t = res.x;
@@ -299,13 +301,13 @@ fn render(ray: Ray3) -> Vec3 {
             col = 0.15 + Vec3::splat(0.05);
             ks = 0.4;
         }
-        let occ = cal_ao(pos, nor);
+        let occ = cal_ao(pos, nor, jitter.z);
         let mut lin = Vec3::ZERO;
         {
             let lig = vec3(-0.5, 0.4, -0.6).normalize();
             let hal = (lig -ray.dir).normalize();
             let mut dif = lig.dot(nor).clamp(0.0, 1.0);
-            dif *= cal_soft_shadow(Ray3 { pos: pos, dir: lig }, 0.02, 2.5);
+            dif *= cal_soft_shadow(Ray3 { pos: pos, dir: lig, time: 0.0 }, 0.02, 2.5, jitter.x);
             let mut spe = nor.dot(hal).clamp(0.0, 1.0).powf(16.0);
             spe *= dif;
             spe *= 0.04 + 0.96 * (1.0 - hal.dot(lig)).clamp(0.0, 1.0).powf(0.5);
@@ -318,7 +320,7 @@ fn render(ray: Ray3) -> Vec3 {
             let mut spe = smoothstep( -0.2, 0.2, ref_.y );
             spe *= dif;
             spe *= 0.04+0.96*(1.0+nor.dot(ray.dir)).clamp(0.0,1.0).powf(5.0);
-            spe *= cal_soft_shadow( Ray3 { pos, dir: ref_ }, 0.02, 2.5 );
+            spe *= cal_soft_shadow( Ray3 { pos, dir: ref_, time: 0.0 }, 0.02, 2.5, jitter.y );
             lin += col*0.60*dif*vec3(0.40,0.60,1.15);
             lin +=     2.00*spe*vec3(0.40,0.60,1.30)*ks;
         }
@@ -354,23 +356,30 @@ pub fn vert(
     *out_tex_coord = vec2(x, y);
 }
 
+// the same as in main.rs
+const BLUE_NOISE_SIZE: u32 = 64;
+
 #[spirv(fragment)]
 pub fn frag(
     #[spirv(uniform, descriptor_set = 0, binding = 1)] view_target_size: &UVec2,
     #[spirv(uniform, descriptor_set = 1, binding = 0)] view: &RayTracingViewInfo,
+    #[spirv(storage_buffer, descriptor_set = 2, binding = 0)] blue_noise: &[u32],
     tex_coord: Vec2,
     output: &mut Vec4,
 ) {
     let frag_coord = tex_coord * view_target_size.as_vec2();
-    let mut rng = SRng::new(common::math::zzz_deprecated_svt::float_bits_to_uint(
-        frag_coord.x * (view_target_size.y as f32) + frag_coord.y + view.time
-    ));
+    let noise = BlueNoise { size: BLUE_NOISE_SIZE, ranks: blue_noise };
+    let pixel = frag_coord.as_uvec2();
     const AA: u32 = 2;
     let mut total_color = Vec3::ZERO;
     for i in 0 .. AA {
         for j in 0..AA {
-            let ray = view.get_ray(frag_coord + (uvec2(i, j).as_vec2() + 0.5) / (AA as f32));
-            total_color += render(ray);
+            // 5 dimensions for each sub pixel, 2 for where it is and 3 for `render`
+            let dimension = (i * AA + j) * 5;
+            let offset = noise.sample_2d(pixel, view.frame_index, dimension);
+            let ray = view.get_ray(frag_coord + (uvec2(i, j).as_vec2() + offset) / (AA as f32));
+            let jitter = noise.sample_2d(pixel, view.frame_index, dimension + 2).extend(noise.sample(pixel, view.frame_index, dimension + 4));
+            total_color += render(ray, jitter);
         }
     }
     let frag_color = (total_color / ((AA * AA) as f32)).extend(1.0);
@@ -3,21 +3,88 @@ use std::time::Duration;
 use bevy::{
     asset::AssetServerSettings,
     diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin},
-    prelude::{App, Msaa, Plugin},
-    render::options::{Backends, WgpuOptions},
+    prelude::{App, Msaa, Plugin, Query, Res, ResMut},
+    render::{
+        options::{Backends, WgpuOptions},
+        render_resource::{
+            BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
+            BindingType, Buffer, BufferBindingType, BufferInitDescriptor, BufferUsages, ShaderStages,
+        },
+        renderer::RenderDevice,
+        view::{ExtractedView, ViewTarget},
+        RenderApp,
+    },
     DefaultPlugins,
 };
-use bevy_common::{full_screen_render, load_shader};
+use bevy_common::{
+    full_screen_render::{self, GraphLayout, Pipeline},
+    load_shader,
+};
 use common::{math::*, cargo_manifest_dir};
 use rust_gpu_builder::{RustGpuBuild};
 
 pub struct RenderPlugin;
 
+// the same as in the shader
+const BLUE_NOISE_SIZE: u32 = 64;
+
+struct BlueNoiseBuffer(Buffer);
+
+fn extract_phase() {}
+
+fn queue_phase(
+    device: Res<RenderDevice>,
+    pipeline_layout: Res<GraphLayout>,
+    pipeline: ResMut<Pipeline>,
+    view: Query<(&ExtractedView, &ViewTarget)>,
+    blue_noise: Res<BlueNoiseBuffer>,
+) {
+    let bg = device.create_bind_group(&BindGroupDescriptor {
+        label: None,
+        layout: &pipeline_layout.pipeline.scene_layout,
+        entries: &[BindGroupEntry {
+            binding: 0,
+            resource: blue_noise.0.as_entire_binding(),
+        }],
+    });
+    full_screen_render::queue_pipeline(device, pipeline_layout, pipeline, view, bg);
+}
+
 impl Plugin for RenderPlugin {
     fn build(&self, app: &mut App) {
         let vert = load_shader(app, "vert.spv");
         let frag = load_shader(app, "frag.spv");
-        full_screen_render::setup_plugin_simple(app, vert, frag, "toy");
+        let path = cargo_manifest_dir!().join("assets").join(format!("blue_noise_{}.bin", BLUE_NOISE_SIZE));
+        let ranks = load_or_generate_blue_noise(&path, BLUE_NOISE_SIZE).unwrap();
+        let render_app = app.sub_app_mut(RenderApp);
+        let device = render_app.world.get_resource::<RenderDevice>().unwrap();
+        let buffer = device.create_buffer_with_data(&BufferInitDescriptor {
+            label: None,
+            usage: BufferUsages::STORAGE,
+            contents: &ranks.iter().flat_map(|r| r.to_le_bytes()).collect::<Vec<u8>>(),
+        });
+        render_app.insert_resource(BlueNoiseBuffer(buffer));
+        full_screen_render::setup_plugin(
+            app,
+            BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            },
+            vert,
+            frag,
+            extract_phase,
+            queue_phase,
+            "toy",
+        );
     }
 }
 